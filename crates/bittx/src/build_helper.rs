@@ -1,4 +1,10 @@
-use builder::{anchor, base, unsigned};
+use bitcoin::psbt::Psbt;
+use bitcoin::XOnlyPublicKey;
+use builder::{anchor, anchor_batch, base, batch, unsigned};
+use fee_policy::SweepFeePolicy;
+use fee_rate::FeeRate;
+use policy::DustThreshold;
+use weight::Satisfaction;
 
 use super::*;
 
@@ -8,15 +14,14 @@ pub async fn build_transer_tx(
 ) -> Result<(Transaction, Vec<TxOut>)> {
     let utxos = utxo::gets_uspent_utxo(&info.sender).await?;
 
-    let tx = base::build_transfer_tx(
+    base::build_transfer_tx(
         &info.sender,
         &info.recipient,
         info.amount,
         info.feerate,
         utxos,
         network,
-    );
-    Ok(tx)
+    )
 }
 
 pub async fn build_transer_tx_with_utxo(
@@ -24,41 +29,91 @@ pub async fn build_transer_tx_with_utxo(
     utxos: Vec<types::Utxo>,
     network: Option<Network>,
 ) -> Result<(Transaction, Vec<TxOut>)> {
-    let tx = base::build_transfer_tx(
+    base::build_transfer_tx(
         &info.sender,
         &info.recipient,
         info.amount,
         info.feerate,
         utxos,
         network,
-    );
+    )
+}
+
+pub async fn build_batch_transfer_tx(
+    info: types::BatchTransferInfo,
+    dust: &DustThreshold,
+    network: Option<Network>,
+) -> Result<(Transaction, Vec<TxOut>)> {
+    let utxos = utxo::gets_uspent_utxo(&info.sender).await?;
+    let fee_rate = FeeRate::try_from(info.feerate as f64)?;
+
+    let tx = batch::build_batch_tx(
+        &info.sender,
+        &info.recipients,
+        info.op_return.as_deref(),
+        fee_rate,
+        utxos,
+        dust,
+        network,
+    )?;
     Ok(tx)
 }
 
 pub async fn build_anchor_tx(
     info: types::AnchorInfo,
-    my_utxo: types::Utxo,
+    my_utxos: &[types::Utxo],
+    policy: &SweepFeePolicy,
 ) -> Result<(Transaction, Vec<TxOut>)> {
+    let fee_rate = FeeRate::try_from(info.feerate as f64)?;
+    let recipient = recipient_script(&info.recipient)?;
     let mut anchor_utxos = Vec::new();
-    for (out, out_point) in info.unlock_outs.iter() {
+    let mut anchors = Vec::new();
+    for ((out, out_point), payload) in info.unlock_outs.iter().zip(info.unlock_bytes.iter()) {
         anchor_utxos.push(types::Utxo {
             out_point: *out_point,
             value: out.value,
             script_pubkey: out.script_pubkey.clone(),
         });
+        anchors.push(Satisfaction::Satisfied {
+            script_sig: ScriptBuf::new(),
+            witness: anchor::anchor_witness(&out.script_pubkey, payload)?,
+        });
     }
+    let swept = anchor_utxos.iter().map(|utxo| utxo.value).sum();
+    let my_utxo = select_sweep_fee_input(my_utxos, &anchors, swept, &recipient, fee_rate)?;
 
-    let (tx, prev_outs) =
-        anchor::build_lightning_anchor_tx(&my_utxo, anchor_utxos, info.unlock_bytes);
+    let (tx, prev_outs) = anchor::build_lightning_anchor_tx(
+        &my_utxo,
+        anchor_utxos,
+        info.unlock_bytes,
+        recipient,
+        fee_rate,
+        policy,
+    )?;
 
     Ok((tx, prev_outs))
 }
 
 pub async fn build_batch_anchor_tx(
     info: types::AnchorsInfo,
-    my_utxo: types::Utxo,
+    my_utxos: &[types::Utxo],
+    policy: &SweepFeePolicy,
 ) -> Result<(Transaction, Vec<TxOut>)> {
-    anchor::build_anchor_sweep_tx(&my_utxo, info.details)
+    let fee_rate = FeeRate::try_from(info.feerate as f64)?;
+    let recipient = recipient_script(&info.recipient)?;
+    let anchors = info
+        .details
+        .iter()
+        .map(anchor_batch::anchor_satisfaction)
+        .collect::<Result<Vec<_>>>()?;
+    let swept = info
+        .details
+        .iter()
+        .map(|detail| Amount::from_sat(detail.out_value))
+        .sum();
+    let my_utxo = select_sweep_fee_input(my_utxos, &anchors, swept, &recipient, fee_rate)?;
+    // the wallet utxos are confirmed ones
+    anchor::build_anchor_sweep_tx(&my_utxo, 0, info.details, recipient, fee_rate, policy)
 }
 
 pub async fn build_unsigned_tx(info: types::UnsignedInfo) -> Result<(Transaction, Vec<TxOut>)> {
@@ -74,14 +129,28 @@ pub async fn build_unsigned_tx(info: types::UnsignedInfo) -> Result<(Transaction
         }
     }
 
-    let my_utxo = utxos.first().unwrap();
-    let (tx, prev_outs) = unsigned::build_unsigned_tx(my_utxo, info.input_out, unsigned_utxos);
+    let fee_rate = FeeRate::try_from(info.feerate as f64)?;
+    let recipient = recipient_script(&info.recipient)?;
+    let my_utxo = select_sweep_fee_input(
+        &utxos,
+        &unsigned_satisfactions(&unsigned_utxos),
+        info.input_out.value,
+        &recipient,
+        fee_rate,
+    )?;
+    let (tx, prev_outs) = unsigned::build_unsigned_tx(
+        &my_utxo,
+        info.input_out,
+        unsigned_utxos,
+        recipient,
+        fee_rate,
+    )?;
     Ok((tx, prev_outs))
 }
 
 pub fn build_unsigned_tx_with_receive_utxo(
     info: types::UnsignedInfo,
-    utxo: types::Utxo,
+    my_utxos: &[types::Utxo],
 ) -> Result<(Transaction, Vec<TxOut>)> {
     let mut unsigned_utxos = Vec::new();
     for (idx, input) in info.tx.input.into_iter().enumerate() {
@@ -90,22 +159,179 @@ pub fn build_unsigned_tx_with_receive_utxo(
         }
     }
 
-    let (tx, prev_outs) = unsigned::build_unsigned_tx(&utxo, info.input_out, unsigned_utxos);
+    let fee_rate = FeeRate::try_from(info.feerate as f64)?;
+    let recipient = recipient_script(&info.recipient)?;
+    let utxo = select_sweep_fee_input(
+        my_utxos,
+        &unsigned_satisfactions(&unsigned_utxos),
+        info.input_out.value,
+        &recipient,
+        fee_rate,
+    )?;
+    let (tx, prev_outs) =
+        unsigned::build_unsigned_tx(&utxo, info.input_out, unsigned_utxos, recipient, fee_rate)?;
     Ok((tx, prev_outs))
 }
 
+/// the fee input of a sweep of `inputs` worth `swept`, big enough to leave the output above dust
+fn select_sweep_fee_input(
+    my_utxos: &[types::Utxo],
+    inputs: &[Satisfaction],
+    swept: Amount,
+    recipient: &Script,
+    fee_rate: FeeRate,
+) -> Result<types::Utxo> {
+    let outputs = [TxOut {
+        value: Amount::ZERO,
+        script_pubkey: recipient.to_owned(),
+    }];
+    let min_value = coin_select::sweep_fee_floor(inputs, swept, &outputs, fee_rate);
+    coin_select::select_fee_input(my_utxos, min_value)
+}
+
+/// unsigned inputs are swept with the witness they carry
+fn unsigned_satisfactions(inputs: &[TxIn]) -> Vec<Satisfaction> {
+    inputs
+        .iter()
+        .map(|input| Satisfaction::Satisfied {
+            script_sig: input.script_sig.clone(),
+            witness: input.witness.clone(),
+        })
+        .collect()
+}
+
+/// the script of a sweep recipient, our own destination so the network is not checked
+fn recipient_script(address: &str) -> Result<ScriptBuf> {
+    Ok(Address::from_str(address)
+        .map_err(|e| anyhow!("invalid recipient {}: {}", address, e))?
+        .assume_checked()
+        .script_pubkey())
+}
+
+/// a psbt of a wallet built tx, its taproot inputs spend one of `internal_keys` and its
+/// legacy inputs carry the funding tx fetched from mempool
+async fn wallet_psbt(
+    tx: Transaction,
+    prevouts: &[TxOut],
+    internal_keys: &[XOnlyPublicKey],
+) -> Result<Psbt> {
+    let mut prev_txs = vec![];
+    for txid in psbt::legacy_prev_txids(&tx, prevouts) {
+        prev_txs.push(mempool::tx::get_tx(&txid).await?);
+    }
+    let mut psbt = psbt::from_tx(tx, prevouts, &prev_txs)?;
+    psbt::set_wallet_inputs(&mut psbt, internal_keys)?;
+    Ok(psbt)
+}
+
+pub async fn build_transfer_psbt(
+    info: types::TransferInfo,
+    internal_keys: &[XOnlyPublicKey],
+    network: Option<Network>,
+) -> Result<Psbt> {
+    let (tx, prevouts) = build_transer_tx(info, network).await?;
+    wallet_psbt(tx, &prevouts, internal_keys).await
+}
+
+pub async fn build_batch_transfer_psbt(
+    info: types::BatchTransferInfo,
+    internal_keys: &[XOnlyPublicKey],
+    dust: &DustThreshold,
+    network: Option<Network>,
+) -> Result<Psbt> {
+    let (tx, prevouts) = build_batch_transfer_tx(info, dust, network).await?;
+    wallet_psbt(tx, &prevouts, internal_keys).await
+}
+
+pub async fn build_anchor_psbt(
+    info: types::AnchorInfo,
+    my_utxos: &[types::Utxo],
+    internal_keys: &[XOnlyPublicKey],
+    policy: &SweepFeePolicy,
+) -> Result<Psbt> {
+    let (tx, prevouts) = build_anchor_tx(info, my_utxos, policy).await?;
+    wallet_psbt(tx, &prevouts, internal_keys).await
+}
+
+pub async fn build_batch_anchor_psbt(
+    info: types::AnchorsInfo,
+    my_utxos: &[types::Utxo],
+    internal_keys: &[XOnlyPublicKey],
+    policy: &SweepFeePolicy,
+) -> Result<Psbt> {
+    let (tx, prevouts) = build_batch_anchor_tx(info, my_utxos, policy).await?;
+    wallet_psbt(tx, &prevouts, internal_keys).await
+}
+
+pub async fn build_unsigned_psbt_with_receive_utxo(
+    info: types::UnsignedInfo,
+    my_utxos: &[types::Utxo],
+    internal_keys: &[XOnlyPublicKey],
+) -> Result<Psbt> {
+    let (tx, prevouts) = build_unsigned_tx_with_receive_utxo(info, my_utxos)?;
+    wallet_psbt(tx, &prevouts, internal_keys).await
+}
+
 #[cfg(test)]
 mod tests {
     use bitcoin::{
         consensus::encode::{deserialize_hex, serialize_hex},
         Amount, OutPoint, Transaction,
     };
-    use datatypes::types;
+    use datatypes::{fixtures, types};
     use mempool::utxo;
 
     use crate::build_helper::build_unsigned_tx;
 
-    use super::{build_anchor_tx, lightning::check_lightning_channel_close};
+    use super::{
+        build_anchor_tx, build_unsigned_tx_with_receive_utxo,
+        hd::{Keychain, Purpose},
+        lightning::check_lightning_channel_close,
+        SweepFeePolicy,
+    };
+    use bitcoin::{hashes::Hash, Network, ScriptBuf, Sequence, TxIn, TxOut, Txid, Witness};
+
+    const XPRV: &str = "xprv9s21ZrQH143K3GJpoapnV8SFfukcVBSfeCficPSGfubmSFDxo1kuHnLisriDvSnRRuL2Qrg5ggqHKNVpxR86QEC8w35uxmGoggxtQTPvfUu";
+
+    #[test]
+    fn sweeps_to_the_derived_recipient() {
+        let keychain = Keychain::new(XPRV, Purpose::Bip86, 0, Network::Bitcoin).unwrap();
+        let recipient = keychain.address(false, 3).unwrap();
+        let fee_utxo = fixtures::utxo(
+            &keychain.address(false, 0).unwrap().script_pubkey(),
+            0,
+            20_000,
+        );
+        let unsigned = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint {
+                    txid: Txid::all_zeros(),
+                    vout: 1,
+                },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::from_slice(&[vec![0u8; 65]]),
+            }],
+            output: vec![],
+        };
+        let info = types::UnsignedInfo {
+            recipient: recipient.to_string(),
+            tx: unsigned,
+            input_idx: 0,
+            input_out: TxOut {
+                value: Amount::from_sat(5_000),
+                script_pubkey: fee_utxo.script_pubkey.clone(),
+            },
+            feerate: 2.0,
+        };
+
+        let (tx, _) =
+            build_unsigned_tx_with_receive_utxo(info, std::slice::from_ref(&fee_utxo)).unwrap();
+        assert_eq!(tx.output[0].script_pubkey, recipient.script_pubkey());
+        assert_ne!(tx.output[0].script_pubkey, fee_utxo.script_pubkey);
+    }
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_build_anchor_tx() {
@@ -132,6 +358,7 @@ mod tests {
                 ),
             ],
             recipient: "bc1pdwy6qmwjhfng95v96avuer8za40vy7f66u5cphn9e09dzr6eemfstalyac".to_string(),
+            feerate: 2.0,
         };
 
         let utxos = utxo::gets_uspent_utxo(&data.recipient).await.unwrap();
@@ -139,8 +366,7 @@ mod tests {
             eprintln!("not found unspent utxo");
             assert!(false);
         }
-        let my_utxo = &utxos[0];
-        let res = build_anchor_tx(data, my_utxo.clone()).await;
+        let res = build_anchor_tx(data, &utxos, &SweepFeePolicy::default()).await;
         assert!(res.is_ok());
 
         println!("{}", serialize_hex(&res.unwrap().0))
@@ -156,6 +382,7 @@ mod tests {
             tx: tx.clone(),
             input_idx: 1,
             input_out: out.clone().clone(),
+            feerate: 2.0,
         };
 
         let res = build_unsigned_tx(data).await;
@@ -1,25 +1,36 @@
 use bitcoin::{
-    opcodes::all::{OP_CSV, OP_ENDIF, OP_IFDUP, OP_NOTIF, OP_PUSHBYTES_33, OP_PUSHNUM_16},
+    opcodes::all::{OP_CSV, OP_ENDIF, OP_IFDUP, OP_NOTIF, OP_PUSHNUM_16},
     script::Builder,
+    taproot::{LeafVersion, TaprootBuilder, TaprootSpendInfo},
     OutPoint,
 };
+use secp256k1::{Secp256k1, XOnlyPublicKey};
 use std::vec;
 
 use super::*;
+use base::set_sweep_output_value;
+use fee_policy::{SweepCandidate, SweepFeePolicy, SweepPlan};
+use fee_rate::FeeRate;
+use weight::Satisfaction;
 
+/// sweep `anchor_details` with `my_utxo` paying the fee, everything goes to `recipient`.
+/// `unconfirmed_ancestors` counts the mempool transactions `my_utxo` descends from
 pub fn build_anchor_sweep_tx(
     my_utxo: &types::Utxo,
+    unconfirmed_ancestors: usize,
     anchor_details: Vec<types::AnchorDetail>,
+    recipient: ScriptBuf,
+    fee_rate: FeeRate,
+    policy: &SweepFeePolicy,
 ) -> Result<(Transaction, Vec<TxOut>)> {
     if anchor_details.is_empty() {
         return Err(anyhow!(
             "build anchor swept transaction anchor_details is empty"
         ));
     }
-    let recipient_amount = Amount::from_sat(my_utxo.value.to_sat() + 100);
     let receiver_out = TxOut {
-        value: recipient_amount,
-        script_pubkey: my_utxo.script_pubkey.clone(),
+        value: Amount::ZERO,
+        script_pubkey: recipient,
     };
 
     let mut tx_ins = vec![];
@@ -39,21 +50,19 @@ pub fn build_anchor_sweep_tx(
     let outputs: Vec<TxOut> = vec![receiver_out];
     match builds_input_and_prev_fetch(&mut tx_ins, &mut prevouts, anchor_details) {
         Ok(_) => {
-            if tx_ins.len() < 3 {
-                return Err(anyhow!(
-                    "build anchor swept transaction input is too less than {}",
-                    tx_ins.len()
-                ));
-            }
-
             let mut tx = Transaction {
-                version: Version::TWO,
+                version: policy.version.version(),
                 lock_time: LockTime::ZERO,
                 input: tx_ins,
                 output: outputs,
             };
-            tx.output[0].value =
-                Amount::from_sat(my_utxo.value.to_sat() + (tx.vsize() as u64 - 100));
+            apply_sweep_policy(
+                &mut tx,
+                &mut prevouts,
+                unconfirmed_ancestors,
+                fee_rate,
+                policy,
+            )?;
 
             Ok((tx, prevouts))
         }
@@ -61,29 +70,80 @@ pub fn build_anchor_sweep_tx(
     }
 }
 
-/// need a UTXO which will be added
+/// need a UTXO which will be added, a confirmed one
 pub fn build_lightning_anchor_tx(
     adder_utxos: &types::Utxo,
     anchor_utxos: Vec<types::Utxo>,
     input_payloads: Vec<Vec<u8>>,
-) -> (Transaction, Vec<TxOut>) {
-    let recipient_amount = Amount::from_sat(adder_utxos.value.to_sat() + 100);
+    recipient: ScriptBuf,
+    fee_rate: FeeRate,
+    policy: &SweepFeePolicy,
+) -> Result<(Transaction, Vec<TxOut>)> {
     let receiver_out = TxOut {
-        value: recipient_amount,
-        script_pubkey: adder_utxos.script_pubkey.clone(),
+        value: Amount::ZERO,
+        script_pubkey: recipient,
     };
 
     let outputs: Vec<TxOut> = vec![receiver_out];
-    let (witness_inputs, prev_fetcher) =
-        build_anchor_input_and_prev_fetch(adder_utxos, anchor_utxos, input_payloads);
-    let tx = Transaction {
-        version: Version::TWO,
+    let (witness_inputs, mut prev_fetcher) =
+        build_anchor_input_and_prev_fetch(adder_utxos, anchor_utxos, input_payloads)?;
+    let mut tx = Transaction {
+        version: policy.version.version(),
         lock_time: LockTime::ZERO,
         input: witness_inputs,
         output: outputs,
     };
+    apply_sweep_policy(&mut tx, &mut prev_fetcher, 0, fee_rate, policy)?;
 
-    (tx, prev_fetcher)
+    Ok((tx, prev_fetcher))
+}
+
+/// input 0 pays the fee, the others are anchors with their witness in place. keeps
+/// the anchors `policy` finds worth sweeping and gives the output the rest.
+///
+/// anchors are 16 blocks deep, the unconfirmed ancestors of a TRUC sweep are those
+/// of its fee input
+fn apply_sweep_policy(
+    tx: &mut Transaction,
+    prevouts: &mut Vec<TxOut>,
+    unconfirmed_ancestors: usize,
+    fee_rate: FeeRate,
+    policy: &SweepFeePolicy,
+) -> Result<SweepPlan> {
+    let fee_input = prevouts
+        .first()
+        .ok_or_else(|| anyhow!("anchor sweep has no fee input"))?;
+    let fixed = [Satisfaction::for_script_pubkey(&fee_input.script_pubkey)];
+    let candidates: Vec<SweepCandidate> = tx
+        .input
+        .iter()
+        .zip(prevouts.iter())
+        .skip(1)
+        .map(|(input, prevout)| SweepCandidate {
+            value: prevout.value,
+            satisfaction: Satisfaction::Satisfied {
+                script_sig: input.script_sig.clone(),
+                witness: input.witness.clone(),
+            },
+        })
+        .collect();
+    let plan = policy.plan(&fixed, &tx.output, &candidates, fee_rate)?;
+
+    let keep: Vec<usize> = std::iter::once(0)
+        .chain(plan.selected.iter().map(|idx| idx + 1))
+        .collect();
+    tx.input = keep.iter().map(|idx| tx.input[*idx].clone()).collect();
+    *prevouts = keep.iter().map(|idx| prevouts[*idx].clone()).collect();
+    set_sweep_output_value(tx, prevouts, fee_rate)?;
+    policy::check_truc(tx, prevouts, unconfirmed_ancestors)?;
+    info!(
+        "anchor sweep spends {} of {} anchors, fee {}, expected net gain {}",
+        plan.selected.len(),
+        candidates.len(),
+        plan.fee,
+        plan.net_gain
+    );
+    Ok(plan)
 }
 
 pub fn builds_input_and_prev_fetch(
@@ -93,9 +153,16 @@ pub fn builds_input_and_prev_fetch(
 ) -> Result<()> {
     // put anchor inputs
     for detail in details.iter() {
-        let mut witness = Witness::new();
-        witness.push(Vec::new());
-        witness.push(ScriptBuf::from_hex(&detail.redeem_script_hex)?);
+        let script_pubkey = ScriptBuf::from_hex(&detail.script_pubkey_hex)?;
+        let witness = if script_pubkey.is_p2tr() {
+            let internal_key = XOnlyPublicKey::from_str(&detail.redeem_script_hex)?;
+            build_taproot_anchor_witness(internal_key)?
+        } else {
+            let mut witness = Witness::new();
+            witness.push(Vec::new());
+            witness.push(ScriptBuf::from_hex(&detail.redeem_script_hex)?);
+            witness
+        };
 
         let tx_in = TxIn {
             previous_output: OutPoint::from_str(&format!(
@@ -108,7 +175,7 @@ pub fn builds_input_and_prev_fetch(
         };
         prev_outs.push(TxOut {
             value: Amount::from_sat(detail.out_value),
-            script_pubkey: ScriptBuf::from_hex(&detail.script_pubkey_hex)?,
+            script_pubkey,
         });
         inputs.push(tx_in);
     }
@@ -120,7 +187,7 @@ pub fn build_anchor_input_and_prev_fetch(
     adder_utxo: &types::Utxo,
     inputs: Vec<types::Utxo>,
     input_payloads: Vec<Vec<u8>>,
-) -> (Vec<TxIn>, Vec<TxOut>) {
+) -> Result<(Vec<TxIn>, Vec<TxOut>)> {
     let mut tx_ins = vec![];
     let mut prevouts = Vec::new();
     let tx_in: TxIn = TxIn {
@@ -136,12 +203,14 @@ pub fn build_anchor_input_and_prev_fetch(
     tx_ins.push(tx_in);
 
     for (idx, input) in inputs.iter().enumerate() {
-        let witness = build_anchor_witness(input_payloads.get(idx).unwrap());
+        let payload = input_payloads
+            .get(idx)
+            .ok_or_else(|| anyhow!("no unlock payload for anchor {}", input.out_point))?;
         let tx_in = TxIn {
             previous_output: input.out_point,
             script_sig: ScriptBuf::new(),
             sequence: Sequence(0x10),
-            witness,
+            witness: anchor_witness(&input.script_pubkey, payload)?,
         };
         prevouts.push(TxOut {
             value: input.value,
@@ -150,7 +219,16 @@ pub fn build_anchor_input_and_prev_fetch(
         tx_ins.push(tx_in);
     }
 
-    (tx_ins, prevouts)
+    Ok((tx_ins, prevouts))
+}
+
+/// the witness sweeping an anchor paying `script_pubkey`, `payload` is the internal key
+/// of a taproot anchor or the funding pubkey of a v0 one
+pub fn anchor_witness(script_pubkey: &Script, payload: &Vec<u8>) -> Result<Witness> {
+    match XOnlyPublicKey::from_slice(payload) {
+        Ok(internal_key) if script_pubkey.is_p2tr() => build_taproot_anchor_witness(internal_key),
+        _ => Ok(build_anchor_witness(payload)),
+    }
 }
 
 /// OP_PUSHBYTES_33 [payload]
@@ -166,7 +244,6 @@ pub fn build_anchor_witness(payload: &Vec<u8>) -> Witness {
 
     let payload: &PushBytes = payload.as_slice().try_into().unwrap();
     let script = Builder::new()
-        .push_opcode(OP_PUSHBYTES_33)
         .push_slice(payload)
         .push_opcode(OP_CHECKSIG)
         .push_opcode(OP_IFDUP)
@@ -184,7 +261,6 @@ pub fn build_anchor_witness(payload: &Vec<u8>) -> Witness {
 pub fn build_anchor_redeem_script(payload: &Vec<u8>) -> ScriptBuf {
     let payload: &PushBytes = payload.as_slice().try_into().unwrap();
     Builder::new()
-        .push_opcode(OP_PUSHBYTES_33)
         .push_slice(payload)
         .push_opcode(OP_CHECKSIG)
         .push_opcode(OP_IFDUP)
@@ -195,6 +271,44 @@ pub fn build_anchor_redeem_script(payload: &Vec<u8>) -> ScriptBuf {
         .into_script()
 }
 
+/// OP_PUSHNUM_16
+/// OP_CSV
+///
+/// the only leaf of a simple taproot channel anchor, the internal key is the
+/// local delayed key or the remote key of the commitment
+pub fn build_taproot_anchor_leaf() -> ScriptBuf {
+    Builder::new()
+        .push_opcode(OP_PUSHNUM_16)
+        .push_opcode(OP_CSV)
+        .into_script()
+}
+
+pub fn taproot_anchor_spend_info(internal_key: XOnlyPublicKey) -> Result<TaprootSpendInfo> {
+    let secp = Secp256k1::verification_only();
+    TaprootBuilder::new()
+        .add_leaf(0, build_taproot_anchor_leaf())?
+        .finalize(&secp, internal_key)
+        .map_err(|_| anyhow!("taproot anchor tree is not finalizable"))
+}
+
+pub fn taproot_anchor_script_pubkey(internal_key: XOnlyPublicKey) -> Result<ScriptBuf> {
+    let spend_info = taproot_anchor_spend_info(internal_key)?;
+    Ok(ScriptBuf::new_p2tr_tweaked(spend_info.output_key()))
+}
+
+/// the script path spend anyone can make 16 blocks after the commitment confirmed
+pub fn build_taproot_anchor_witness(internal_key: XOnlyPublicKey) -> Result<Witness> {
+    let leaf = build_taproot_anchor_leaf();
+    let control_block = taproot_anchor_spend_info(internal_key)?
+        .control_block(&(leaf.clone(), LeafVersion::TapScript))
+        .ok_or_else(|| anyhow!("taproot anchor leaf is not in the tree"))?;
+
+    let mut witness = Witness::new();
+    witness.push(leaf);
+    witness.push(control_block.serialize());
+    Ok(witness)
+}
+
 pub fn calc_script_pubkey(wit: Witness) -> Result<ScriptBuf> {
     let redeem_script = wit.last().unwrap();
     let script_pubkey = ScriptBuf::from_bytes(redeem_script.to_vec()).to_p2wsh();
@@ -0,0 +1,192 @@
+use bitcoin::OutPoint;
+use std::collections::HashSet;
+
+use super::*;
+use anchor::builds_input_and_prev_fetch;
+use policy::TxVersion;
+use weight::Satisfaction;
+
+/// blocks after its commitment confirms before anyone can sweep an anchor
+pub const ANCHOR_MATURITY: u64 = 16;
+
+/// an anchor of a closed channel and the height it can be swept from
+#[derive(Debug, Clone)]
+pub struct MaturedAnchor {
+    pub detail: types::AnchorDetail,
+    pub maturity_height: u64,
+}
+
+/// group `anchors` of any number of closes into sweeps of at most `max_weight`.
+///
+/// the oldest anchors go first and outpoints in `spent` are left out. every sweep
+/// also carries a fee input spending `fee_script` and an output paying to `recipient`,
+/// its weight stays within the standard limit of `version` whatever `max_weight` says
+pub fn plan_anchor_batches(
+    mut anchors: Vec<MaturedAnchor>,
+    spent: &HashSet<OutPoint>,
+    fee_script: &Script,
+    recipient: &Script,
+    max_weight: usize,
+    version: TxVersion,
+) -> Result<Vec<Vec<types::AnchorDetail>>> {
+    let max_weight = max_weight.min(version.max_weight());
+    let fee_input = Satisfaction::for_script_pubkey(fee_script);
+    let outputs = [TxOut {
+        value: Amount::ZERO,
+        script_pubkey: recipient.to_owned(),
+    }];
+    if weight::tx_weight(std::slice::from_ref(&fee_input), &outputs) > max_weight {
+        bail!("anchor sweeps can not fit in {} weight units", max_weight);
+    }
+
+    anchors.sort_by(|a, b| {
+        (a.maturity_height, &a.detail.anchor_txid, a.detail.vout).cmp(&(
+            b.maturity_height,
+            &b.detail.anchor_txid,
+            b.detail.vout,
+        ))
+    });
+
+    let mut batches = vec![];
+    let mut batch: Vec<types::AnchorDetail> = vec![];
+    let mut inputs = vec![fee_input.clone()];
+    let mut seen = HashSet::new();
+    for anchor in anchors {
+        let out_point = anchor_out_point(&anchor.detail)?;
+        if spent.contains(&out_point) || !seen.insert(out_point) {
+            continue;
+        }
+        let satisfaction = anchor_satisfaction(&anchor.detail)?;
+
+        inputs.push(satisfaction.clone());
+        if weight::tx_weight(&inputs, &outputs) <= max_weight {
+            batch.push(anchor.detail);
+            continue;
+        }
+        if batch.is_empty() {
+            bail!(
+                "anchor {} alone is above {} weight units",
+                out_point,
+                max_weight
+            );
+        }
+        batches.push(std::mem::take(&mut batch));
+        inputs = vec![fee_input.clone(), satisfaction];
+        batch.push(anchor.detail);
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    info!(
+        "planned {} anchor sweeps of at most {} weight units",
+        batches.len(),
+        max_weight
+    );
+
+    Ok(batches)
+}
+
+pub fn anchor_out_point(detail: &types::AnchorDetail) -> Result<OutPoint> {
+    Ok(OutPoint::from_str(&format!(
+        "{}:{}",
+        detail.anchor_txid, detail.vout
+    ))?)
+}
+
+/// the input the sweep will carry for `detail`, its witness needs no signature
+pub fn anchor_satisfaction(detail: &types::AnchorDetail) -> Result<Satisfaction> {
+    let mut inputs = vec![];
+    let mut prevouts = vec![];
+    builds_input_and_prev_fetch(&mut inputs, &mut prevouts, vec![detail.clone()])?;
+    let input = inputs.remove(0);
+    Ok(Satisfaction::Satisfied {
+        script_sig: input.script_sig,
+        witness: input.witness,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor::build_anchor_redeem_script;
+
+    fn anchor(txid_byte: char, vout: u32, maturity_height: u64) -> MaturedAnchor {
+        let redeem_script = build_anchor_redeem_script(&vec![2u8; 33]);
+        MaturedAnchor {
+            detail: types::AnchorDetail {
+                anchor_txid: txid_byte.to_string().repeat(64),
+                vout,
+                redeem_script_hex: redeem_script.to_hex_string(),
+                script_pubkey_hex: redeem_script.to_p2wsh().to_hex_string(),
+                out_value: 330,
+            },
+            maturity_height,
+        }
+    }
+
+    #[test]
+    fn groups_anchors_by_maturity_under_the_weight_cap() {
+        let fee_script =
+            ScriptBuf::from_hex("00140000000000000000000000000000000000000000").unwrap();
+        let anchors = vec![
+            anchor('c', 0, 300),
+            anchor('a', 1, 100),
+            anchor('b', 0, 200),
+            anchor('a', 0, 100),
+            anchor('b', 1, 200),
+        ];
+        let spent = HashSet::from([OutPoint::from_str(&format!("{}:1", "b".repeat(64))).unwrap()]);
+
+        // room for the fee input and two anchors
+        let base = weight::tx_weight(
+            &[Satisfaction::P2wpkh],
+            &[TxOut {
+                value: Amount::ZERO,
+                script_pubkey: fee_script.clone(),
+            }],
+        );
+        let per_anchor = anchor_satisfaction(&anchors[0].detail)
+            .unwrap()
+            .input_weight();
+        let max_weight = base + 2 * per_anchor + 1;
+
+        let batches = plan_anchor_batches(
+            anchors.clone(),
+            &spent,
+            &fee_script,
+            &fee_script,
+            max_weight,
+            TxVersion::Standard,
+        )
+        .unwrap();
+        let planned: Vec<Vec<(String, u32)>> = batches
+            .iter()
+            .map(|batch| {
+                batch
+                    .iter()
+                    .map(|detail| (detail.anchor_txid[..1].to_string(), detail.vout))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            planned,
+            vec![
+                vec![("a".to_string(), 0), ("a".to_string(), 1)],
+                vec![("b".to_string(), 0), ("c".to_string(), 0)],
+            ]
+        );
+
+        // a cap above the standard limit falls back to it
+        let batches = plan_anchor_batches(
+            anchors,
+            &spent,
+            &fee_script,
+            &fee_script,
+            usize::MAX,
+            TxVersion::Truc,
+        )
+        .unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].len(), 4);
+    }
+}
@@ -1,4 +1,8 @@
 use super::*;
+use coin_select::{Change, WasteMetric};
+use error::BuildError;
+use fee_rate::FeeRate;
+use policy::DustThreshold;
 use std::vec;
 
 pub fn build_transfer_tx(
@@ -8,7 +12,7 @@ pub fn build_transfer_tx(
     fee_rate: f32,
     in_utxos: Vec<types::Utxo>,
     network: Option<Network>,
-) -> (Transaction, Vec<TxOut>) {
+) -> Result<(Transaction, Vec<TxOut>)> {
     let net = network.unwrap_or(Network::Bitcoin);
     let sender_address = Address::from_str(sender)
         .unwrap()
@@ -38,20 +42,71 @@ pub fn build_transfer_tx(
         script_pubkey: recipient_address.script_pubkey(),
     };
 
-    let change_out = TxOut {
-        value: Amount::from_sat(0),
-        script_pubkey: sender_address.script_pubkey(),
-    };
-    let outputs = vec![receiver_out, change_out];
+    let fee_rate = FeeRate::try_from(fee_rate as f64)?;
+    let mut outputs = vec![receiver_out];
+    let target = recipient_amount + fee_rate.fee(weight::base_vsize(&outputs));
+    let metric = WasteMetric::with_default_long_term(&sender_address.script_pubkey());
+    let selection = coin_select::select_coins(&inputs, target, fee_rate, &metric)?;
+    info!(
+        "selected {} of {} utxos with {:?}, change: {:?}",
+        selection.utxos.len(),
+        inputs.len(),
+        selection.strategy,
+        selection.change
+    );
 
-    build_tx(inputs, outputs, fee_rate)
+    if let Change::Some(_) = selection.change {
+        outputs.push(TxOut {
+            value: Amount::ZERO,
+            script_pubkey: sender_address.script_pubkey(),
+        });
+        return Ok(build_tx(
+            selection.utxos,
+            outputs,
+            fee_rate,
+            &DustThreshold::default(),
+        )?);
+    }
+
+    Ok(assemble_tx(&selection.utxos, outputs))
 }
 
+/// the last output is the change, it is dropped when the change would be dust
 pub fn build_tx(
     inputs: Vec<types::Utxo>,
     mut outputs: Vec<TxOut>,
-    fee_rate: f32,
-) -> (Transaction, Vec<TxOut>) {
+    fee_rate: FeeRate,
+    dust: &DustThreshold,
+) -> Result<(Transaction, Vec<TxOut>), BuildError> {
+    let change_amount = calc_change_amount(&inputs, &outputs, fee_rate)?;
+    let mut change_output = outputs.pop().ok_or(BuildError::NoRecipients)?;
+    change_output.value = change_amount;
+    if !dust.is_dust(&change_output) {
+        outputs.push(change_output);
+    }
+
+    Ok(assemble_tx(&inputs, outputs))
+}
+
+/// give the only output of a sweep everything the inputs hold minus the fee at `fee_rate`
+pub fn set_sweep_output_value(
+    tx: &mut Transaction,
+    prevouts: &[TxOut],
+    fee_rate: FeeRate,
+) -> Result<(), BuildError> {
+    let available: Amount = prevouts.iter().map(|out| out.value).sum();
+    let fee = fee_rate.fee(weight::signed_vsize(tx, prevouts));
+    let output = tx.output.first_mut().ok_or(BuildError::NoRecipients)?;
+    let needed = fee + output.script_pubkey.minimal_non_dust();
+    if available < needed {
+        return Err(BuildError::InsufficientFunds { needed, available });
+    }
+
+    output.value = available - fee;
+    Ok(())
+}
+
+pub fn assemble_tx(inputs: &[types::Utxo], outputs: Vec<TxOut>) -> (Transaction, Vec<TxOut>) {
     let mut tx_ins = vec![];
     let mut prevouts = Vec::new();
 
@@ -69,10 +124,6 @@ pub fn build_tx(
         tx_ins.push(tx_in);
     }
 
-    let change_amount = calc_change_amount(inputs, &outputs, fee_rate);
-    let mut change_output = outputs.pop().unwrap();
-    change_output.value = change_amount;
-    outputs.push(change_output);
     let tx = Transaction {
         version: Version::TWO,
         lock_time: LockTime::ZERO,
@@ -83,32 +134,22 @@ pub fn build_tx(
     (tx, prevouts)
 }
 
-fn calc_change_amount(inputs: Vec<types::Utxo>, outputs: &[TxOut], fee_rate: f32) -> Amount {
-    let mut tx_ins = vec![];
-    let mut input_val: u64 = 0;
-    for input in inputs.iter() {
-        let tx_in = TxIn {
-            previous_output: input.out_point,
-            script_sig: ScriptBuf::new(),
-            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
-            witness: Witness::from_slice(&[&[0; SCHNORR_SIGNATURE_SIZE]]),
-        };
-
-        input_val += input.value.to_sat();
-        tx_ins.push(tx_in);
-    }
+fn calc_change_amount(
+    inputs: &[types::Utxo],
+    outputs: &[TxOut],
+    fee_rate: FeeRate,
+) -> Result<Amount, BuildError> {
+    let satisfactions: Vec<weight::Satisfaction> = inputs
+        .iter()
+        .map(|input| weight::Satisfaction::for_script_pubkey(&input.script_pubkey))
+        .collect();
+    let vsize = weight::tx_vsize(&satisfactions, outputs);
 
+    // the change output itself is the last one and still holds no value
     let output_val = outputs.iter().map(|out| out.value).sum::<Amount>();
-    let tx = Transaction {
-        version: Version::TWO,
-        lock_time: LockTime::ZERO,
-        input: tx_ins,
-        output: outputs.to_owned(),
-    };
-
-    // let vsize = get_tx_vsize(tx);
-    let vsize = tx.vsize();
-    let fee = fee_rate * vsize as f32;
-    let change_amount = input_val - output_val.to_sat() - fee as u64;
-    Amount::from_sat(change_amount)
+    let needed = output_val + fee_rate.fee(vsize);
+    let available = inputs.iter().map(|input| input.value).sum::<Amount>();
+    available
+        .checked_sub(needed)
+        .ok_or(BuildError::InsufficientFunds { needed, available })
 }
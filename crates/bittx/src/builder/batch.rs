@@ -0,0 +1,195 @@
+use super::*;
+use base::{assemble_tx, build_tx};
+use coin_select::{Change, WasteMetric};
+use error::BuildError;
+use fee_rate::FeeRate;
+use policy::{DustThreshold, MAX_OP_RETURN_DATA};
+
+/// pay many recipients from `sender` in one transaction.
+/// change below the dust threshold of the sender script is left to the miner
+pub fn build_batch_tx(
+    sender: &str,
+    recipients: &[(String, u64)],
+    op_return: Option<&[u8]>,
+    fee_rate: FeeRate,
+    in_utxos: Vec<types::Utxo>,
+    dust: &DustThreshold,
+    network: Option<Network>,
+) -> Result<(Transaction, Vec<TxOut>), BuildError> {
+    if recipients.is_empty() {
+        return Err(BuildError::NoRecipients);
+    }
+
+    let net = network.unwrap_or(Network::Bitcoin);
+    let sender_script = parse_address(sender, net)?.script_pubkey();
+    let inputs: Vec<types::Utxo> = in_utxos
+        .into_iter()
+        .map(|utxo| types::Utxo {
+            script_pubkey: sender_script.clone(),
+            ..utxo
+        })
+        .collect();
+
+    let mut outputs = vec![];
+    for (address, amount) in recipients.iter() {
+        let out = TxOut {
+            value: Amount::from_sat(*amount),
+            script_pubkey: parse_address(address, net)?.script_pubkey(),
+        };
+        if dust.is_dust(&out) {
+            return Err(BuildError::DustOutput {
+                threshold: dust.for_script(&out.script_pubkey),
+                script: out.script_pubkey,
+                value: out.value,
+            });
+        }
+        outputs.push(out);
+    }
+
+    if let Some(data) = op_return {
+        let push: &PushBytes = data
+            .try_into()
+            .map_err(|_| BuildError::OpReturnTooLarge(data.len()))?;
+        if data.len() > MAX_OP_RETURN_DATA {
+            return Err(BuildError::OpReturnTooLarge(data.len()));
+        }
+        outputs.push(TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::new_op_return(push),
+        });
+    }
+
+    let pay_amount: Amount = outputs.iter().map(|out| out.value).sum();
+    let target = pay_amount + fee_rate.fee(weight::base_vsize(&outputs));
+    let mut metric = WasteMetric::with_default_long_term(&sender_script);
+    metric.min_change = dust.for_script(&sender_script);
+    let selection = coin_select::select_coins(&inputs, target, fee_rate, &metric)?;
+
+    match selection.change {
+        Change::Some(_) => {
+            outputs.push(TxOut {
+                value: Amount::ZERO,
+                script_pubkey: sender_script,
+            });
+            build_tx(selection.utxos, outputs, fee_rate, dust)
+        }
+        Change::None { excess } => {
+            info!("batch tx without change, {} added to the fee", excess);
+            Ok(assemble_tx(&selection.utxos, outputs))
+        }
+    }
+}
+
+fn parse_address(address: &str, network: Network) -> Result<Address, BuildError> {
+    Address::from_str(address)
+        .ok()
+        .and_then(|addr| addr.require_network(network).ok())
+        .ok_or_else(|| BuildError::InvalidAddress(address.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datatypes::fixtures;
+
+    const SENDER: &str = "bcrt1pg254mdmy6tyul7kjym75pcxp7xql8dcl5xh00rp96jve3qmg292qju447x";
+    const RECIPIENT: &str = "bcrt1q8g8nly0syz3kksgtvdymae0xlgxnawvyrhc4pf";
+
+    fn utxos(values: &[u64]) -> Vec<types::Utxo> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(vout, value)| fixtures::utxo(&ScriptBuf::new(), vout as u32, *value))
+            .collect()
+    }
+
+    fn recipients(amounts: &[u64]) -> Vec<(String, u64)> {
+        amounts
+            .iter()
+            .map(|amount| (RECIPIENT.to_string(), *amount))
+            .collect()
+    }
+
+    #[test]
+    fn pays_every_recipient_with_change() {
+        let fee_rate = FeeRate::try_from(2.0).unwrap();
+        let (tx, prevouts) = build_batch_tx(
+            SENDER,
+            &recipients(&[10_000, 20_000, 30_000]),
+            Some(b"metabit"),
+            fee_rate,
+            utxos(&[100_000]),
+            &DustThreshold::default(),
+            Some(Network::Regtest),
+        )
+        .unwrap();
+
+        assert_eq!(tx.output.len(), 5);
+        assert!(tx.output[3].script_pubkey.is_op_return());
+        let input_value: Amount = prevouts.iter().map(|out| out.value).sum();
+        let output_value: Amount = tx.output.iter().map(|out| out.value).sum();
+        assert_eq!(
+            input_value - output_value,
+            fee_rate.fee(weight::signed_vsize(&tx, &prevouts))
+        );
+    }
+
+    #[test]
+    fn dust_change_goes_to_fee() {
+        let fee_rate = FeeRate::try_from(1.0).unwrap();
+        let (tx, _) = build_batch_tx(
+            SENDER,
+            &recipients(&[10_000, 10_000]),
+            None,
+            fee_rate,
+            utxos(&[20_300]),
+            &DustThreshold::default(),
+            Some(Network::Regtest),
+        )
+        .unwrap();
+
+        assert_eq!(tx.output.len(), 2);
+    }
+
+    #[test]
+    fn insufficient_funds_is_an_error() {
+        let fee_rate = FeeRate::try_from(1.0).unwrap();
+        let res = build_batch_tx(
+            SENDER,
+            &recipients(&[10_000, 10_000]),
+            None,
+            fee_rate,
+            utxos(&[5_000, 6_000]),
+            &DustThreshold::default(),
+            Some(Network::Regtest),
+        );
+
+        assert!(matches!(res, Err(BuildError::InsufficientFunds { .. })));
+    }
+
+    #[test]
+    fn rejects_dust_recipient_and_large_op_return() {
+        let fee_rate = FeeRate::try_from(1.0).unwrap();
+        let res = build_batch_tx(
+            SENDER,
+            &recipients(&[100]),
+            None,
+            fee_rate,
+            utxos(&[50_000]),
+            &DustThreshold::default(),
+            Some(Network::Regtest),
+        );
+        assert!(matches!(res, Err(BuildError::DustOutput { .. })));
+
+        let res = build_batch_tx(
+            SENDER,
+            &recipients(&[10_000]),
+            Some(&[0u8; 81]),
+            fee_rate,
+            utxos(&[50_000]),
+            &DustThreshold::default(),
+            Some(Network::Regtest),
+        );
+        assert_eq!(res.unwrap_err(), BuildError::OpReturnTooLarge(81));
+    }
+}
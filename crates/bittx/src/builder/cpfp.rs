@@ -0,0 +1,246 @@
+use super::*;
+use bitcoin::OutPoint;
+use bitcoincore_rpc::json::GetMempoolEntryResult;
+use error::BuildError;
+use fee_rate::FeeRate;
+use policy::{DustThreshold, TRUC_VERSION};
+
+/// bitcoin core's default -minrelaytxfee in sat/vB, the child has to pay it on its own
+pub const MIN_RELAY_FEE: f64 = 1.0;
+
+/// the unconfirmed package a child would pull along, the parent and its own
+/// unconfirmed ancestors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ancestors {
+    /// transactions in the package, the parent included
+    pub count: u64,
+    pub vsize: u64,
+    pub fee: Amount,
+}
+
+impl From<&GetMempoolEntryResult> for Ancestors {
+    fn from(entry: &GetMempoolEntryResult) -> Self {
+        Self {
+            count: entry.ancestor_count,
+            vsize: entry.ancestor_size,
+            fee: entry.fees.ancestor,
+        }
+    }
+}
+
+/// an unsigned child, `fee` is what it pays on top of its ancestors
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cpfp {
+    pub tx: Transaction,
+    pub prevouts: Vec<TxOut>,
+    pub fee: Amount,
+}
+
+/// spend our output `vout` of the unconfirmed `parent` so the package of the child
+/// and `ancestors` pays `target`.
+///
+/// everything left goes to `recipient`. when our output can not cover the child fee
+/// `extra` wallet utxos are added, largest first. the child comes back unsigned so
+/// the caller can hold the `extra` inputs it picked before signing every input.
+///
+/// the child of a TRUC parent is TRUC too, `extra` has to be confirmed then and the
+/// child fails when it would break the BIP 431 limits
+#[allow(clippy::too_many_arguments)]
+pub fn build_cpfp_child(
+    parent: &Transaction,
+    vout: u32,
+    ancestors: &Ancestors,
+    target: FeeRate,
+    recipient: ScriptBuf,
+    extra: &[types::Utxo],
+    dust: &DustThreshold,
+) -> Result<Cpfp> {
+    let parent_out = parent
+        .output
+        .get(vout as usize)
+        .ok_or_else(|| anyhow!("parent has no output {}", vout))?;
+    if ancestors.fee >= target.fee(ancestors.vsize as usize) {
+        bail!(
+            "{} already pays {:?} with its ancestors",
+            parent.compute_txid(),
+            target
+        );
+    }
+
+    let ours = types::Utxo {
+        out_point: OutPoint {
+            txid: parent.compute_txid(),
+            vout,
+        },
+        value: parent_out.value,
+        script_pubkey: parent_out.script_pubkey.clone(),
+    };
+    let mut extra: Vec<&types::Utxo> = extra
+        .iter()
+        .filter(|utxo| utxo.out_point != ours.out_point)
+        .collect();
+    extra.sort_by_key(|utxo| std::cmp::Reverse(utxo.value));
+    let mut extra = extra.into_iter();
+
+    let min_relay = FeeRate::try_from(MIN_RELAY_FEE)?;
+    let min_output = dust.for_script(&recipient);
+    let outputs = vec![TxOut {
+        value: Amount::ZERO,
+        script_pubkey: recipient,
+    }];
+    let mut inputs = vec![ours];
+    let version = if parent.version == TRUC_VERSION {
+        TRUC_VERSION
+    } else {
+        Version::TWO
+    };
+    loop {
+        let (mut tx, prevouts) = base::assemble_tx(&inputs, outputs.clone());
+        tx.version = version;
+        let vsize = weight::signed_vsize(&tx, &prevouts);
+        let package_fee = target.fee(ancestors.vsize as usize + vsize);
+        let fee = package_fee
+            .checked_sub(ancestors.fee)
+            .unwrap_or(Amount::ZERO)
+            .max(min_relay.fee(vsize));
+        let available: Amount = prevouts.iter().map(|out| out.value).sum();
+        let needed = fee + min_output;
+        if available >= needed {
+            tx.output[0].value = available - fee;
+            policy::check_truc(&tx, &prevouts, ancestors.count as usize)?;
+            info!(
+                "cpfp child pays {} for parent {}",
+                fee,
+                parent.compute_txid()
+            );
+            return Ok(Cpfp { tx, prevouts, fee });
+        }
+
+        let Some(utxo) = extra.next() else {
+            return Err(BuildError::InsufficientFunds { needed, available }.into());
+        };
+        inputs.push(utxo.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::PrivateKey;
+    use datatypes::fixtures;
+    use signer::{LocalSigner, Signer};
+
+    fn parent_paying(script_pubkey: &ScriptBuf, value: u64) -> Transaction {
+        let utxo = fixtures::utxo(script_pubkey, 0, value + 200);
+        let outputs = vec![TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: script_pubkey.clone(),
+        }];
+        base::assemble_tx(&[utxo], outputs).0
+    }
+
+    #[test]
+    fn child_lifts_package_to_target() {
+        let private_key = PrivateKey::from_slice(&[7u8; 32], Network::Regtest).unwrap();
+        let secp = secp256k1::Secp256k1::new();
+        let (internal_key, _) = private_key.inner.x_only_public_key(&secp);
+        let script_pubkey = ScriptBuf::new_p2tr(&secp, internal_key, None);
+        let signer = LocalSigner::new(private_key.to_wif());
+        let dust = DustThreshold::default();
+        let target = FeeRate::try_from(10.0).unwrap();
+        let ancestors = Ancestors {
+            count: 1,
+            vsize: 200,
+            fee: Amount::from_sat(200),
+        };
+
+        let parent = parent_paying(&script_pubkey, 20_000);
+        let child = build_cpfp_child(
+            &parent,
+            0,
+            &ancestors,
+            target,
+            script_pubkey.clone(),
+            &[],
+            &dust,
+        )
+        .unwrap();
+        assert_eq!(
+            child.tx.input[0].previous_output.txid,
+            parent.compute_txid()
+        );
+        let signed = |child: Cpfp| {
+            let sign_idx = (0..child.tx.input.len()).collect();
+            signer.sign_tx(child.tx, child.prevouts, sign_idx).unwrap()
+        };
+        // the key spend is estimated with a sighash byte the default flag leaves out
+        let tx = signed(child.clone());
+        let package_weight = ancestors.vsize as usize * 4 + tx.weight().to_wu() as usize;
+        assert_eq!(
+            child.fee + ancestors.fee,
+            target.fee((package_weight + 1).div_ceil(4))
+        );
+        assert_eq!(
+            child.tx.output[0].value,
+            Amount::from_sat(20_000) - child.fee
+        );
+
+        // a small output needs a wallet utxo to pay for the child
+        let parent = parent_paying(&script_pubkey, 1_000);
+        let child_with = |extra| {
+            build_cpfp_child(
+                &parent,
+                0,
+                &ancestors,
+                target,
+                script_pubkey.clone(),
+                extra,
+                &dust,
+            )
+        };
+        assert!(child_with(&[]).is_err());
+        let wallet = fixtures::utxo(&script_pubkey, 1, 30_000);
+        let child = child_with(&[wallet]).unwrap();
+        assert_eq!(child.tx.input.len(), 2);
+        assert!(signed(child)
+            .input
+            .iter()
+            .all(|input| input.witness.len() == 1));
+
+        let rich = Ancestors {
+            count: 1,
+            vsize: 200,
+            fee: Amount::from_sat(4_000),
+        };
+        assert!(
+            build_cpfp_child(&parent, 0, &rich, target, script_pubkey.clone(), &[], &dust).is_err()
+        );
+
+        // a TRUC parent makes a TRUC child
+        let mut truc_parent = parent_paying(&script_pubkey, 20_000);
+        truc_parent.version = TRUC_VERSION;
+        let truc_child = |ancestors: &Ancestors| {
+            build_cpfp_child(
+                &truc_parent,
+                0,
+                ancestors,
+                target,
+                script_pubkey.clone(),
+                &[],
+                &dust,
+            )
+        };
+        assert_eq!(truc_child(&ancestors).unwrap().tx.version, TRUC_VERSION);
+        let deep = Ancestors {
+            count: 2,
+            ..ancestors
+        };
+        assert_eq!(
+            truc_child(&deep)
+                .unwrap_err()
+                .downcast::<BuildError>()
+                .unwrap(),
+            BuildError::TrucAncestors(2)
+        );
+    }
+}
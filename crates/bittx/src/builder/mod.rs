@@ -1,5 +1,9 @@
 pub mod anchor;
+pub mod anchor_batch;
 pub mod base;
+pub mod batch;
+pub mod cpfp;
+pub mod p2a;
 pub mod unsigned;
 
 use super::*;
@@ -0,0 +1,214 @@
+use bitcoin::{OutPoint, Txid};
+
+use super::*;
+use cpfp::{Ancestors, MIN_RELAY_FEE};
+use error::BuildError;
+use fee_rate::FeeRate;
+use policy::TRUC_VERSION;
+
+/// OP_1 <0x4e73>, the keyless anchor bitcoin core relays since 28.0
+pub fn is_p2a(script_pubkey: &Script) -> bool {
+    script_pubkey == ScriptBuf::new_p2a().as_script()
+}
+
+/// the p2a outputs of `tx`
+pub fn p2a_outputs(tx: &Transaction) -> Vec<types::P2aDetail> {
+    let txid = tx.compute_txid();
+    tx.output
+        .iter()
+        .enumerate()
+        .filter(|(_, out)| is_p2a(&out.script_pubkey))
+        .map(|(vout, out)| types::P2aDetail {
+            out_point: OutPoint {
+                txid,
+                vout: vout as u32,
+            },
+            value: out.value.to_sat(),
+            parent_version: tx.version.0,
+            confirmed: false,
+        })
+        .collect()
+}
+
+/// spend `anchors` together with `fee_utxo`, everything left goes to `recipient`.
+///
+/// the relay rules decide the shape. a child of an unconfirmed TRUC parent is TRUC too,
+/// has no other unconfirmed ancestor and stays within 1000 vB, so `fee_utxo` has to be
+/// confirmed then. an unconfirmed parent with ephemeral dust pays no fee and relays only
+/// together with the child spending its dust, `ancestors` is that parent's package and
+/// the child pays for all of it at `fee_rate`
+pub fn build_p2a_sweep_tx(
+    fee_utxo: &types::Utxo,
+    anchors: &[types::P2aDetail],
+    ancestors: Option<&Ancestors>,
+    recipient: ScriptBuf,
+    fee_rate: FeeRate,
+) -> Result<(Transaction, Vec<TxOut>)> {
+    if anchors.is_empty() {
+        bail!("build p2a sweep transaction anchors is empty");
+    }
+    let mut unconfirmed: Vec<(Txid, i32)> = vec![];
+    for anchor in anchors.iter().filter(|anchor| !anchor.confirmed) {
+        let parent = (anchor.out_point.txid, anchor.parent_version);
+        if !unconfirmed.contains(&parent) {
+            unconfirmed.push(parent);
+        }
+    }
+    let truc_parent = unconfirmed
+        .iter()
+        .any(|(_, version)| *version == TRUC_VERSION.0);
+    // a TRUC child can not have unconfirmed ancestors of another version
+    let version = if unconfirmed.is_empty() || truc_parent {
+        TRUC_VERSION
+    } else {
+        Version::TWO
+    };
+    let unconfirmed_ancestors = ancestors
+        .map(|ancestors| ancestors.count as usize)
+        .unwrap_or_default()
+        .max(unconfirmed.len());
+
+    let mut inputs = vec![TxIn {
+        previous_output: fee_utxo.out_point,
+        script_sig: ScriptBuf::new(),
+        sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+        witness: Witness::new(),
+    }];
+    let mut prevouts = vec![TxOut {
+        value: fee_utxo.value,
+        script_pubkey: fee_utxo.script_pubkey.clone(),
+    }];
+    for anchor in anchors.iter() {
+        inputs.push(TxIn {
+            previous_output: anchor.out_point,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        });
+        prevouts.push(TxOut {
+            value: Amount::from_sat(anchor.value),
+            script_pubkey: ScriptBuf::new_p2a(),
+        });
+    }
+
+    let mut tx = Transaction {
+        version,
+        lock_time: LockTime::ZERO,
+        input: inputs,
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: recipient,
+        }],
+    };
+    policy::check_truc(&tx, &prevouts, unconfirmed_ancestors)?;
+    let vsize = weight::signed_vsize(&tx, &prevouts);
+
+    let fee = match ancestors {
+        Some(ancestors) => fee_rate
+            .fee(ancestors.vsize as usize + vsize)
+            .checked_sub(ancestors.fee)
+            .unwrap_or(Amount::ZERO)
+            .max(FeeRate::try_from(MIN_RELAY_FEE)?.fee(vsize)),
+        None => fee_rate.fee(vsize),
+    };
+    let available: Amount = prevouts.iter().map(|out| out.value).sum();
+    let needed = fee + tx.output[0].script_pubkey.minimal_non_dust();
+    if available < needed {
+        return Err(BuildError::InsufficientFunds { needed, available }.into());
+    }
+    tx.output[0].value = available - fee;
+    info!(
+        "p2a sweep spends {} anchors, version {}, fee {}",
+        anchors.len(),
+        tx.version.0,
+        fee
+    );
+
+    Ok((tx, prevouts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use datatypes::fixtures;
+
+    fn parent(version: Version, p2a_value: u64) -> Transaction {
+        Transaction {
+            version,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint {
+                    txid: Txid::all_zeros(),
+                    vout: 0,
+                },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::from_slice(&[vec![1u8; 64]]),
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(50_000),
+                    script_pubkey: ScriptBuf::new_op_return([]),
+                },
+                TxOut {
+                    value: Amount::from_sat(p2a_value),
+                    script_pubkey: ScriptBuf::new_p2a(),
+                },
+            ],
+        }
+    }
+
+    fn recipient() -> ScriptBuf {
+        ScriptBuf::from_hex("51200000000000000000000000000000000000000000000000000000000000000001")
+            .unwrap()
+    }
+
+    #[test]
+    fn sweeps_ephemeral_anchor_with_a_truc_child() {
+        let fee_utxo = fixtures::utxo(
+            &ScriptBuf::from_hex("00140000000000000000000000000000000000000000").unwrap(),
+            1,
+            20_000,
+        );
+        let truc = parent(TRUC_VERSION, 0);
+        let anchors = p2a_outputs(&truc);
+        assert_eq!(anchors.len(), 1);
+        assert_eq!(anchors[0].out_point.vout, 1);
+
+        // the zero fee parent is paid for by the child
+        let ancestors = Ancestors {
+            count: 1,
+            vsize: 100,
+            fee: Amount::ZERO,
+        };
+        let fee_rate = FeeRate::try_from(10.0).unwrap();
+        let (tx, prevouts) =
+            build_p2a_sweep_tx(&fee_utxo, &anchors, Some(&ancestors), recipient(), fee_rate)
+                .unwrap();
+        assert_eq!(tx.version, TRUC_VERSION);
+        assert_eq!(tx.output[0].script_pubkey, recipient());
+        assert!(tx.input[1].witness.is_empty());
+        let vsize = weight::signed_vsize(&tx, &prevouts);
+        assert_eq!(
+            tx.output[0].value,
+            fee_utxo.value - fee_rate.fee(100 + vsize)
+        );
+
+        // a TRUC child takes a single unconfirmed parent
+        let mut two_parents = anchors.clone();
+        two_parents.extend(p2a_outputs(&parent(TRUC_VERSION, 240)));
+        assert_eq!(
+            build_p2a_sweep_tx(&fee_utxo, &two_parents, None, recipient(), fee_rate)
+                .unwrap_err()
+                .downcast::<BuildError>()
+                .unwrap(),
+            BuildError::TrucAncestors(2)
+        );
+
+        // an unconfirmed v2 parent keeps the child at v2
+        let v2 = p2a_outputs(&parent(Version::TWO, 240));
+        let (tx, _) = build_p2a_sweep_tx(&fee_utxo, &v2, None, recipient(), fee_rate).unwrap();
+        assert_eq!(tx.version, Version::TWO);
+    }
+}
@@ -1,28 +1,33 @@
 use super::*;
+use base::set_sweep_output_value;
+use fee_rate::FeeRate;
 use std::vec;
 
+/// take over `inputs` signed by someone else and pay them with the fee input to `recipient`
 pub fn build_unsigned_tx(
     adder_utxos: &types::Utxo,
     input_out: TxOut,
     inputs: Vec<TxIn>,
-) -> (Transaction, Vec<TxOut>) {
-    let recipient_amount = Amount::from_sat(adder_utxos.value.to_sat() + 166);
+    recipient: ScriptBuf,
+    fee_rate: FeeRate,
+) -> Result<(Transaction, Vec<TxOut>)> {
     let receiver_out = TxOut {
-        value: recipient_amount,
-        script_pubkey: adder_utxos.script_pubkey.clone(),
+        value: Amount::ZERO,
+        script_pubkey: recipient,
     };
 
     let outputs: Vec<TxOut> = vec![receiver_out];
     let (witness_inputs, prev_fetcher) =
         build_unsigned_input_and_prev_fetch(adder_utxos, input_out, inputs);
-    let tx = Transaction {
+    let mut tx = Transaction {
         version: Version::TWO,
         lock_time: LockTime::ZERO,
         input: witness_inputs,
         output: outputs,
     };
+    set_sweep_output_value(&mut tx, &prev_fetcher, fee_rate)?;
 
-    (tx, prev_fetcher)
+    Ok((tx, prev_fetcher))
 }
 
 pub fn build_unsigned_input_and_prev_fetch(
@@ -0,0 +1,761 @@
+use super::*;
+use bitcoin::hashes::{hash160, ripemd160, sha1, sha256, sha256d, Hash};
+use bitcoin::opcodes::all::*;
+use bitcoin::opcodes::{Class, ClassifyContext, Opcode};
+use bitcoin::script::{read_scriptbool, read_scriptint_non_minimal, write_scriptint};
+use bitcoin::taproot::LeafVersion;
+use bitcoin::WScriptHash;
+use std::fmt;
+
+/// how sure a classification is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    /// the script could not be evaluated, the label says nothing about signing
+    Low,
+    /// the spend type is inferred from the witness alone
+    Medium,
+    /// the prevout confirms the spend type and the executed branch is known
+    High,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpendType {
+    P2wpkh,
+    /// a witness script whose executed branch checked a signature
+    P2wsh {
+        template: &'static str,
+    },
+    P2trKeyPath,
+    /// a leaf whose executed branch checked a signature
+    P2trScriptPath {
+        leaf: ScriptBuf,
+    },
+    /// the executed branch needs nothing anybody watching the mempool lacks
+    AnyoneCanSpend,
+    /// a preimage was the only secret of the executed branch
+    HashLockOnly,
+    /// only a locktime guarded the executed branch
+    TimelockOnly,
+    Unknown,
+}
+
+impl fmt::Display for SpendType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpendType::P2wpkh => write!(f, "p2wpkh"),
+            SpendType::P2wsh { template } => write!(f, "p2wsh {}", template),
+            SpendType::P2trKeyPath => write!(f, "p2tr key path"),
+            SpendType::P2trScriptPath { leaf } => write!(f, "p2tr script path {}", leaf),
+            SpendType::AnyoneCanSpend => write!(f, "anyone can spend"),
+            SpendType::HashLockOnly => write!(f, "hash lock only"),
+            SpendType::TimelockOnly => write!(f, "timelock only"),
+            SpendType::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Classification {
+    pub spend: SpendType,
+    pub confidence: Confidence,
+    pub reason: String,
+    /// template of the witness script or tapscript leaf, for script spends
+    pub template: Option<&'static str>,
+}
+
+impl Classification {
+    fn new(spend: SpendType, confidence: Confidence, reason: impl Into<String>) -> Self {
+        Self {
+            spend,
+            confidence,
+            reason: reason.into(),
+            template: None,
+        }
+    }
+
+    /// whoever sees the witness can reuse it in a transaction of their own
+    pub fn is_unsigned(&self) -> bool {
+        matches!(
+            self.spend,
+            SpendType::AnyoneCanSpend | SpendType::HashLockOnly | SpendType::TimelockOnly
+        )
+    }
+}
+
+/// labels inputs by the branch their witness actually executes.
+///
+/// scripts are run on the witness stack, a signature check passes when its signature
+/// is not empty: a transaction in the mempool can not carry a failing one
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WitnessClassifier;
+
+impl WitnessClassifier {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// `prevout` raises the confidence, without it the spend type is guessed from the witness
+    pub fn classify(&self, input: &TxIn, prevout: Option<&TxOut>) -> Classification {
+        let stack: Vec<&[u8]> = input.witness.iter().collect();
+        if stack.is_empty() {
+            return Classification::new(
+                SpendType::Unknown,
+                Confidence::Low,
+                "no witness, a legacy spend",
+            );
+        }
+
+        let Some(prevout) = prevout else {
+            return self.classify_by_shape(&input.witness, &stack);
+        };
+        let script_pubkey = &prevout.script_pubkey;
+        if script_pubkey.is_p2wpkh() {
+            Classification::new(SpendType::P2wpkh, Confidence::High, "p2wpkh prevout")
+        } else if script_pubkey.is_p2wsh() {
+            classify_p2wsh(&stack, Some(script_pubkey), Confidence::High)
+        } else if script_pubkey.is_p2tr() {
+            classify_p2tr(&input.witness, Some(prevout), Confidence::High)
+        } else if script_pubkey.is_p2sh() {
+            // nested segwit, the redeem script in script_sig decides
+            let nested = self.classify_by_shape(&input.witness, &stack);
+            Classification {
+                reason: format!("nested in p2sh, {}", nested.reason),
+                ..nested
+            }
+        } else {
+            Classification::new(
+                SpendType::Unknown,
+                Confidence::High,
+                format!("witness spending non segwit script {}", script_pubkey),
+            )
+        }
+    }
+
+    /// indexes of the inputs anybody could spend with the witness they carry
+    pub fn unsigned_inputs(&self, tx: &Transaction, prevouts: &[Option<TxOut>]) -> Vec<usize> {
+        tx.input
+            .iter()
+            .enumerate()
+            .filter(|(idx, input)| {
+                let prevout = prevouts.get(*idx).and_then(|prevout| prevout.as_ref());
+                self.classify(input, prevout).is_unsigned()
+            })
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    fn classify_by_shape(&self, witness: &Witness, stack: &[&[u8]]) -> Classification {
+        match stack {
+            [signature] if signature.len() == 64 || signature.len() == 65 => Classification::new(
+                SpendType::P2trKeyPath,
+                Confidence::Medium,
+                "a single schnorr signature sized element",
+            ),
+            [signature, pubkey] if is_ecdsa_signature(signature) && is_pubkey(pubkey) => {
+                Classification::new(
+                    SpendType::P2wpkh,
+                    Confidence::Medium,
+                    "an ecdsa signature and a compressed pubkey",
+                )
+            }
+            [.., _, last] if is_control_block(last) => {
+                classify_p2tr(witness, None, Confidence::Medium)
+            }
+            _ => classify_p2wsh(stack, None, Confidence::Medium),
+        }
+    }
+}
+
+fn classify_p2wsh(
+    stack: &[&[u8]],
+    script_pubkey: Option<&ScriptBuf>,
+    confidence: Confidence,
+) -> Classification {
+    let (witness_script, args) = stack.split_last().expect("witness is not empty");
+    let witness_script = Script::from_bytes(witness_script);
+    if let Some(script_pubkey) = script_pubkey {
+        let hash = WScriptHash::hash(witness_script.as_bytes());
+        if script_pubkey.as_bytes()[2..] != hash[..] {
+            return Classification::new(
+                SpendType::Unknown,
+                Confidence::High,
+                "witness script does not hash to the prevout",
+            );
+        }
+    }
+    let template = template(witness_script);
+    classify_script(
+        witness_script,
+        args,
+        false,
+        SpendType::P2wsh { template },
+        confidence,
+    )
+}
+
+fn classify_p2tr(
+    witness: &Witness,
+    prevout: Option<&TxOut>,
+    confidence: Confidence,
+) -> Classification {
+    let (stack, _) = witness::split_annex(witness);
+    if stack.len() == 1 {
+        return Classification::new(SpendType::P2trKeyPath, confidence, "key path spend");
+    }
+    let Some(spend) = witness::parse_tapscript_spend(witness, prevout) else {
+        return Classification::new(SpendType::Unknown, confidence, "invalid control block");
+    };
+    if prevout.is_some_and(|prevout| !spend.commits_to(prevout)) {
+        return Classification::new(
+            SpendType::Unknown,
+            confidence,
+            "leaf is not committed to by the output key",
+        );
+    }
+    if spend.control_block.leaf_version != LeafVersion::TapScript {
+        return Classification::new(
+            SpendType::Unknown,
+            confidence,
+            format!("leaf version {}", spend.control_block.leaf_version),
+        );
+    }
+
+    let args: Vec<&[u8]> = spend.args.iter().map(Vec::as_slice).collect();
+    classify_script(
+        &spend.leaf,
+        &args,
+        true,
+        SpendType::P2trScriptPath {
+            leaf: spend.leaf.clone(),
+        },
+        confidence,
+    )
+}
+
+fn classify_script(
+    script: &Script,
+    args: &[&[u8]],
+    tapscript: bool,
+    signed: SpendType,
+    confidence: Confidence,
+) -> Classification {
+    let template = template(script);
+    let classification = match execute(script, args, tapscript) {
+        Ok(run) if run.signature => Classification::new(
+            signed,
+            confidence,
+            format!("{} checks a signature in the executed branch", template),
+        ),
+        Ok(run) if run.hash_lock => Classification::new(
+            SpendType::HashLockOnly,
+            confidence,
+            format!("{} only checks a preimage in the executed branch", template),
+        ),
+        Ok(run) if run.timelock => Classification::new(
+            SpendType::TimelockOnly,
+            confidence,
+            format!("{} only checks a locktime in the executed branch", template),
+        ),
+        Ok(_) => Classification::new(
+            SpendType::AnyoneCanSpend,
+            confidence,
+            format!("{} checks nothing in the executed branch", template),
+        ),
+        Err(e) => Classification::new(
+            SpendType::Unknown,
+            Confidence::Low,
+            format!("{} not evaluated: {}", template, e),
+        ),
+    };
+    Classification {
+        template: Some(template),
+        ..classification
+    }
+}
+
+/// what the executed branch checked
+#[derive(Debug, Default)]
+struct Execution {
+    signature: bool,
+    hash_lock: bool,
+    timelock: bool,
+}
+
+fn execute(script: &Script, args: &[&[u8]], tapscript: bool) -> Result<Execution, String> {
+    let ctx = if tapscript {
+        ClassifyContext::TapScript
+    } else {
+        ClassifyContext::Legacy
+    };
+    let mut run = Execution::default();
+    let mut ops = vec![];
+    for instruction in script.instructions() {
+        match instruction.map_err(|e| format!("undecodable script: {}", e))? {
+            Instruction::Op(op) if op.classify(ctx) == Class::SuccessOp => {
+                // OP_SUCCESSx makes the whole leaf spendable by anyone
+                return Ok(run);
+            }
+            instruction => ops.push(instruction),
+        }
+    }
+
+    let mut stack: Vec<Vec<u8>> = args.iter().map(|arg| arg.to_vec()).collect();
+    let mut alt_stack = vec![];
+    let mut branches: Vec<bool> = vec![];
+    for instruction in ops {
+        let executing = branches.iter().all(|taken| *taken);
+        let op = match instruction {
+            Instruction::PushBytes(bytes) => {
+                if executing {
+                    stack.push(bytes.as_bytes().to_vec());
+                }
+                continue;
+            }
+            Instruction::Op(op) => op,
+        };
+        match op {
+            OP_IF | OP_NOTIF => {
+                let taken = executing && {
+                    let condition = read_scriptbool(&pop(&mut stack)?);
+                    condition == (op == OP_IF)
+                };
+                branches.push(taken);
+                continue;
+            }
+            OP_ELSE => {
+                let taken = branches.last_mut().ok_or("OP_ELSE outside a branch")?;
+                *taken = !*taken;
+                continue;
+            }
+            OP_ENDIF => {
+                branches.pop().ok_or("OP_ENDIF outside a branch")?;
+                continue;
+            }
+            _ if !executing => continue,
+            _ => {}
+        }
+
+        match op {
+            OP_PUSHNUM_NEG1 => stack.push(num(-1)),
+            op if (OP_PUSHNUM_1.to_u8()..=OP_PUSHNUM_16.to_u8()).contains(&op.to_u8()) => {
+                stack.push(num(i64::from(op.to_u8() - OP_PUSHNUM_1.to_u8() + 1)))
+            }
+            OP_NOP => {}
+            OP_VERIFY => verify(&pop(&mut stack)?, op)?,
+            OP_TOALTSTACK => alt_stack.push(pop(&mut stack)?),
+            OP_FROMALTSTACK => stack.push(pop(&mut alt_stack)?),
+            OP_DROP => {
+                pop(&mut stack)?;
+            }
+            OP_2DROP => {
+                pop(&mut stack)?;
+                pop(&mut stack)?;
+            }
+            OP_DUP => stack.push(peek(&stack, 0)?),
+            OP_2DUP => {
+                let (a, b) = (peek(&stack, 1)?, peek(&stack, 0)?);
+                stack.extend([a, b]);
+            }
+            OP_IFDUP => {
+                let top = peek(&stack, 0)?;
+                if read_scriptbool(&top) {
+                    stack.push(top);
+                }
+            }
+            OP_NIP => {
+                let top = pop(&mut stack)?;
+                pop(&mut stack)?;
+                stack.push(top);
+            }
+            OP_OVER => stack.push(peek(&stack, 1)?),
+            OP_SWAP => {
+                let (b, a) = (pop(&mut stack)?, pop(&mut stack)?);
+                stack.extend([b, a]);
+            }
+            OP_TUCK => {
+                let (b, a) = (pop(&mut stack)?, pop(&mut stack)?);
+                stack.extend([b.clone(), a, b]);
+            }
+            OP_ROT => {
+                let (c, b, a) = (pop(&mut stack)?, pop(&mut stack)?, pop(&mut stack)?);
+                stack.extend([b, c, a]);
+            }
+            OP_DEPTH => stack.push(num(stack.len() as i64)),
+            OP_SIZE => stack.push(num(peek(&stack, 0)?.len() as i64)),
+            OP_EQUAL | OP_EQUALVERIFY => {
+                let equal = pop(&mut stack)? == pop(&mut stack)?;
+                push_or_verify(&mut stack, equal, op == OP_EQUALVERIFY, op)?;
+            }
+            OP_NOT | OP_0NOTEQUAL | OP_1ADD | OP_1SUB => {
+                let n = pop_num(&mut stack)?;
+                stack.push(num(match op {
+                    OP_NOT => i64::from(n == 0),
+                    OP_0NOTEQUAL => i64::from(n != 0),
+                    OP_1ADD => n + 1,
+                    _ => n - 1,
+                }));
+            }
+            OP_ADD | OP_SUB | OP_BOOLAND | OP_BOOLOR | OP_LESSTHAN | OP_GREATERTHAN => {
+                let (b, a) = (pop_num(&mut stack)?, pop_num(&mut stack)?);
+                stack.push(num(match op {
+                    OP_ADD => a + b,
+                    OP_SUB => a - b,
+                    OP_BOOLAND => i64::from(a != 0 && b != 0),
+                    OP_BOOLOR => i64::from(a != 0 || b != 0),
+                    OP_LESSTHAN => i64::from(a < b),
+                    _ => i64::from(a > b),
+                }));
+            }
+            OP_NUMEQUAL | OP_NUMEQUALVERIFY => {
+                let equal = pop_num(&mut stack)? == pop_num(&mut stack)?;
+                push_or_verify(&mut stack, equal, op == OP_NUMEQUALVERIFY, op)?;
+            }
+            op if HASH_OPS.contains(&op) => {
+                let data = pop(&mut stack)?;
+                stack.push(hash_op(op, &data));
+                run.hash_lock = true;
+            }
+            OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+                pop(&mut stack)?;
+                let signed = !pop(&mut stack)?.is_empty();
+                run.signature |= signed;
+                push_or_verify(&mut stack, signed, op == OP_CHECKSIGVERIFY, op)?;
+            }
+            OP_CHECKSIGADD if tapscript => {
+                pop(&mut stack)?;
+                let n = pop_num(&mut stack)?;
+                let signed = !pop(&mut stack)?.is_empty();
+                run.signature |= signed;
+                stack.push(num(n + i64::from(signed)));
+            }
+            OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY if !tapscript => {
+                let keys = pop_num(&mut stack)?;
+                for _ in 0..keys {
+                    pop(&mut stack)?;
+                }
+                let required = pop_num(&mut stack)?;
+                let mut signed = true;
+                for _ in 0..required {
+                    signed &= !pop(&mut stack)?.is_empty();
+                }
+                // the extra element consumed by the off by one bug
+                pop(&mut stack)?;
+                // 0-of-n needs no signature at all
+                run.signature |= signed && required > 0;
+                push_or_verify(&mut stack, signed, op == OP_CHECKMULTISIGVERIFY, op)?;
+            }
+            OP_CLTV | OP_CSV => {
+                peek(&stack, 0)?;
+                run.timelock = true;
+            }
+            op => return Err(format!("{:?} is not evaluated", op)),
+        }
+    }
+
+    if !branches.is_empty() {
+        return Err("unbalanced conditional".to_string());
+    }
+    Ok(run)
+}
+
+pub(crate) const HASH_OPS: [Opcode; 5] = [OP_RIPEMD160, OP_SHA1, OP_SHA256, OP_HASH160, OP_HASH256];
+
+pub(crate) const SIGNATURE_OPS: [Opcode; 5] = [
+    OP_CHECKSIG,
+    OP_CHECKSIGVERIFY,
+    OP_CHECKSIGADD,
+    OP_CHECKMULTISIG,
+    OP_CHECKMULTISIGVERIFY,
+];
+
+/// the digest one of `HASH_OPS` leaves on the stack for `data`
+pub(crate) fn hash_op(op: Opcode, data: &[u8]) -> Vec<u8> {
+    match op {
+        OP_RIPEMD160 => ripemd160::Hash::hash(data).to_byte_array().to_vec(),
+        OP_SHA1 => sha1::Hash::hash(data).to_byte_array().to_vec(),
+        OP_SHA256 => sha256::Hash::hash(data).to_byte_array().to_vec(),
+        OP_HASH160 => hash160::Hash::hash(data).to_byte_array().to_vec(),
+        _ => sha256d::Hash::hash(data).to_byte_array().to_vec(),
+    }
+}
+
+fn pop(stack: &mut Vec<Vec<u8>>) -> Result<Vec<u8>, String> {
+    stack.pop().ok_or_else(|| "stack underflow".to_string())
+}
+
+fn peek(stack: &[Vec<u8>], depth: usize) -> Result<Vec<u8>, String> {
+    stack
+        .len()
+        .checked_sub(depth + 1)
+        .map(|idx| stack[idx].clone())
+        .ok_or_else(|| "stack underflow".to_string())
+}
+
+fn pop_num(stack: &mut Vec<Vec<u8>>) -> Result<i64, String> {
+    let bytes = pop(stack)?;
+    if bytes.len() > 5 {
+        return Err("number longer than 5 bytes".to_string());
+    }
+    read_scriptint_non_minimal(&bytes).map_err(|e| e.to_string())
+}
+
+fn num(n: i64) -> Vec<u8> {
+    let mut buf = [0u8; 8];
+    let len = write_scriptint(&mut buf, n);
+    buf[..len].to_vec()
+}
+
+fn verify(value: &[u8], op: Opcode) -> Result<(), String> {
+    if read_scriptbool(value) {
+        Ok(())
+    } else {
+        // the transaction would be invalid, whatever it is it is not in the mempool
+        Err(format!("{:?} fails", op))
+    }
+}
+
+fn push_or_verify(
+    stack: &mut Vec<Vec<u8>>,
+    result: bool,
+    is_verify: bool,
+    op: Opcode,
+) -> Result<(), String> {
+    let value = num(i64::from(result));
+    if is_verify {
+        verify(&value, op)
+    } else {
+        stack.push(value);
+        Ok(())
+    }
+}
+
+/// a name for the well known scripts, "custom" for the rest
+fn template(script: &Script) -> &'static str {
+    let ops: Vec<Instruction> = match script.instructions().collect() {
+        Ok(ops) => ops,
+        Err(_) => return "undecodable",
+    };
+    let push_len = |instruction: &Instruction, lens: &[usize]| match instruction {
+        Instruction::PushBytes(bytes) => lens.contains(&bytes.len()),
+        _ => false,
+    };
+    let has = |targets: &[Opcode]| {
+        ops.iter()
+            .any(|instruction| matches!(instruction, Instruction::Op(op) if targets.contains(op)))
+    };
+    use Instruction::Op;
+
+    match ops.as_slice() {
+        [] => "empty",
+        [Op(OP_PUSHNUM_1)] => "op_true",
+        [key, Op(OP_CHECKSIG)] if push_len(key, &[32, 33]) => "pk",
+        [Op(OP_DUP), Op(OP_HASH160), hash, Op(OP_EQUALVERIFY), Op(OP_CHECKSIG)]
+            if push_len(hash, &[20]) =>
+        {
+            "pkh"
+        }
+        [key, Op(OP_CHECKSIG), Op(OP_IFDUP), Op(OP_NOTIF), Op(OP_PUSHNUM_16), Op(OP_CSV), Op(OP_ENDIF)]
+            if push_len(key, &[33]) =>
+        {
+            "lightning_anchor"
+        }
+        // the only leaf of a simple taproot channel anchor
+        [Op(OP_PUSHNUM_16), Op(OP_CSV)] => "lightning_anchor",
+        [key, Op(OP_CHECKSIGVERIFY), Op(OP_PUSHNUM_1), Op(OP_CSV)] if push_len(key, &[33]) => {
+            "lightning_to_remote"
+        }
+        [Op(m), .., Op(OP_CHECKMULTISIG)]
+            if (OP_PUSHNUM_1.to_u8()..=OP_PUSHNUM_16.to_u8()).contains(&m.to_u8()) =>
+        {
+            "multisig"
+        }
+        _ if has(&[OP_CHECKSIGADD]) => "multi_a",
+        _ => {
+            let hash = has(&HASH_OPS);
+            let sig = has(&SIGNATURE_OPS);
+            let timelock = has(&[OP_CLTV, OP_CSV]);
+            match (hash, sig, timelock) {
+                (true, true, _) => "htlc",
+                (true, false, _) => "hash_lock",
+                (false, false, true) => "timelock",
+                _ => "custom",
+            }
+        }
+    }
+}
+
+fn is_ecdsa_signature(element: &[u8]) -> bool {
+    // der sequence plus the sighash byte
+    (9..=73).contains(&element.len()) && element[0] == 0x30
+}
+
+fn is_pubkey(element: &[u8]) -> bool {
+    element.len() == 33 && (element[0] == 0x02 || element[0] == 0x03)
+}
+
+fn is_control_block(element: &[u8]) -> bool {
+    element.len() >= 33 && (element.len() - 33).is_multiple_of(32) && element[0] & 0xfe == 0xc0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::script::Builder;
+    use bitcoin::taproot::TaprootBuilder;
+    use bitcoin::{hashes::Hash, OutPoint, PrivateKey, Txid};
+
+    fn input(witness: Vec<Vec<u8>>) -> TxIn {
+        TxIn {
+            previous_output: OutPoint {
+                txid: Txid::all_zeros(),
+                vout: 0,
+            },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence(0x10),
+            witness: Witness::from_slice(&witness),
+        }
+    }
+
+    fn p2wsh(witness_script: &Script, args: Vec<Vec<u8>>) -> (TxIn, TxOut) {
+        let mut witness = args;
+        witness.push(witness_script.to_bytes());
+        let prevout = TxOut {
+            value: Amount::from_sat(330),
+            script_pubkey: witness_script.to_p2wsh(),
+        };
+        (input(witness), prevout)
+    }
+
+    #[test]
+    fn labels_the_executed_branch() {
+        let classifier = WitnessClassifier::new();
+        let secp = secp256k1::Secp256k1::new();
+        let private_key = PrivateKey::from_slice(&[7u8; 32], Network::Regtest).unwrap();
+        let pubkey = private_key.public_key(&secp);
+        let signature = vec![0x30; 72];
+
+        // an anchor swept after 16 blocks never runs its signature check
+        let anchor = builder::anchor::build_anchor_redeem_script(&pubkey.to_bytes());
+        let (swept, prevout) = p2wsh(&anchor, vec![vec![]]);
+        let label = classifier.classify(&swept, Some(&prevout));
+        assert_eq!(label.spend, SpendType::TimelockOnly);
+        assert_eq!(label.template, Some("lightning_anchor"));
+        assert_eq!(label.confidence, Confidence::High);
+        assert!(label.is_unsigned());
+
+        let (signed, prevout) = p2wsh(&anchor, vec![signature.clone()]);
+        let label = classifier.classify(&signed, Some(&prevout));
+        assert_eq!(
+            label.spend,
+            SpendType::P2wsh {
+                template: "lightning_anchor"
+            }
+        );
+        assert!(!label.is_unsigned());
+
+        // a preimage alone unlocks a hash lock
+        let preimage = vec![1u8; 32];
+        let hash_lock = Builder::new()
+            .push_opcode(OP_SHA256)
+            .push_slice(sha256::Hash::hash(&preimage).to_byte_array())
+            .push_opcode(OP_EQUAL)
+            .into_script();
+        let (revealed, prevout) = p2wsh(&hash_lock, vec![preimage]);
+        let label = classifier.classify(&revealed, Some(&prevout));
+        assert_eq!(label.spend, SpendType::HashLockOnly);
+
+        let op_true = Builder::new().push_opcode(OP_PUSHNUM_1).into_script();
+        let (free, prevout) = p2wsh(&op_true, vec![]);
+        assert_eq!(
+            classifier.classify(&free, Some(&prevout)).spend,
+            SpendType::AnyoneCanSpend
+        );
+        // the same witness against another script is not what it claims
+        let mut other = prevout.clone();
+        other.script_pubkey = anchor.to_p2wsh();
+        assert_eq!(
+            classifier.classify(&free, Some(&other)).spend,
+            SpendType::Unknown
+        );
+
+        let p2wpkh = input(vec![signature.clone(), pubkey.to_bytes()]);
+        let label = classifier.classify(&p2wpkh, None);
+        assert_eq!(label.spend, SpendType::P2wpkh);
+        assert_eq!(label.confidence, Confidence::Medium);
+
+        let unsupported = Builder::new()
+            .push_opcode(OP_CAT)
+            .push_opcode(OP_DROP)
+            .push_opcode(OP_PUSHNUM_1)
+            .into_script();
+        let (unknown, prevout) = p2wsh(&unsupported, vec![vec![1], vec![2]]);
+        let label = classifier.classify(&unknown, Some(&prevout));
+        assert_eq!(label.spend, SpendType::Unknown);
+        assert_eq!(label.confidence, Confidence::Low);
+    }
+
+    #[test]
+    fn labels_taproot_paths() {
+        let classifier = WitnessClassifier::new();
+        let secp = secp256k1::Secp256k1::new();
+        let private_key = PrivateKey::from_slice(&[7u8; 32], Network::Regtest).unwrap();
+        let (internal_key, _) = private_key.inner.x_only_public_key(&secp);
+        let pk_leaf = Builder::new()
+            .push_x_only_key(&internal_key)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let free_leaf = Builder::new().push_opcode(OP_PUSHNUM_1).into_script();
+        let spend_info = TaprootBuilder::new()
+            .add_leaf(1, pk_leaf.clone())
+            .unwrap()
+            .add_leaf(1, free_leaf.clone())
+            .unwrap()
+            .finalize(&secp, internal_key)
+            .unwrap();
+        let prevout = TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: ScriptBuf::new_p2tr_tweaked(spend_info.output_key()),
+        };
+        let control_block = |leaf: &ScriptBuf| {
+            spend_info
+                .control_block(&(leaf.clone(), LeafVersion::TapScript))
+                .unwrap()
+                .serialize()
+        };
+
+        let key_path = input(vec![vec![1u8; 64]]);
+        assert_eq!(
+            classifier.classify(&key_path, Some(&prevout)).spend,
+            SpendType::P2trKeyPath
+        );
+
+        let signed = input(vec![
+            vec![1u8; 64],
+            pk_leaf.to_bytes(),
+            control_block(&pk_leaf),
+        ]);
+        assert_eq!(
+            classifier.classify(&signed, Some(&prevout)).spend,
+            SpendType::P2trScriptPath {
+                leaf: pk_leaf.clone()
+            }
+        );
+
+        let free = input(vec![free_leaf.to_bytes(), control_block(&free_leaf)]);
+        let label = classifier.classify(&free, Some(&prevout));
+        assert_eq!(label.spend, SpendType::AnyoneCanSpend);
+        // the shape alone finds the script path too
+        assert_eq!(classifier.classify(&free, None).spend, label.spend);
+
+        let tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![signed, free],
+            output: vec![],
+        };
+        assert_eq!(
+            classifier.unsigned_inputs(&tx, &[Some(prevout.clone()), Some(prevout)]),
+            vec![1]
+        );
+    }
+}
@@ -0,0 +1,596 @@
+use super::*;
+use error::BuildError;
+use fee_rate::FeeRate;
+use weight::{input_vsize, tx_vsize, Satisfaction};
+
+/// fee rate (sat/vB) we expect to pay when the change is spent later
+pub const DEFAULT_LONG_TERM_FEE_RATE: f64 = 10.0;
+
+const BNB_TOTAL_TRIES: usize = 100_000;
+const KNAPSACK_ITERATIONS: usize = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    BranchAndBound,
+    Knapsack,
+    LargestFirst,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// changeless, the excess goes to the miner
+    None {
+        excess: Amount,
+    },
+    Some(Amount),
+}
+
+#[derive(Debug, Clone)]
+pub struct Selection {
+    pub utxos: Vec<types::Utxo>,
+    pub change: Change,
+    pub strategy: Strategy,
+    pub waste: i64,
+}
+
+impl Selection {
+    pub fn input_value(&self) -> Amount {
+        self.utxos.iter().map(|utxo| utxo.value).sum()
+    }
+}
+
+/// the waste metric compares selections by what they cost now against
+/// what the same inputs would cost at `long_term_fee_rate`
+#[derive(Debug, Clone, Copy)]
+pub struct WasteMetric {
+    pub long_term_fee_rate: FeeRate,
+    pub change_output_vsize: usize,
+    pub change_spend_vsize: usize,
+    pub min_change: Amount,
+}
+
+impl WasteMetric {
+    pub fn new(long_term_fee_rate: FeeRate, change_script: &Script) -> Self {
+        let change_out = TxOut {
+            value: Amount::ZERO,
+            script_pubkey: change_script.to_owned(),
+        };
+        Self {
+            long_term_fee_rate,
+            change_output_vsize: change_out.size(),
+            change_spend_vsize: input_vsize(change_script),
+            min_change: change_script.minimal_non_dust(),
+        }
+    }
+
+    pub fn with_default_long_term(change_script: &Script) -> Self {
+        let long_term = FeeRate::try_from(DEFAULT_LONG_TERM_FEE_RATE)
+            .expect("default long term fee rate is valid");
+        Self::new(long_term, change_script)
+    }
+
+    fn change_fee(&self, fee_rate: FeeRate) -> i64 {
+        fee_rate.fee(self.change_output_vsize).to_sat() as i64
+    }
+
+    fn cost_of_change(&self, fee_rate: FeeRate) -> i64 {
+        self.change_fee(fee_rate)
+            + self
+                .long_term_fee_rate
+                .fee(self.change_spend_vsize)
+                .to_sat() as i64
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Candidate {
+    utxo: types::Utxo,
+    effective_value: i64,
+    fee: i64,
+    long_term_fee: i64,
+}
+
+/// run every strategy and keep the selection with the lowest waste
+pub fn select_coins(
+    utxos: &[types::Utxo],
+    target: Amount,
+    fee_rate: FeeRate,
+    metric: &WasteMetric,
+) -> Result<Selection, BuildError> {
+    let mut best: Option<Selection> = None;
+    for strategy in [
+        Strategy::BranchAndBound,
+        Strategy::Knapsack,
+        Strategy::LargestFirst,
+    ] {
+        if let Ok(selection) = select_coins_with(strategy, utxos, target, fee_rate, metric) {
+            if best.as_ref().is_none_or(|b| selection.waste < b.waste) {
+                best = Some(selection);
+            }
+        }
+    }
+
+    best.ok_or_else(|| BuildError::InsufficientFunds {
+        needed: target,
+        available: utxos.iter().map(|utxo| utxo.value).sum(),
+    })
+}
+
+pub fn select_coins_with(
+    strategy: Strategy,
+    utxos: &[types::Utxo],
+    target: Amount,
+    fee_rate: FeeRate,
+    metric: &WasteMetric,
+) -> Result<Selection> {
+    let candidates = candidates(utxos, fee_rate, metric.long_term_fee_rate);
+    let target_sat = target.to_sat() as i64;
+    let selected = match strategy {
+        Strategy::BranchAndBound => {
+            branch_and_bound(&candidates, target_sat, metric.cost_of_change(fee_rate))
+        }
+        Strategy::Knapsack => knapsack(&candidates, target_sat, metric.min_change.to_sat() as i64),
+        Strategy::LargestFirst => largest_first(&candidates, target_sat),
+    }
+    .ok_or_else(|| {
+        anyhow!(
+            "{:?} coin selection found no solution for {}",
+            strategy,
+            target
+        )
+    })?;
+
+    let chosen: Vec<&Candidate> = selected.iter().map(|idx| &candidates[*idx]).collect();
+    let effective: i64 = chosen.iter().map(|c| c.effective_value).sum();
+    let excess = effective - target_sat;
+    let input_waste: i64 = chosen.iter().map(|c| c.fee - c.long_term_fee).sum();
+
+    // BnB never creates change, it only accepts solutions inside the changeless window
+    let change_fee = metric.change_fee(fee_rate);
+    let with_change = strategy != Strategy::BranchAndBound
+        && excess - change_fee >= metric.min_change.to_sat() as i64;
+    let (change, waste) = if with_change {
+        (
+            Change::Some(Amount::from_sat((excess - change_fee) as u64)),
+            input_waste + metric.cost_of_change(fee_rate),
+        )
+    } else {
+        (
+            Change::None {
+                excess: Amount::from_sat(excess as u64),
+            },
+            input_waste + excess,
+        )
+    };
+
+    Ok(Selection {
+        utxos: chosen.into_iter().map(|c| c.utxo.clone()).collect(),
+        change,
+        strategy,
+        waste,
+    })
+}
+
+/// pick the single cheapest-to-spend utxo holding at least `min_value`.
+/// used by the sweep builders, which always need exactly one wallet input
+pub fn select_fee_input(candidates: &[types::Utxo], min_value: Amount) -> Result<types::Utxo> {
+    candidates
+        .iter()
+        .filter(|utxo| {
+            utxo.value >= min_value && utxo.value >= utxo.script_pubkey.minimal_non_dust()
+        })
+        .min_by_key(|utxo| (input_vsize(&utxo.script_pubkey), utxo.value))
+        .cloned()
+        .ok_or_else(|| anyhow!("not found utxo with value of at least {}", min_value))
+}
+
+/// the `min_value` of the fee input of a sweep spending `inputs` worth `swept` to `outputs`:
+/// the fee and non dust outputs the swept value does not cover. the fee input is priced
+/// as p2pkh, the largest wallet input
+pub fn sweep_fee_floor(
+    inputs: &[Satisfaction],
+    swept: Amount,
+    outputs: &[TxOut],
+    fee_rate: FeeRate,
+) -> Amount {
+    let inputs: Vec<Satisfaction> = std::iter::once(Satisfaction::P2pkh)
+        .chain(inputs.iter().cloned())
+        .collect();
+    let fee = fee_rate.fee(tx_vsize(&inputs, outputs));
+    let dust: Amount = outputs
+        .iter()
+        .map(|out| out.script_pubkey.minimal_non_dust())
+        .sum();
+    (fee + dust).checked_sub(swept).unwrap_or(Amount::ZERO)
+}
+
+fn candidates(utxos: &[types::Utxo], fee_rate: FeeRate, long_term: FeeRate) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = utxos
+        .iter()
+        .map(|utxo| {
+            let vsize = input_vsize(&utxo.script_pubkey);
+            let fee = fee_rate.fee(vsize).to_sat() as i64;
+            Candidate {
+                utxo: utxo.clone(),
+                effective_value: utxo.value.to_sat() as i64 - fee,
+                fee,
+                long_term_fee: long_term.fee(vsize).to_sat() as i64,
+            }
+        })
+        .filter(|c| c.effective_value > 0)
+        .collect();
+    candidates.sort_by_key(|c| std::cmp::Reverse(c.effective_value));
+    candidates
+}
+
+/// depth first search for a changeless solution in [target, target + cost_of_change]
+fn branch_and_bound(
+    candidates: &[Candidate],
+    target: i64,
+    cost_of_change: i64,
+) -> Option<Vec<usize>> {
+    let mut available: i64 = candidates.iter().map(|c| c.effective_value).sum();
+    if available < target {
+        return None;
+    }
+
+    let fee_is_high = candidates.first().is_some_and(|c| c.fee > c.long_term_fee);
+    let mut current: Vec<usize> = vec![];
+    let mut current_value = 0;
+    let mut current_waste = 0;
+    let mut best: Option<Vec<usize>> = None;
+    let mut best_waste = i64::MAX;
+
+    let mut idx = 0;
+    for _ in 0..BNB_TOTAL_TRIES {
+        let mut backtrack = false;
+        if current_value + available < target
+            || current_value > target + cost_of_change
+            || (current_waste > best_waste && fee_is_high)
+        {
+            backtrack = true;
+        } else if current_value >= target {
+            let waste = current_waste + current_value - target;
+            if waste <= best_waste {
+                best = Some(current.clone());
+                best_waste = waste;
+            }
+            backtrack = true;
+        }
+
+        if backtrack {
+            let Some(&last) = current.last() else {
+                break;
+            };
+            // put the skipped candidates back, then try the branch without `last`
+            idx -= 1;
+            while idx > last {
+                available += candidates[idx].effective_value;
+                idx -= 1;
+            }
+            let candidate = &candidates[last];
+            current_value -= candidate.effective_value;
+            current_waste -= candidate.fee - candidate.long_term_fee;
+            current.pop();
+        } else {
+            let candidate = &candidates[idx];
+            available -= candidate.effective_value;
+            // an equal candidate right after an excluded one leads to the same solutions
+            let is_duplicate = !current.is_empty()
+                && current.last() != Some(&(idx - 1))
+                && candidate.effective_value == candidates[idx - 1].effective_value
+                && candidate.fee == candidates[idx - 1].fee;
+            if !is_duplicate {
+                current.push(idx);
+                current_value += candidate.effective_value;
+                current_waste += candidate.fee - candidate.long_term_fee;
+            }
+        }
+        idx += 1;
+    }
+
+    best
+}
+
+fn knapsack(candidates: &[Candidate], target: i64, min_change: i64) -> Option<Vec<usize>> {
+    let mut lowest_larger: Option<usize> = None;
+    let mut applicable = vec![];
+    let mut total_lower = 0;
+    for (idx, candidate) in candidates.iter().enumerate() {
+        if candidate.effective_value == target {
+            return Some(vec![idx]);
+        } else if candidate.effective_value < target + min_change {
+            applicable.push(idx);
+            total_lower += candidate.effective_value;
+        } else if lowest_larger
+            .is_none_or(|l| candidate.effective_value < candidates[l].effective_value)
+        {
+            lowest_larger = Some(idx);
+        }
+    }
+
+    if total_lower == target {
+        return Some(applicable);
+    }
+    if total_lower < target {
+        return lowest_larger.map(|l| vec![l]);
+    }
+
+    let values: Vec<i64> = applicable
+        .iter()
+        .map(|idx| candidates[*idx].effective_value)
+        .collect();
+    let mut rng = XorShift::new(target as u64 ^ values.len() as u64);
+    let (mut best, mut best_value) =
+        approximate_best_subset(&mut rng, &values, total_lower, target);
+    if best_value != target && total_lower >= target + min_change {
+        (best, best_value) =
+            approximate_best_subset(&mut rng, &values, total_lower, target + min_change);
+    }
+
+    if let Some(l) = lowest_larger {
+        if (best_value != target && best_value < target + min_change)
+            || candidates[l].effective_value <= best_value
+        {
+            return Some(vec![l]);
+        }
+    }
+
+    Some(
+        best.iter()
+            .zip(applicable.iter())
+            .filter(|(included, _)| **included)
+            .map(|(_, idx)| *idx)
+            .collect(),
+    )
+}
+
+fn approximate_best_subset(
+    rng: &mut XorShift,
+    values: &[i64],
+    total_lower: i64,
+    target: i64,
+) -> (Vec<bool>, i64) {
+    let mut best = vec![true; values.len()];
+    let mut best_value = total_lower;
+
+    for _ in 0..KNAPSACK_ITERATIONS {
+        if best_value == target {
+            break;
+        }
+        let mut included = vec![false; values.len()];
+        let mut total = 0;
+        let mut reached_target = false;
+        for pass in 0..2 {
+            if reached_target {
+                break;
+            }
+            for i in 0..values.len() {
+                // first pass picks randomly, second pass fills up with the rest
+                let pick = if pass == 0 {
+                    rng.next_bool()
+                } else {
+                    !included[i]
+                };
+                if !pick {
+                    continue;
+                }
+                total += values[i];
+                included[i] = true;
+                if total >= target {
+                    reached_target = true;
+                    if total < best_value {
+                        best_value = total;
+                        best = included.clone();
+                    }
+                    total -= values[i];
+                    included[i] = false;
+                }
+            }
+        }
+    }
+
+    (best, best_value)
+}
+
+fn largest_first(candidates: &[Candidate], target: i64) -> Option<Vec<usize>> {
+    let mut total = 0;
+    let mut selected = vec![];
+    for (idx, candidate) in candidates.iter().enumerate() {
+        if total >= target {
+            break;
+        }
+        total += candidate.effective_value;
+        selected.push(idx);
+    }
+
+    if total < target {
+        return None;
+    }
+    Some(selected)
+}
+
+// knapsack only needs a cheap, deterministic coin flip
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        Self(seed | 1)
+    }
+
+    fn next_bool(&mut self) -> bool {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 & 1 == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datatypes::fixtures;
+
+    fn p2tr_script() -> ScriptBuf {
+        ScriptBuf::from_hex("5120937ad9cb999e5aee75d91e398e33b0a45e64c2a532a2adf3c0e10c838dcf7825")
+            .unwrap()
+    }
+
+    fn fee_rate(rate: f64) -> FeeRate {
+        FeeRate::try_from(rate).unwrap()
+    }
+
+    #[test]
+    fn bnb_finds_changeless_match() {
+        let rate = fee_rate(1.0);
+        let metric = WasteMetric::new(fee_rate(1.0), &p2tr_script());
+        let input_fee = rate.fee(input_vsize(&p2tr_script())).to_sat();
+        let utxos = vec![
+            fixtures::utxo(&p2tr_script(), 0, 100_000),
+            fixtures::utxo(&p2tr_script(), 1, 30_000 + input_fee),
+            fixtures::utxo(&p2tr_script(), 2, 50_000),
+        ];
+
+        let selection = select_coins_with(
+            Strategy::BranchAndBound,
+            &utxos,
+            Amount::from_sat(30_000),
+            rate,
+            &metric,
+        )
+        .unwrap();
+        assert_eq!(selection.utxos.len(), 1);
+        assert_eq!(selection.utxos[0].out_point.vout, 1);
+        assert_eq!(
+            selection.change,
+            Change::None {
+                excess: Amount::ZERO
+            }
+        );
+    }
+
+    #[test]
+    fn largest_first_creates_change() {
+        let rate = fee_rate(2.0);
+        let metric = WasteMetric::with_default_long_term(&p2tr_script());
+        let utxos = vec![
+            fixtures::utxo(&p2tr_script(), 0, 10_000),
+            fixtures::utxo(&p2tr_script(), 1, 80_000),
+            fixtures::utxo(&p2tr_script(), 2, 50_000),
+        ];
+
+        let selection = select_coins_with(
+            Strategy::LargestFirst,
+            &utxos,
+            Amount::from_sat(100_000),
+            rate,
+            &metric,
+        )
+        .unwrap();
+        assert_eq!(selection.utxos.len(), 2);
+        assert!(matches!(selection.change, Change::Some(_)));
+    }
+
+    #[test]
+    fn select_coins_skips_unnecessary_inputs() {
+        let rate = fee_rate(5.0);
+        let metric = WasteMetric::with_default_long_term(&p2tr_script());
+        let utxos: Vec<types::Utxo> = (0..20)
+            .map(|i| fixtures::utxo(&p2tr_script(), i, 1_000 + i as u64))
+            .collect();
+        let mut all = utxos.clone();
+        all.push(fixtures::utxo(&p2tr_script(), 99, 60_000));
+
+        let selection = select_coins(&all, Amount::from_sat(40_000), rate, &metric).unwrap();
+        assert_eq!(selection.utxos.len(), 1);
+        assert_eq!(selection.utxos[0].out_point.vout, 99);
+    }
+
+    #[test]
+    fn select_coins_insufficient_funds() {
+        let rate = fee_rate(1.0);
+        let metric = WasteMetric::with_default_long_term(&p2tr_script());
+        let utxos = vec![
+            fixtures::utxo(&p2tr_script(), 0, 1_000),
+            fixtures::utxo(&p2tr_script(), 1, 2_000),
+        ];
+        let err = select_coins(&utxos, Amount::from_sat(5_000), rate, &metric).unwrap_err();
+        assert_eq!(
+            err,
+            BuildError::InsufficientFunds {
+                needed: Amount::from_sat(5_000),
+                available: Amount::from_sat(3_000),
+            }
+        );
+    }
+
+    #[test]
+    fn knapsack_combines_small_inputs() {
+        let rate = fee_rate(1.0);
+        let metric = WasteMetric::with_default_long_term(&p2tr_script());
+        let utxos = vec![
+            fixtures::utxo(&p2tr_script(), 0, 3_000),
+            fixtures::utxo(&p2tr_script(), 1, 4_000),
+            fixtures::utxo(&p2tr_script(), 2, 5_000),
+        ];
+
+        let selection = select_coins_with(
+            Strategy::Knapsack,
+            &utxos,
+            Amount::from_sat(7_000),
+            rate,
+            &metric,
+        )
+        .unwrap();
+        let value = selection.input_value().to_sat();
+        assert!(value >= 7_000);
+        assert!(selection.utxos.len() >= 2);
+    }
+
+    #[test]
+    fn fee_input_prefers_smallest_sufficient() {
+        let utxos = vec![
+            fixtures::utxo(&p2tr_script(), 0, 100_000),
+            fixtures::utxo(&p2tr_script(), 1, 2_000),
+            fixtures::utxo(&p2tr_script(), 2, 200),
+        ];
+        let selected = select_fee_input(&utxos, Amount::from_sat(1_000)).unwrap();
+        assert_eq!(selected.out_point.vout, 1);
+        assert!(select_fee_input(&utxos, Amount::from_sat(200_000)).is_err());
+    }
+
+    #[test]
+    fn fee_floor_covers_what_the_sweep_does_not() {
+        let rate = fee_rate(10.0);
+        let outputs = vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: p2tr_script(),
+        }];
+        let inputs = vec![Satisfaction::Anchor, Satisfaction::Anchor];
+        let fee = rate.fee(tx_vsize(
+            &[
+                Satisfaction::P2pkh,
+                Satisfaction::Anchor,
+                Satisfaction::Anchor,
+            ],
+            &outputs,
+        ));
+        let dust = p2tr_script().minimal_non_dust();
+
+        let swept = Amount::from_sat(660);
+        let floor = sweep_fee_floor(&inputs, swept, &outputs, rate);
+        assert_eq!(floor, fee + dust - swept);
+        // a too small fee input is passed over
+        let utxos = vec![
+            fixtures::utxo(&p2tr_script(), 0, floor.to_sat() - 1),
+            fixtures::utxo(&p2tr_script(), 1, floor.to_sat()),
+        ];
+        assert_eq!(select_fee_input(&utxos, floor).unwrap().out_point.vout, 1);
+
+        // a sweep paying for itself takes any fee input
+        assert_eq!(
+            sweep_fee_floor(&inputs, Amount::from_sat(100_000), &outputs, rate),
+            Amount::ZERO
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use super::*;
+use bitcoin::OutPoint;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    InsufficientFunds {
+        needed: Amount,
+        available: Amount,
+    },
+    InvalidAddress(String),
+    DustOutput {
+        script: ScriptBuf,
+        value: Amount,
+        threshold: Amount,
+    },
+    OpReturnTooLarge(usize),
+    NoRecipients,
+    /// a TRUC transaction above its BIP 431 size limit
+    TrucTooLarge {
+        vsize: usize,
+        max: usize,
+    },
+    /// a TRUC transaction with more than one unconfirmed ancestor
+    TrucAncestors(usize),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::InsufficientFunds { needed, available } => {
+                write!(
+                    f,
+                    "insufficient funds: need {}, available {}",
+                    needed, available
+                )
+            }
+            BuildError::InvalidAddress(addr) => write!(f, "invalid address: {}", addr),
+            BuildError::DustOutput {
+                script,
+                value,
+                threshold,
+            } => write!(
+                f,
+                "output {} to {} is below the dust threshold {}",
+                value,
+                script.to_hex_string(),
+                threshold
+            ),
+            BuildError::OpReturnTooLarge(len) => write!(
+                f,
+                "op_return data is {} bytes, max is {}",
+                len,
+                policy::MAX_OP_RETURN_DATA
+            ),
+            BuildError::NoRecipients => write!(f, "no recipients"),
+            BuildError::TrucTooLarge { vsize, max } => {
+                write!(f, "TRUC transaction is {} vB, max is {} vB", vsize, max)
+            }
+            BuildError::TrucAncestors(count) => write!(
+                f,
+                "TRUC transaction has {} unconfirmed ancestors, max is 1",
+                count
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyError {
+    PrevoutCount {
+        inputs: usize,
+        prevouts: usize,
+    },
+    Input {
+        idx: usize,
+        outpoint: OutPoint,
+        reason: String,
+    },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::PrevoutCount { inputs, prevouts } => {
+                write!(f, "tx has {} inputs but {} prevouts", inputs, prevouts)
+            }
+            VerifyError::Input {
+                idx,
+                outpoint,
+                reason,
+            } => write!(f, "input {} spending {} fails: {}", idx, outpoint, reason),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
@@ -0,0 +1,166 @@
+use super::*;
+use bitcoin::SignedAmount;
+use fee_rate::FeeRate;
+use policy::TxVersion;
+use weight::Satisfaction;
+
+/// decides which anchors are worth sweeping at the current fee rate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SweepFeePolicy {
+    /// sweeps expected to net less than this are declined
+    pub min_profit: Amount,
+    /// the version sweeps are built with
+    pub version: TxVersion,
+}
+
+/// an anchor the sweep may spend
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepCandidate {
+    pub value: Amount,
+    pub satisfaction: Satisfaction,
+}
+
+/// the anchors picked by `SweepFeePolicy::plan`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SweepPlan {
+    /// indexes into the candidates, in their original order
+    pub selected: Vec<usize>,
+    pub fee: Amount,
+    pub net_gain: SignedAmount,
+}
+
+impl SweepFeePolicy {
+    pub fn new(min_profit: Amount) -> Self {
+        Self {
+            min_profit,
+            version: TxVersion::Standard,
+        }
+    }
+
+    /// build sweeps as TRUC transactions
+    pub fn truc(self) -> Self {
+        Self {
+            version: TxVersion::Truc,
+            ..self
+        }
+    }
+
+    /// pick the anchors paying more than their own input costs at `fee_rate`.
+    ///
+    /// `fixed` inputs and `outputs` are part of the sweep whatever is selected, the
+    /// fee input gives back its value so only its weight counts against the gain.
+    /// fails when the sweep would net less than `min_profit`
+    pub fn plan(
+        &self,
+        fixed: &[Satisfaction],
+        outputs: &[TxOut],
+        candidates: &[SweepCandidate],
+        fee_rate: FeeRate,
+    ) -> Result<SweepPlan> {
+        let selected: Vec<usize> = candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, candidate)| {
+                let input_fee = fee_rate.fee(candidate.satisfaction.input_weight().div_ceil(4));
+                candidate.value > input_fee
+            })
+            .map(|(idx, _)| idx)
+            .collect();
+        if selected.is_empty() {
+            bail!(
+                "none of {} anchors pays for its input at {:?}",
+                candidates.len(),
+                fee_rate
+            );
+        }
+
+        let mut inputs = fixed.to_vec();
+        inputs.extend(
+            selected
+                .iter()
+                .map(|idx| candidates[*idx].satisfaction.clone()),
+        );
+        let fee = fee_rate.fee(weight::tx_vsize(&inputs, outputs));
+        let swept: Amount = selected.iter().map(|idx| candidates[*idx].value).sum();
+        let net_gain = swept.to_signed()? - fee.to_signed()?;
+        if net_gain < self.min_profit.to_signed()? {
+            bail!(
+                "sweeping {} anchors of {} for a {} fee nets {}, below the {} minimum",
+                selected.len(),
+                swept,
+                fee,
+                net_gain,
+                self.min_profit
+            );
+        }
+
+        Ok(SweepPlan {
+            selected,
+            fee,
+            net_gain,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn anchors(values: &[u64]) -> Vec<SweepCandidate> {
+        values
+            .iter()
+            .map(|value| SweepCandidate {
+                value: Amount::from_sat(*value),
+                satisfaction: Satisfaction::Anchor,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn drops_unprofitable_anchors_and_declines_losing_sweeps() {
+        let fixed = [Satisfaction::TaprootKeySpend];
+        let outputs = [TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::from_hex(
+                "51200000000000000000000000000000000000000000000000000000000000000000",
+            )
+            .unwrap(),
+        }];
+        let policy = SweepFeePolicy::default();
+        let anchor_vsize = Satisfaction::Anchor.input_weight().div_ceil(4) as u64;
+
+        // at 1 sat/vB two 330 sat anchors pay for the whole sweep
+        let low = FeeRate::try_from(1.0).unwrap();
+        let plan = policy
+            .plan(&fixed, &outputs, &anchors(&[330, 330]), low)
+            .unwrap();
+        assert_eq!(plan.selected, vec![0, 1]);
+        assert_eq!(
+            plan.net_gain,
+            SignedAmount::from_sat(660) - plan.fee.to_signed().unwrap()
+        );
+
+        // an anchor worth less than its own input is left out
+        let rate = FeeRate::try_from(5.0).unwrap();
+        let plan = policy
+            .plan(
+                &fixed,
+                &outputs,
+                &anchors(&[5 * anchor_vsize, 20_000]),
+                rate,
+            )
+            .unwrap();
+        assert_eq!(plan.selected, vec![1]);
+
+        // the fee input and output overhead make the sweep a loss
+        let high = FeeRate::try_from(4.0).unwrap();
+        assert!(policy
+            .plan(&fixed, &outputs, &anchors(&[330, 330]), high)
+            .is_err());
+
+        let picky = SweepFeePolicy::new(Amount::from_sat(500));
+        assert!(picky
+            .plan(&fixed, &outputs, &anchors(&[330, 330]), low)
+            .is_err());
+    }
+}
@@ -0,0 +1,391 @@
+use super::*;
+use bitcoin::opcodes::all::*;
+use bitcoin::opcodes::Opcode;
+use bitcoin::taproot::LeafVersion;
+use std::fmt;
+use tracing::debug;
+
+/// scripts with more conditionals are not walked path by path
+const MAX_CONDITIONALS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashKind {
+    Sha256,
+    Hash160,
+    Hash256,
+    Ripemd160,
+}
+
+impl HashKind {
+    const ALL: [HashKind; 4] = [
+        HashKind::Sha256,
+        HashKind::Hash160,
+        HashKind::Hash256,
+        HashKind::Ripemd160,
+    ];
+
+    fn opcode(&self) -> Opcode {
+        match self {
+            HashKind::Sha256 => OP_SHA256,
+            HashKind::Hash160 => OP_HASH160,
+            HashKind::Hash256 => OP_HASH256,
+            HashKind::Ripemd160 => OP_RIPEMD160,
+        }
+    }
+
+    fn from_opcode(op: Opcode) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.opcode() == op)
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            HashKind::Sha256 | HashKind::Hash256 => 32,
+            HashKind::Hash160 | HashKind::Ripemd160 => 20,
+        }
+    }
+
+    /// what the script's hash opcode makes of `preimage`
+    pub fn digest(&self, preimage: &[u8]) -> Vec<u8> {
+        classifier::hash_op(self.opcode(), preimage)
+    }
+}
+
+impl fmt::Display for HashKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HashKind::Sha256 => "sha256",
+            HashKind::Hash160 => "hash160",
+            HashKind::Hash256 => "hash256",
+            HashKind::Ripemd160 => "ripemd160",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for HashKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "sha256" => Ok(HashKind::Sha256),
+            "hash160" => Ok(HashKind::Hash160),
+            "hash256" => Ok(HashKind::Hash256),
+            "ripemd160" => Ok(HashKind::Ripemd160),
+            _ => bail!("unknown hash kind {}", s),
+        }
+    }
+}
+
+/// `<kind> <hash> OP_EQUAL(VERIFY)` somewhere in a script
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HashLock {
+    pub kind: HashKind,
+    pub hash: Vec<u8>,
+}
+
+impl HashLock {
+    pub fn opens_with(&self, preimage: &[u8]) -> bool {
+        self.kind.digest(preimage) == self.hash
+    }
+}
+
+/// a preimage published in a spending witness
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preimage {
+    pub lock: HashLock,
+    pub preimage: Vec<u8>,
+}
+
+/// the script an input executes with the output script it hashes to, for p2wsh and tapscript spends
+pub fn spent_script(input: &TxIn) -> Option<(ScriptBuf, ScriptBuf)> {
+    if let Some(spend) = witness::parse_tapscript_spend(&input.witness, None) {
+        if spend.control_block.leaf_version == LeafVersion::TapScript {
+            let script_pubkey = spend.script_pubkey();
+            return Some((spend.leaf, script_pubkey));
+        }
+    }
+
+    // a taproot spend carrying an annex
+    if witness::split_annex(&input.witness).1.is_some() {
+        return None;
+    }
+    let label = classifier::WitnessClassifier::new().classify(input, None);
+    if label.spend == classifier::SpendType::P2wpkh {
+        return None;
+    }
+    let stack: Vec<&[u8]> = input.witness.iter().collect();
+    match stack.as_slice() {
+        [_, .., script] => {
+            let script = ScriptBuf::from_bytes(script.to_vec());
+            if script
+                .instructions()
+                .any(|instruction| instruction.is_err())
+            {
+                return None;
+            }
+            let script_pubkey = script.to_p2wsh();
+            Some((script, script_pubkey))
+        }
+        _ => None,
+    }
+}
+
+/// every hash lock of the script, whatever else its branch checks
+pub fn hash_locks(script: &Script) -> Vec<HashLock> {
+    match script.instructions().collect::<Result<Vec<_>, _>>() {
+        Ok(instructions) => locks_in(&instructions),
+        Err(_) => vec![],
+    }
+}
+
+/// hash locks on a spend path checking no signature, anybody knowing the preimage spends it.
+///
+/// every path is walked: outputs are checked before any witness exists to run
+/// `classifier` on, and a spend only executes one of them
+pub fn hash_lock_only_paths(script: &Script) -> Vec<HashLock> {
+    let Ok(instructions) = script.instructions().collect::<Result<Vec<_>, _>>() else {
+        return vec![];
+    };
+    let conditionals = instructions
+        .iter()
+        .filter(|instruction| {
+            matches!(instruction, Instruction::Op(op) if *op == OP_IF || *op == OP_NOTIF)
+        })
+        .count();
+    if conditionals > MAX_CONDITIONALS {
+        debug!("{} conditionals, not walking {}", conditionals, script);
+        return vec![];
+    }
+
+    let mut locks = vec![];
+    for choice in 0..1u32 << conditionals {
+        let path = walk(&instructions, choice);
+        let signed = path.iter().any(|instruction| {
+            matches!(instruction, Instruction::Op(op) if classifier::SIGNATURE_OPS.contains(op))
+        });
+        if signed {
+            continue;
+        }
+        for lock in locks_in(&path) {
+            if !locks.contains(&lock) {
+                locks.push(lock);
+            }
+        }
+    }
+    locks
+}
+
+/// preimages the witness hands to the hash locks of the script it spends
+pub fn revealed_preimages(input: &TxIn) -> Vec<Preimage> {
+    let Some((script, _)) = spent_script(input) else {
+        return vec![];
+    };
+    let locks = hash_locks(&script);
+    if locks.is_empty() {
+        return vec![];
+    }
+
+    let mut preimages = vec![];
+    for element in input.witness.iter() {
+        for lock in locks.iter() {
+            if lock.opens_with(element) {
+                preimages.push(Preimage {
+                    lock: lock.clone(),
+                    preimage: element.to_vec(),
+                });
+            }
+        }
+    }
+    preimages
+}
+
+/// the instructions executed when the n-th conditional takes the branch of the n-th bit of `choice`
+fn walk<'a>(instructions: &[Instruction<'a>], choice: u32) -> Vec<Instruction<'a>> {
+    let mut path = vec![];
+    let mut branches: Vec<bool> = vec![];
+    let mut conditional = 0;
+    for instruction in instructions {
+        match instruction {
+            Instruction::Op(OP_IF | OP_NOTIF) => {
+                let taken = choice >> conditional & 1 == 1;
+                conditional += 1;
+                branches.push(taken);
+            }
+            Instruction::Op(OP_ELSE) => {
+                if let Some(taken) = branches.last_mut() {
+                    *taken = !*taken;
+                }
+            }
+            Instruction::Op(OP_ENDIF) => {
+                branches.pop();
+            }
+            instruction if branches.iter().all(|taken| *taken) => path.push(*instruction),
+            _ => {}
+        }
+    }
+    path
+}
+
+fn locks_in(instructions: &[Instruction]) -> Vec<HashLock> {
+    instructions
+        .windows(3)
+        .filter_map(|window| match window {
+            [Instruction::Op(op), Instruction::PushBytes(hash), Instruction::Op(OP_EQUAL | OP_EQUALVERIFY)] =>
+            {
+                let kind = HashKind::from_opcode(*op)?;
+                (hash.len() == kind.len()).then(|| HashLock {
+                    kind,
+                    hash: hash.as_bytes().to_vec(),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::{sha256, sha256d, Hash};
+    use bitcoin::script::Builder;
+    use bitcoin::taproot::TaprootBuilder;
+    use bitcoin::{OutPoint, PrivateKey, Txid};
+
+    fn spend(witness: Vec<Vec<u8>>) -> TxIn {
+        TxIn {
+            previous_output: OutPoint {
+                txid: Txid::all_zeros(),
+                vout: 0,
+            },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::from_slice(&witness),
+        }
+    }
+
+    #[test]
+    fn finds_paths_without_signatures() {
+        let preimage = [9u8; 32];
+        let secp = secp256k1::Secp256k1::new();
+        let private_key = PrivateKey::from_slice(&[7u8; 32], Network::Regtest).unwrap();
+        let pubkey = private_key.public_key(&secp);
+
+        let lock = HashLock {
+            kind: HashKind::Sha256,
+            hash: HashKind::Sha256.digest(&preimage),
+        };
+        let hash_only = Builder::new()
+            .push_opcode(OP_SHA256)
+            .push_slice(sha256::Hash::hash(&preimage).to_byte_array())
+            .push_opcode(OP_EQUAL)
+            .into_script();
+        assert_eq!(hash_lock_only_paths(&hash_only), vec![lock.clone()]);
+
+        // the preimage path of an htlc also needs a signature
+        let payment_hash = HashKind::Hash160.digest(&preimage);
+        let htlc = Builder::new()
+            .push_opcode(OP_IF)
+            .push_opcode(OP_HASH160)
+            .push_slice(<[u8; 20]>::try_from(payment_hash.as_slice()).unwrap())
+            .push_opcode(OP_EQUALVERIFY)
+            .push_key(&pubkey)
+            .push_opcode(OP_CHECKSIG)
+            .push_opcode(OP_ELSE)
+            .push_int(144)
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .push_key(&pubkey)
+            .push_opcode(OP_CHECKSIG)
+            .push_opcode(OP_ENDIF)
+            .into_script();
+        assert!(hash_lock_only_paths(&htlc).is_empty());
+        assert_eq!(hash_locks(&htlc).len(), 1);
+
+        // a hash lock behind the branch without a signature
+        let escape = Builder::new()
+            .push_opcode(OP_NOTIF)
+            .push_key(&pubkey)
+            .push_opcode(OP_CHECKSIG)
+            .push_opcode(OP_ELSE)
+            .push_opcode(OP_SHA256)
+            .push_slice(sha256::Hash::hash(&preimage).to_byte_array())
+            .push_opcode(OP_EQUAL)
+            .push_opcode(OP_ENDIF)
+            .into_script();
+        assert_eq!(hash_lock_only_paths(&escape), vec![lock.clone()]);
+
+        let input = spend(vec![preimage.to_vec(), vec![1], escape.to_bytes()]);
+        assert_eq!(
+            revealed_preimages(&input),
+            vec![Preimage {
+                lock,
+                preimage: preimage.to_vec()
+            }]
+        );
+        assert_eq!(spent_script(&input).unwrap().1, escape.to_p2wsh());
+    }
+
+    #[test]
+    fn recomputes_the_taproot_output() {
+        let secp = secp256k1::Secp256k1::new();
+        let private_key = PrivateKey::from_slice(&[7u8; 32], Network::Regtest).unwrap();
+        let (internal_key, _) = private_key.inner.x_only_public_key(&secp);
+        let preimage = [3u8; 32];
+        let leaf = Builder::new()
+            .push_opcode(OP_HASH256)
+            .push_slice(sha256d::Hash::hash(&preimage).to_byte_array())
+            .push_opcode(OP_EQUAL)
+            .into_script();
+        let other = Builder::new()
+            .push_x_only_key(&internal_key)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let spend_info = TaprootBuilder::new()
+            .add_leaf(1, leaf.clone())
+            .unwrap()
+            .add_leaf(1, other)
+            .unwrap()
+            .finalize(&secp, internal_key)
+            .unwrap();
+        let control_block = spend_info
+            .control_block(&(leaf.clone(), LeafVersion::TapScript))
+            .unwrap();
+
+        let input = spend(vec![
+            preimage.to_vec(),
+            leaf.to_bytes(),
+            control_block.serialize(),
+        ]);
+        let (script, script_pubkey) = spent_script(&input).unwrap();
+        assert_eq!(script, leaf);
+        assert_eq!(
+            script_pubkey,
+            ScriptBuf::new_p2tr_tweaked(spend_info.output_key())
+        );
+        assert_eq!(revealed_preimages(&input)[0].lock.kind, HashKind::Hash256);
+
+        // the annex is no part of the leaf spend
+        let annexed = spend(vec![
+            preimage.to_vec(),
+            leaf.to_bytes(),
+            control_block.serialize(),
+            vec![0x50, 1],
+        ]);
+        assert_eq!(spent_script(&annexed), Some((script, script_pubkey)));
+        let key_path = spend(vec![vec![1u8; 64], vec![0x50, 1]]);
+        assert_eq!(spent_script(&key_path), None);
+    }
+
+    #[test]
+    fn skips_witnesses_without_a_script() {
+        let secp = secp256k1::Secp256k1::new();
+        let private_key = PrivateKey::from_slice(&[7u8; 32], Network::Regtest).unwrap();
+        let pubkey = private_key.public_key(&secp);
+
+        let p2wpkh = spend(vec![vec![0x30; 71], pubkey.to_bytes()]);
+        assert_eq!(spent_script(&p2wpkh), None);
+        // a push running past the end is no script
+        let truncated = spend(vec![vec![1], vec![0x4c, 0x20, 1]]);
+        assert_eq!(spent_script(&truncated), None);
+    }
+}
@@ -0,0 +1,486 @@
+use super::*;
+use bitcoin::bip32::{ChildNumber, DerivationPath, Xpriv};
+use bitcoin::{CompressedPublicKey, NetworkKind, PrivateKey};
+use secp256k1::{All, Secp256k1};
+use serde::Deserialize;
+use signer::Signer;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// scripts derived past the persisted index, so outputs paid to a slightly newer
+/// address by another instance are still recognised
+pub const DEFAULT_LOOKAHEAD: u32 = 20;
+
+/// utxo lookup rounds between two lookups of every used address
+pub const RESCAN_EVERY: u32 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Purpose {
+    /// single key taproot, m/86'/coin'/account'
+    #[default]
+    Bip86,
+    /// native segwit, m/84'/coin'/account'
+    Bip84,
+}
+
+impl Purpose {
+    fn index(self) -> u32 {
+        match self {
+            Purpose::Bip86 => 86,
+            Purpose::Bip84 => 84,
+        }
+    }
+}
+
+/// keys and scripts of one BIP86 or BIP84 account
+pub struct Keychain {
+    account_xprv: Xpriv,
+    purpose: Purpose,
+    network: Network,
+    secp: Secp256k1<All>,
+}
+
+impl Keychain {
+    pub fn new(xprv: &str, purpose: Purpose, account: u32, network: Network) -> Result<Self> {
+        let root = Xpriv::from_str(xprv.trim())?;
+        if root.network != NetworkKind::from(network) {
+            bail!("extended key is not for {}", network);
+        }
+        let coin = match network {
+            Network::Bitcoin => 0,
+            _ => 1,
+        };
+        let secp = Secp256k1::new();
+        let account_path = [
+            ChildNumber::from_hardened_idx(purpose.index())?,
+            ChildNumber::from_hardened_idx(coin)?,
+            ChildNumber::from_hardened_idx(account)?,
+        ];
+        Ok(Self {
+            account_xprv: root.derive_priv(&secp, &account_path)?,
+            purpose,
+            network,
+            secp,
+        })
+    }
+
+    /// path below the account, `change` picks the internal chain
+    pub fn path(change: bool, index: u32) -> Result<DerivationPath> {
+        Ok(DerivationPath::from(vec![
+            ChildNumber::from_normal_idx(change as u32)?,
+            ChildNumber::from_normal_idx(index)?,
+        ]))
+    }
+
+    pub fn private_key(&self, change: bool, index: u32) -> Result<PrivateKey> {
+        let path = Self::path(change, index)?;
+        Ok(self.account_xprv.derive_priv(&self.secp, &path)?.to_priv())
+    }
+
+    pub fn address(&self, change: bool, index: u32) -> Result<Address> {
+        let private_key = self.private_key(change, index)?;
+        let address = match self.purpose {
+            Purpose::Bip86 => {
+                let (internal_key, _) = private_key.inner.x_only_public_key(&self.secp);
+                Address::p2tr(&self.secp, internal_key, None, self.network)
+            }
+            Purpose::Bip84 => {
+                let public_key = CompressedPublicKey::from_private_key(&self.secp, &private_key)?;
+                Address::p2wpkh(&public_key, self.network)
+            }
+        };
+        Ok(address)
+    }
+}
+
+/// a keychain plus the next unused receive index, kept in `index_file` so a restart
+/// never hands out an address twice
+pub struct HdWallet {
+    keychain: Keychain,
+    index_file: PathBuf,
+    lookahead: u32,
+    state: Mutex<WalletState>,
+}
+
+#[derive(Default)]
+struct WalletState {
+    next_index: u32,
+    /// derived script to (change, index)
+    scripts: HashMap<ScriptBuf, (bool, u32)>,
+    derived_until: u32,
+    /// receive indexes handed out by `peek_address` and not broadcast yet
+    pending: BTreeSet<u32>,
+}
+
+impl fmt::Debug for HdWallet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HdWallet")
+            .field("index_file", &self.index_file)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HdWallet {
+    pub fn open(
+        keychain: Keychain,
+        index_file: impl Into<PathBuf>,
+        lookahead: u32,
+    ) -> Result<Self> {
+        let wallet = Self {
+            keychain,
+            index_file: index_file.into(),
+            lookahead,
+            state: Mutex::new(WalletState::default()),
+        };
+        wallet.reload()?;
+        Ok(wallet)
+    }
+
+    /// a receive address nobody was given before, the index is persisted first
+    pub fn next_address(&self) -> Result<Address> {
+        let address = self.peek_address()?;
+        self.commit(&address.script_pubkey())?;
+        Ok(address)
+    }
+
+    /// a receive address not given out yet. the index is only persisted by `commit`
+    /// once a transaction paying it is broadcast, `release` hands it out again
+    pub fn peek_address(&self) -> Result<Address> {
+        let mut state = self.lock()?;
+        state.next_index = state.next_index.max(self.read_index()?);
+        let mut index = state.next_index;
+        while state.pending.contains(&index) {
+            index += 1;
+        }
+        state.pending.insert(index);
+        self.derive_until(&mut state, index + 1 + self.lookahead)?;
+        let address = self.keychain.address(false, index)?;
+        info!("peeked receive address {} at index {}", address, index);
+        Ok(address)
+    }
+
+    /// persist the index of a receive script a broadcast transaction pays
+    pub fn commit(&self, script_pubkey: &Script) -> Result<()> {
+        let mut state = self.lock()?;
+        let Some(&(false, index)) = state.scripts.get(script_pubkey) else {
+            return Ok(());
+        };
+        state.pending.remove(&index);
+        let next_index = state.next_index.max(self.read_index()?);
+        if index >= next_index {
+            self.write_index(index + 1)?;
+            info!("committed receive index {}", index);
+        }
+        state.next_index = next_index.max(index + 1);
+        Ok(())
+    }
+
+    /// give a peeked receive script back when its transaction was never broadcast
+    pub fn release(&self, script_pubkey: &Script) {
+        if let Ok(mut state) = self.lock() {
+            if let Some(&(false, index)) = state.scripts.get(script_pubkey) {
+                state.pending.remove(&index);
+            }
+        }
+    }
+
+    /// every receive address handed out so far, where our utxos can be
+    pub fn used_addresses(&self) -> Result<Vec<Address>> {
+        let next_index = self.reload()?;
+        (0..next_index)
+            .map(|index| self.keychain.address(false, index))
+            .collect()
+    }
+
+    /// the key behind a derived script, looked up in the script index
+    pub fn private_key_for(&self, script_pubkey: &Script) -> Result<Option<PrivateKey>> {
+        let mut found = self.lock()?.scripts.get(script_pubkey).copied();
+        if found.is_none() {
+            // another instance may have moved the index on
+            self.reload()?;
+            found = self.lock()?.scripts.get(script_pubkey).copied();
+        }
+        found
+            .map(|(change, index)| self.keychain.private_key(change, index))
+            .transpose()
+    }
+
+    fn reload(&self) -> Result<u32> {
+        let mut state = self.lock()?;
+        state.next_index = state.next_index.max(self.read_index()?);
+        let until = state.next_index + self.lookahead;
+        self.derive_until(&mut state, until)?;
+        Ok(state.next_index)
+    }
+
+    fn derive_until(&self, state: &mut WalletState, until: u32) -> Result<()> {
+        for index in state.derived_until..until {
+            for change in [false, true] {
+                let script_pubkey = self.keychain.address(change, index)?.script_pubkey();
+                state.scripts.insert(script_pubkey, (change, index));
+            }
+        }
+        state.derived_until = state.derived_until.max(until);
+        Ok(())
+    }
+
+    fn read_index(&self) -> Result<u32> {
+        match fs::read_to_string(&self.index_file) {
+            Ok(s) => Ok(s.trim().parse()?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_index(&self, next_index: u32) -> Result<()> {
+        let tmp = self.index_file.with_extension("tmp");
+        fs::write(&tmp, format!("{}\n", next_index))?;
+        fs::rename(&tmp, &self.index_file)?;
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, WalletState>> {
+        self.state
+            .lock()
+            .map_err(|_| anyhow!("hd wallet state is poisoned"))
+    }
+}
+
+/// where sweeps pay to and where our utxos are looked up
+#[derive(Debug, Clone)]
+pub enum Destination {
+    /// one address reused by every sweep
+    Fixed(String),
+    /// a fresh receive address per sweep
+    Fresh(Arc<HdWallet>),
+}
+
+impl Destination {
+    pub fn next_address(&self) -> Result<String> {
+        match self {
+            Destination::Fixed(address) => Ok(address.clone()),
+            Destination::Fresh(wallet) => Ok(wallet.next_address()?.to_string()),
+        }
+    }
+
+    /// the address the next sweep pays, `commit` it once the sweep is broadcast
+    pub fn peek_address(&self) -> Result<String> {
+        match self {
+            Destination::Fixed(address) => Ok(address.clone()),
+            Destination::Fresh(wallet) => Ok(wallet.peek_address()?.to_string()),
+        }
+    }
+
+    pub fn commit(&self, script_pubkey: &Script) -> Result<()> {
+        match self {
+            Destination::Fixed(_) => Ok(()),
+            Destination::Fresh(wallet) => wallet.commit(script_pubkey),
+        }
+    }
+
+    pub fn release(&self, script_pubkey: &Script) {
+        if let Destination::Fresh(wallet) = self {
+            wallet.release(script_pubkey);
+        }
+    }
+
+    pub fn watched_addresses(&self) -> Result<Vec<String>> {
+        match self {
+            Destination::Fixed(address) => Ok(vec![address.clone()]),
+            Destination::Fresh(wallet) => Ok(wallet
+                .used_addresses()?
+                .iter()
+                .map(|address| address.to_string())
+                .collect()),
+        }
+    }
+}
+
+/// picks the watched addresses worth a utxo lookup: those holding funds at their last
+/// lookup and those handed out since. every `RESCAN_EVERY` rounds all of them are looked
+/// up, so a deposit to an emptied address is still found
+#[derive(Debug, Default)]
+pub struct AddressScan {
+    funded: HashSet<String>,
+    /// leading watched addresses looked up at least once
+    scanned: usize,
+    rounds: u32,
+}
+
+impl AddressScan {
+    pub fn due(&mut self, watched: &[String]) -> Vec<String> {
+        let full = self.rounds.is_multiple_of(RESCAN_EVERY);
+        self.rounds = self.rounds.wrapping_add(1);
+        watched
+            .iter()
+            .enumerate()
+            .filter(|(i, address)| full || *i >= self.scanned || self.funded.contains(*address))
+            .map(|(_, address)| address.clone())
+            .collect()
+    }
+
+    /// the lookup of `address` found `funded` utxos
+    pub fn record(&mut self, address: &str, funded: bool) {
+        if funded {
+            self.funded.insert(address.to_string());
+        } else {
+            self.funded.remove(address);
+        }
+    }
+
+    /// every address `due` returned was looked up
+    pub fn finish(&mut self, watched: &[String]) {
+        self.scanned = watched.len();
+    }
+}
+
+impl Signer for HdWallet {
+    /// each input is signed with the key its prevout script was derived from
+    fn sign_tx(
+        &self,
+        tx: Transaction,
+        prevouts: Vec<TxOut>,
+        sign_idx: Vec<usize>,
+    ) -> Result<Transaction> {
+        let mut tx = tx;
+        for idx in sign_idx {
+            let prevout = prevouts
+                .get(idx)
+                .ok_or_else(|| anyhow!("no prevout for input {}", idx))?;
+            let private_key = self
+                .private_key_for(&prevout.script_pubkey)?
+                .ok_or_else(|| anyhow!("input {} does not spend a derived script", idx))?;
+            tx = signer::sign_tx(private_key.to_wif(), tx, prevouts.clone(), vec![idx])?;
+        }
+        Ok(tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datatypes::fixtures;
+
+    // root key of the "abandon ... about" mnemonic used by the BIP84 and BIP86 vectors
+    const XPRV: &str = "xprv9s21ZrQH143K3GJpoapnV8SFfukcVBSfeCficPSGfubmSFDxo1kuHnLisriDvSnRRuL2Qrg5ggqHKNVpxR86QEC8w35uxmGoggxtQTPvfUu";
+
+    #[test]
+    fn matches_bip_vectors() {
+        let bip86 = Keychain::new(XPRV, Purpose::Bip86, 0, Network::Bitcoin).unwrap();
+        assert_eq!(
+            bip86.address(false, 0).unwrap().to_string(),
+            "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+        );
+        assert_eq!(
+            bip86.address(true, 0).unwrap().to_string(),
+            "bc1p3qkhfews2uk44qtvauqyr2ttdsw7svhkl9nkm9s9c3x4ax5h60wqwruhk7"
+        );
+
+        let bip84 = Keychain::new(XPRV, Purpose::Bip84, 0, Network::Bitcoin).unwrap();
+        assert_eq!(
+            bip84.address(false, 0).unwrap().to_string(),
+            "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+        );
+        assert!(Keychain::new(XPRV, Purpose::Bip84, 0, Network::Regtest).is_err());
+    }
+
+    #[test]
+    fn persists_peeked_addresses_only_once_committed() {
+        let index_file = std::env::temp_dir().join(format!("bittx-hd-peek-{}", std::process::id()));
+        let _ = fs::remove_file(&index_file);
+        let keychain = || Keychain::new(XPRV, Purpose::Bip86, 0, Network::Bitcoin).unwrap();
+
+        let wallet = HdWallet::open(keychain(), &index_file, 2).unwrap();
+        let first = wallet.peek_address().unwrap();
+        let second = wallet.peek_address().unwrap();
+        assert_eq!(first, keychain().address(false, 0).unwrap());
+        assert_eq!(second, keychain().address(false, 1).unwrap());
+        assert!(wallet.used_addresses().unwrap().is_empty());
+
+        // the first sweep failed, its address goes to the next one
+        wallet.release(&first.script_pubkey());
+        wallet.commit(&second.script_pubkey()).unwrap();
+        assert_eq!(wallet.used_addresses().unwrap().len(), 2);
+        let restarted = HdWallet::open(keychain(), &index_file, 2).unwrap();
+        assert_eq!(
+            restarted.peek_address().unwrap(),
+            keychain().address(false, 2).unwrap()
+        );
+        fs::remove_file(&index_file).unwrap();
+    }
+
+    #[test]
+    fn looks_up_funded_and_new_addresses_between_rescans() {
+        let watched: Vec<String> = ["a", "b", "c"].map(String::from).to_vec();
+        let mut scan = AddressScan::default();
+        assert_eq!(scan.due(&watched), watched);
+        scan.record("a", false);
+        scan.record("b", true);
+        scan.record("c", false);
+        scan.finish(&watched);
+
+        let mut grown = watched.clone();
+        grown.push("d".to_string());
+        assert_eq!(scan.due(&grown), vec!["b".to_string(), "d".to_string()]);
+        // a failed round looks the new address up again
+        assert_eq!(scan.due(&grown), vec!["b".to_string(), "d".to_string()]);
+        scan.finish(&grown);
+        for _ in 3..RESCAN_EVERY {
+            assert_eq!(scan.due(&grown), vec!["b".to_string()]);
+        }
+        assert_eq!(scan.due(&grown), grown);
+    }
+
+    #[test]
+    fn index_survives_restart_and_signs_derived_inputs() {
+        let index_file =
+            std::env::temp_dir().join(format!("bittx-hd-index-{}", std::process::id()));
+        let _ = fs::remove_file(&index_file);
+        let keychain = || Keychain::new(XPRV, Purpose::Bip86, 0, Network::Bitcoin).unwrap();
+
+        let wallet = HdWallet::open(keychain(), &index_file, 2).unwrap();
+        let first = wallet.next_address().unwrap();
+        let second = wallet.next_address().unwrap();
+        assert_ne!(first, second);
+
+        let restarted = HdWallet::open(keychain(), &index_file, 2).unwrap();
+        let third = restarted.next_address().unwrap();
+        assert_eq!(third, keychain().address(false, 2).unwrap());
+        assert_eq!(
+            restarted.used_addresses().unwrap(),
+            vec![first, second.clone(), third]
+        );
+        // the first wallet picks up the index the restarted one wrote
+        assert_eq!(
+            wallet.next_address().unwrap(),
+            keychain().address(false, 3).unwrap()
+        );
+
+        let utxos: Vec<types::Utxo> = [
+            second.script_pubkey(),
+            keychain().address(true, 1).unwrap().script_pubkey(),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(vout, script_pubkey)| fixtures::utxo(&script_pubkey, vout as u32, 10_000))
+        .collect();
+        let outputs = vec![TxOut {
+            value: Amount::from_sat(19_000),
+            script_pubkey: second.script_pubkey(),
+        }];
+        let (tx, prevouts) = builder::base::assemble_tx(&utxos, outputs);
+        let signed = wallet
+            .sign_tx(tx.clone(), prevouts.clone(), vec![0, 1])
+            .unwrap();
+        assert!(signed.input.iter().all(|input| input.witness.len() == 1));
+
+        let mut foreign = prevouts;
+        foreign[0].script_pubkey = keychain().address(false, 100).unwrap().script_pubkey();
+        assert!(wallet.sign_tx(tx, foreign, vec![0]).is_err());
+        fs::remove_file(&index_file).unwrap();
+    }
+}
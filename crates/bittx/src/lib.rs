@@ -14,10 +14,22 @@ use tracing::info;
 
 pub mod build_helper;
 pub mod builder;
+pub mod classifier;
+pub mod coin_select;
+pub mod error;
+pub mod fee_policy;
 pub mod fee_rate;
+pub mod hash_lock;
+pub mod hd;
 pub mod lightning;
+pub mod policy;
+pub mod psbt;
+pub mod replacement;
+pub mod sighash;
 pub mod signer;
+pub mod verify;
 pub mod vsize;
+pub mod weight;
 pub mod witness;
 
 const SCHNORR_SIGNATURE_SIZE: usize = 64;
@@ -1,74 +1,273 @@
-use builder::anchor::build_anchor_redeem_script;
+use bitcoin::opcodes::{Class, ClassifyContext};
+use bitcoin::OutPoint;
+use builder::anchor::{build_anchor_redeem_script, taproot_anchor_script_pubkey};
+use secp256k1::XOnlyPublicKey;
+use tracing::debug;
 use types::AnchorUnlockDetail;
 
 use super::*;
 
-// this is the simple implementation
+/// upper byte of nLockTime in a commitment transaction
+const COMMITMENT_LOCKTIME_PREFIX: u32 = 0x20;
+/// upper byte of nSequence of the funding input in a commitment transaction
+const COMMITMENT_SEQUENCE_PREFIX: u32 = 0x80;
+const ANCHOR_VALUE: u64 = 330;
+
+/// the two anchors of a commitment transaction, a channel without anchors is not reported
 pub fn check_lightning_channel_close(tx: &Transaction) -> Option<types::AnchorUnlockInfo> {
-    if tx.input.len() != 1 {
-        return None;
-    }
+    obscured_commitment_number(tx)?;
+    let funding = is_multisig_2_of_2(&tx.input[0].witness)?;
+    let anchors = [&funding.unlock1, &funding.unlock2]
+        .map(|pubkey| build_anchor_redeem_script(pubkey).to_p2wsh());
+    tx.output
+        .iter()
+        .any(|out| anchors.contains(&out.script_pubkey))
+        .then_some(funding)
+}
 
-    if tx.output.len() < 2 {
+/// the anchors of a commitment transaction, `scripts` label its other outputs in the log
+pub fn check_lightning_channel_closed(
+    tx: &Transaction,
+    scripts: &[ScriptBuf],
+) -> Result<Vec<types::AnchorUnlockDetail>> {
+    let report =
+        decode_commitment(tx, scripts).ok_or_else(|| anyhow!("not a commitment transaction"))?;
+    info!("found commitment transaction : {:?}", report);
+    let anchor_details = report
+        .anchors()
+        .map(|anchor| AnchorUnlockDetail {
+            redeem_script_hex: anchor.redeem_script_hex.clone().unwrap_or_default(),
+            out_value: anchor.value,
+            nsequence: 16,
+            vout: anchor.vout,
+        })
+        .collect();
+    Ok(anchor_details)
+}
+
+/// the obscured commitment number, when nLockTime and nSequence carry the BOLT3 prefixes
+pub fn obscured_commitment_number(tx: &Transaction) -> Option<u64> {
+    let [input] = tx.input.as_slice() else {
+        return None;
+    };
+    let locktime = tx.lock_time.to_consensus_u32();
+    let sequence = input.sequence.to_consensus_u32();
+    if locktime >> 24 != COMMITMENT_LOCKTIME_PREFIX || sequence >> 24 != COMMITMENT_SEQUENCE_PREFIX
+    {
         return None;
     }
 
-    // must be include two 330 output
-    let mut count_of_330 = 0;
-    for out in &tx.output {
-        if out.value.to_sat() == 330 {
-            count_of_330 += 1;
-        }
-    }
+    Some(u64::from(sequence & 0xffffff) << 24 | u64::from(locktime & 0xffffff))
+}
 
-    if count_of_330 < 2 {
-        return None;
+/// decode a commitment transaction spending a 2-of-2 funding output.
+///
+/// anchors follow from the funding keys, to_local, to_remote and htlc outputs are p2wsh
+/// and only labeled when their witness script is among `scripts`, say from a later spend
+pub fn decode_commitment(
+    tx: &Transaction,
+    scripts: &[ScriptBuf],
+) -> Option<types::ChannelCloseReport> {
+    let obscured_commitment_number = obscured_commitment_number(tx)?;
+    let input = &tx.input[0];
+    let funding = is_multisig_2_of_2(&input.witness)?;
+    let anchors: Vec<(ScriptBuf, &Vec<u8>)> = [&funding.unlock1, &funding.unlock2]
+        .into_iter()
+        .map(|pubkey| (build_anchor_redeem_script(pubkey), pubkey))
+        .collect();
+
+    let outputs = tx
+        .output
+        .iter()
+        .enumerate()
+        .map(|(vout, out)| {
+            let anchor = anchors
+                .iter()
+                .find(|(script, _)| out.script_pubkey == script.to_p2wsh());
+            let known = scripts
+                .iter()
+                .find(|script| out.script_pubkey == script.to_p2wsh());
+            let (kind, script) = if let Some((script, pubkey)) = anchor {
+                let kind = types::CommitmentOutputKind::Anchor {
+                    funding_pubkey: pubkey.to_vec(),
+                };
+                (kind, Some(script))
+            } else if out.script_pubkey.is_p2wpkh() {
+                // static_remotekey channels without anchors pay to_remote to a plain key
+                (types::CommitmentOutputKind::ToRemote, None)
+            } else if let Some(script) = known {
+                let kind =
+                    commitment_script_kind(script).unwrap_or(types::CommitmentOutputKind::Unknown);
+                (kind, Some(script))
+            } else {
+                (types::CommitmentOutputKind::Unknown, None)
+            };
+            if matches!(kind, types::CommitmentOutputKind::Anchor { .. })
+                && out.value.to_sat() != ANCHOR_VALUE
+            {
+                debug!(
+                    "anchor {} of {} pays {}",
+                    vout,
+                    tx.compute_txid(),
+                    out.value
+                );
+            }
+            types::CommitmentOutput {
+                vout: vout as u32,
+                value: out.value.to_sat(),
+                kind,
+                redeem_script_hex: script.map(|script| script.to_hex_string()),
+            }
+        })
+        .collect();
+
+    Some(types::ChannelCloseReport {
+        txid: tx.compute_txid().to_string(),
+        funding_outpoint: input.previous_output,
+        funding_pubkeys: [funding.unlock1, funding.unlock2],
+        obscured_commitment_number,
+        outputs,
+    })
+}
+
+/// label a BOLT3 commitment output script by its shape, keys and numbers aside
+pub fn commitment_script_kind(script: &Script) -> Option<types::CommitmentOutputKind> {
+    let tokens: Vec<String> = script
+        .instructions()
+        .map(|instruction| {
+            instruction.map(|instruction| match instruction {
+                Instruction::PushBytes(bytes) if bytes.len() == 33 => "key".to_string(),
+                Instruction::PushBytes(bytes) if bytes.len() == 20 => "h20".to_string(),
+                Instruction::PushBytes(bytes) if bytes.len() <= 4 => "num".to_string(),
+                Instruction::PushBytes(bytes) => format!("<{}>", bytes.len()),
+                Instruction::Op(op) => match op.classify(ClassifyContext::Legacy) {
+                    Class::PushNum(_) => "num".to_string(),
+                    _ => format!("{:?}", op),
+                },
+            })
+        })
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+
+    const TO_LOCAL: &[&str] = &[
+        "OP_IF",
+        "key",
+        "OP_ELSE",
+        "num",
+        "OP_CSV",
+        "OP_DROP",
+        "key",
+        "OP_ENDIF",
+        "OP_CHECKSIG",
+    ];
+    const TO_REMOTE: &[&str] = &["key", "OP_CHECKSIGVERIFY", "num", "OP_CSV"];
+    const HTLC: &[&str] = &[
+        "OP_DUP",
+        "OP_HASH160",
+        "h20",
+        "OP_EQUAL",
+        "OP_IF",
+        "OP_CHECKSIG",
+        "OP_ELSE",
+        "key",
+        "OP_SWAP",
+        "OP_SIZE",
+        "num",
+        "OP_EQUAL",
+    ];
+    if tokens == TO_LOCAL {
+        Some(types::CommitmentOutputKind::ToLocal)
+    } else if tokens == TO_REMOTE {
+        Some(types::CommitmentOutputKind::ToRemote)
+    } else if tokens.starts_with(HTLC) {
+        match tokens.get(HTLC.len()) {
+            Some(&"OP_NOTIF") => Some(types::CommitmentOutputKind::Htlc { offered: true }),
+            Some(&"OP_IF") => Some(types::CommitmentOutputKind::Htlc { offered: false }),
+            _ => None,
+        }
+    } else {
+        None
     }
+}
 
-    let in_witness = &tx.input[0].witness;
-    is_multisig_2_of_2(in_witness)
+/// the BOLT3 witness scripts `tx` reveals sweeping p2wsh commitment outputs, by the
+/// output they spend. these label the outputs once the commitment is decoded again
+pub fn commitment_witness_scripts(
+    tx: &Transaction,
+) -> Vec<(OutPoint, ScriptBuf, types::CommitmentOutputKind)> {
+    tx.input
+        .iter()
+        .filter_map(|input| {
+            let script = input.witness.witness_script()?;
+            let kind = commitment_script_kind(script)?;
+            Some((input.previous_output, script.to_owned(), kind))
+        })
+        .collect()
 }
 
-pub fn check_lightning_channel_closed(tx: &Transaction) -> Result<Vec<types::AnchorUnlockDetail>> {
-    if tx.input.len() != 1 {
-        return Err(anyhow!("tx input len must be 1"));
+/// decode a simple taproot channel commitment. the musig2 funding output is spent by key
+/// path and every output is p2tr, the 330 sat ones are the anchors
+pub fn decode_taproot_commitment(tx: &Transaction) -> Option<types::TaprootChannelClose> {
+    let obscured_commitment_number = obscured_commitment_number(tx)?;
+    let input = &tx.input[0];
+    match input.witness.iter().collect::<Vec<_>>().as_slice() {
+        [signature] if signature.len() == SCHNORR_SIGNATURE_SIZE => {}
+        _ => return None,
+    }
+    if !tx.output.iter().all(|out| out.script_pubkey.is_p2tr()) {
+        return None;
     }
 
-    if tx.output.len() < 2 {
-        return Err(anyhow!("tx output len must more then 2"));
+    let anchors: Vec<types::TaprootAnchor> = tx
+        .output
+        .iter()
+        .enumerate()
+        .filter(|(_, out)| out.value.to_sat() == ANCHOR_VALUE)
+        .map(|(vout, out)| types::TaprootAnchor {
+            vout: vout as u32,
+            value: out.value.to_sat(),
+            script_pubkey: out.script_pubkey.clone(),
+        })
+        .collect();
+    if anchors.is_empty() || anchors.len() > 2 {
+        return None;
     }
 
-    let in_witness = &tx.input[0].witness;
-    match is_multisig_2_of_2(in_witness) {
-        Some(multi_sign) => {
-            info!("found multi-sign of 2-2 : {:?}", multi_sign);
-            let redeem_script1 = build_anchor_redeem_script(&multi_sign.unlock1);
-            let redeem_script2 = build_anchor_redeem_script(&multi_sign.unlock1);
-            let redeem_scripts = [redeem_script1, redeem_script2];
-            let mut anchor_details = vec![];
-            for (idx, out) in tx.output.iter().enumerate() {
-                let mut redeem_script_hex = None;
-                if out.script_pubkey == redeem_scripts[0].to_p2wsh() {
-                    redeem_script_hex = Some(redeem_scripts[0].to_hex_string());
-                } else if out.script_pubkey == redeem_scripts[1].to_p2wsh() {
-                    redeem_script_hex = Some(redeem_scripts[1].to_hex_string());
-                }
-
-                if let Some(redeem_script_hex) = redeem_script_hex {
-                    let detail = AnchorUnlockDetail {
-                        redeem_script_hex,
-                        out_value: out.value.to_sat(),
-                        nsequence: 16,
-                        vout: idx as u32,
-                    };
-                    anchor_details.push(detail);
-                }
+    Some(types::TaprootChannelClose {
+        txid: tx.compute_txid().to_string(),
+        funding_outpoint: input.previous_output,
+        obscured_commitment_number,
+        anchors,
+    })
+}
+
+/// the x-only keys pushed by the leaf of a tapscript spend. sweeping to_local or to_remote
+/// of a taproot channel reveals the internal keys of its anchors this way
+pub fn revealed_anchor_keys(input: &TxIn) -> Vec<XOnlyPublicKey> {
+    let Some(spend) = witness::parse_tapscript_spend(&input.witness, None) else {
+        return vec![];
+    };
+    spend
+        .leaf
+        .instructions()
+        .filter_map(|instruction| match instruction {
+            Ok(Instruction::PushBytes(bytes)) if bytes.len() == 32 => {
+                XOnlyPublicKey::from_slice(bytes.as_bytes()).ok()
             }
+            _ => None,
+        })
+        .collect()
+}
 
-            Ok(anchor_details)
-        }
-        None => Err(anyhow!("not 2-2 multisig")),
-    }
+/// the candidate which is the internal key of the taproot anchor paying to `script_pubkey`
+pub fn taproot_anchor_key(
+    script_pubkey: &Script,
+    candidates: &[XOnlyPublicKey],
+) -> Option<XOnlyPublicKey> {
+    candidates.iter().copied().find(|key| {
+        taproot_anchor_script_pubkey(*key).is_ok_and(|anchor| anchor.as_script() == script_pubkey)
+    })
 }
 
 // 2 <pubkey1> <pubkey2> 2 OP_CHECKMULTISIG
@@ -119,6 +318,24 @@ mod tests {
     use bitcoin::blockdata::transaction::Transaction;
     use bitcoin::consensus::encode::deserialize_hex;
 
+    fn to_local_script() -> ScriptBuf {
+        use bitcoin::opcodes::all::*;
+        use bitcoin::script::Builder;
+
+        let key = [2u8; 33];
+        Builder::new()
+            .push_opcode(OP_IF)
+            .push_slice(key)
+            .push_opcode(OP_ELSE)
+            .push_int(144)
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .push_slice(key)
+            .push_opcode(OP_ENDIF)
+            .push_opcode(OP_CHECKSIG)
+            .into_script()
+    }
+
     #[test]
     fn test_check_sniper_lightning() {
         let raw_tx = "0200000000010199737cff512e7207367804a536173cfbd11633feac0241283d3e8e8570f558ba0100000000b8e9b080044a010000000000002200202352053e1cd0b5f360d93bd39f324ac81ba82b9028252f2b02e9c468b9ba26f84a010000000000002200207535509faff2b5feb747ab8bb8eb12560c2f151a5ace5fe612526a8ca05f1febfc780200000000002200204ba3a03f6d2977476fa238320b2357d81f62ccba6caa104b456172af526612ca239b030000000000220020733a1726c25def1cb9b994c13f95cbe86d3cc48678edc88267bbba61426b173c040047304402207f3f9115b5484b8ebab72e4771ac8952575bd1ba466430dbe61e9b97429a4f2f022074fd8e526c9d94e5298f705db59b49ff9685ca998cca0687ff4a45db7aad6e4101483045022100cea8fabab14cea2a8d99ba3af21d8fc32d4504caeb7349b1b15a2ddd40febaf602200b197832477e13d669d049a54d4e579c942b4f3947bd01f59b11cece38a9dd9901475221024920e2293b862c6eeae69667af2654d0a31c36b0066a91d9b3a86994d3a910d62103a4a513fb72a6e352f0e42886cfaa7bbb433b690c687e791f718e4818c95210c552ae779bd520";
@@ -129,12 +346,242 @@ mod tests {
         assert!(result.is_some());
         let unlock_info: types::AnchorUnlockInfo = result.unwrap();
         assert_eq!(
-            hex::encode(unlock_info.unlock1),
+            hex::encode(&unlock_info.unlock1),
             "024920e2293b862c6eeae69667af2654d0a31c36b0066a91d9b3a86994d3a910d6"
         );
         assert_eq!(
             hex::encode(unlock_info.unlock2),
             "03a4a513fb72a6e352f0e42886cfaa7bbb433b690c687e791f718e4818c95210c5"
         );
+
+        let anchor_script = build_anchor_redeem_script(&unlock_info.unlock1);
+        assert_eq!(anchor_script.len(), 40);
+        assert_eq!(tx.output[1].script_pubkey, anchor_script.to_p2wsh());
+    }
+
+    #[test]
+    fn decodes_commitment_outputs() {
+        let raw_tx = "0200000000010199737cff512e7207367804a536173cfbd11633feac0241283d3e8e8570f558ba0100000000b8e9b080044a010000000000002200202352053e1cd0b5f360d93bd39f324ac81ba82b9028252f2b02e9c468b9ba26f84a010000000000002200207535509faff2b5feb747ab8bb8eb12560c2f151a5ace5fe612526a8ca05f1febfc780200000000002200204ba3a03f6d2977476fa238320b2357d81f62ccba6caa104b456172af526612ca239b030000000000220020733a1726c25def1cb9b994c13f95cbe86d3cc48678edc88267bbba61426b173c040047304402207f3f9115b5484b8ebab72e4771ac8952575bd1ba466430dbe61e9b97429a4f2f022074fd8e526c9d94e5298f705db59b49ff9685ca998cca0687ff4a45db7aad6e4101483045022100cea8fabab14cea2a8d99ba3af21d8fc32d4504caeb7349b1b15a2ddd40febaf602200b197832477e13d669d049a54d4e579c942b4f3947bd01f59b11cece38a9dd9901475221024920e2293b862c6eeae69667af2654d0a31c36b0066a91d9b3a86994d3a910d62103a4a513fb72a6e352f0e42886cfaa7bbb433b690c687e791f718e4818c95210c552ae779bd520";
+        let tx = deserialize_hex::<Transaction>(raw_tx).unwrap();
+
+        let report = decode_commitment(&tx, &[]).unwrap();
+        assert_eq!(report.obscured_commitment_number, 0xb0e9b8d59b77);
+        let anchors: Vec<u32> = report.anchors().map(|anchor| anchor.vout).collect();
+        assert_eq!(anchors, vec![0, 1]);
+        // both funding keys get their anchor
+        assert_eq!(check_lightning_channel_closed(&tx, &[]).unwrap().len(), 2);
+        assert_eq!(report.outputs[2].kind, types::CommitmentOutputKind::Unknown);
+
+        // the to_local script once a sweep revealed it
+        let to_local = to_local_script();
+        let mut labelled = tx.clone();
+        labelled.output[2].script_pubkey = to_local.to_p2wsh();
+        let sweep = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(labelled.compute_txid(), 2),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence(144),
+                witness: Witness::from_slice(&[vec![1u8; 71], vec![], to_local.to_bytes()]),
+            }],
+            output: vec![],
+        };
+        let revealed = commitment_witness_scripts(&sweep);
+        assert_eq!(
+            revealed,
+            vec![(
+                sweep.input[0].previous_output,
+                to_local.clone(),
+                types::CommitmentOutputKind::ToLocal
+            )]
+        );
+        let report = decode_commitment(&labelled, std::slice::from_ref(&to_local)).unwrap();
+        assert_eq!(report.outputs[2].kind, types::CommitmentOutputKind::ToLocal);
+        assert_eq!(
+            report.outputs[2].redeem_script_hex,
+            Some(to_local.to_hex_string())
+        );
+        assert_eq!(report.outputs[3].kind, types::CommitmentOutputKind::Unknown);
+        assert_eq!(
+            check_lightning_channel_closed(&labelled, &[to_local])
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let mut not_commitment = tx.clone();
+        not_commitment.lock_time = LockTime::ZERO;
+        assert!(decode_commitment(&not_commitment, &[]).is_none());
+    }
+
+    #[test]
+    fn labels_commitment_scripts() {
+        use bitcoin::opcodes::all::*;
+        use bitcoin::script::Builder;
+
+        let key = [2u8; 33];
+        let hash = [1u8; 20];
+        let to_local = to_local_script();
+        let to_remote = Builder::new()
+            .push_slice(key)
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .push_int(1)
+            .push_opcode(OP_CSV)
+            .into_script();
+        let htlc_prefix = |builder: Builder| {
+            builder
+                .push_opcode(OP_DUP)
+                .push_opcode(OP_HASH160)
+                .push_slice(hash)
+                .push_opcode(OP_EQUAL)
+                .push_opcode(OP_IF)
+                .push_opcode(OP_CHECKSIG)
+                .push_opcode(OP_ELSE)
+                .push_slice(key)
+                .push_opcode(OP_SWAP)
+                .push_opcode(OP_SIZE)
+                .push_int(32)
+                .push_opcode(OP_EQUAL)
+        };
+        let offered = htlc_prefix(Builder::new())
+            .push_opcode(OP_NOTIF)
+            .push_opcode(OP_DROP)
+            .push_int(2)
+            .push_opcode(OP_SWAP)
+            .push_slice(key)
+            .push_int(2)
+            .push_opcode(OP_CHECKMULTISIG)
+            .push_opcode(OP_ELSE)
+            .push_opcode(OP_HASH160)
+            .push_slice(hash)
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_CHECKSIG)
+            .push_opcode(OP_ENDIF)
+            .push_int(1)
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .push_opcode(OP_ENDIF)
+            .into_script();
+        let received = htlc_prefix(Builder::new()).push_opcode(OP_IF).into_script();
+
+        assert_eq!(
+            commitment_script_kind(&to_local),
+            Some(types::CommitmentOutputKind::ToLocal)
+        );
+        assert_eq!(
+            commitment_script_kind(&to_remote),
+            Some(types::CommitmentOutputKind::ToRemote)
+        );
+        assert_eq!(
+            commitment_script_kind(&offered),
+            Some(types::CommitmentOutputKind::Htlc { offered: true })
+        );
+        assert_eq!(
+            commitment_script_kind(&received),
+            Some(types::CommitmentOutputKind::Htlc { offered: false })
+        );
+        assert_eq!(
+            commitment_script_kind(&build_anchor_redeem_script(&key.to_vec())),
+            None
+        );
+    }
+
+    #[test]
+    fn finds_taproot_channel_anchors() {
+        use bitcoin::opcodes::all::*;
+        use bitcoin::script::Builder;
+        use bitcoin::taproot::{LeafVersion, TaprootBuilder};
+        use bitcoin::Txid;
+        use builder::anchor::build_taproot_anchor_witness;
+        use secp256k1::{Secp256k1, SecretKey};
+
+        let secp = Secp256k1::new();
+        let key = |byte: u8| {
+            SecretKey::from_slice(&[byte; 32])
+                .unwrap()
+                .x_only_public_key(&secp)
+                .0
+        };
+        let (local_delayed, remote, nums) = (key(1), key(2), key(3));
+        let p2tr = |value: u64, script_pubkey: ScriptBuf| TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey,
+        };
+        let commitment = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::from_consensus(0x2000_0001),
+            input: vec![TxIn {
+                previous_output: OutPoint {
+                    txid: Txid::from_str(
+                        "0000000000000000000000000000000000000000000000000000000000000001",
+                    )
+                    .unwrap(),
+                    vout: 0,
+                },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence(0x8000_0002),
+                witness: Witness::from_slice(&[vec![1u8; 64]]),
+            }],
+            output: vec![
+                p2tr(330, taproot_anchor_script_pubkey(local_delayed).unwrap()),
+                p2tr(330, taproot_anchor_script_pubkey(remote).unwrap()),
+                p2tr(50_000, ScriptBuf::new_p2tr(&secp, nums, None)),
+            ],
+        };
+
+        let close = decode_taproot_commitment(&commitment).unwrap();
+        assert_eq!(close.obscured_commitment_number, 0x02_000001);
+        let anchors: Vec<u32> = close.anchors.iter().map(|anchor| anchor.vout).collect();
+        assert_eq!(anchors, vec![0, 1]);
+        // the v0 decoder does not know them
+        assert!(decode_commitment(&commitment, &[]).is_none());
+
+        // the to_remote sweep names the remote key in its leaf
+        let to_remote = Builder::new()
+            .push_x_only_key(&remote)
+            .push_opcode(OP_CHECKSIG)
+            .push_int(1)
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .into_script();
+        let control_block = TaprootBuilder::new()
+            .add_leaf(0, to_remote.clone())
+            .unwrap()
+            .finalize(&secp, nums)
+            .unwrap()
+            .control_block(&(to_remote.clone(), LeafVersion::TapScript))
+            .unwrap();
+        let sweep = TxIn {
+            witness: Witness::from_slice(&[
+                vec![1u8; 64],
+                to_remote.to_bytes(),
+                control_block.serialize(),
+            ]),
+            ..commitment.input[0].clone()
+        };
+        let revealed = revealed_anchor_keys(&sweep);
+        assert_eq!(revealed, vec![remote]);
+        assert_eq!(
+            taproot_anchor_key(&close.anchors[1].script_pubkey, &revealed),
+            Some(remote)
+        );
+        assert_eq!(
+            taproot_anchor_key(&close.anchors[0].script_pubkey, &revealed),
+            None
+        );
+
+        // anyone can then spend the anchor through its only leaf
+        let witness = build_taproot_anchor_witness(remote).unwrap();
+        let spend = witness::parse_tapscript_spend(&witness, Some(&commitment.output[1])).unwrap();
+        assert!(spend.commits_to(&commitment.output[1]));
+        let anchor_sweep = TxIn {
+            witness,
+            ..commitment.input[0].clone()
+        };
+        let label = classifier::WitnessClassifier::new()
+            .classify(&anchor_sweep, Some(&commitment.output[1]));
+        assert!(label.is_unsigned());
+        assert_eq!(label.template, Some("lightning_anchor"));
     }
 }
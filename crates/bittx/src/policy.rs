@@ -0,0 +1,163 @@
+use super::*;
+use error::BuildError;
+
+/// bitcoin core relays at most 80 bytes of data in an op_return output
+pub const MAX_OP_RETURN_DATA: usize = 80;
+
+/// bitcoin core relays no transaction heavier than this
+pub const MAX_STANDARD_TX_WEIGHT: usize = 400_000;
+
+/// TRUC (BIP 431) transactions opt into the topology restricted relay policy by version
+pub const TRUC_VERSION: Version = Version(3);
+pub const TRUC_MAX_VSIZE: usize = 10_000;
+/// a TRUC transaction with an unconfirmed TRUC parent
+pub const TRUC_CHILD_MAX_VSIZE: usize = 1_000;
+
+/// the version builders give their transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TxVersion {
+    #[default]
+    Standard,
+    /// version 3, relayed under the BIP 431 topology limits and always replaceable
+    Truc,
+}
+
+impl TxVersion {
+    pub fn version(&self) -> Version {
+        match self {
+            TxVersion::Standard => Version::TWO,
+            TxVersion::Truc => TRUC_VERSION,
+        }
+    }
+
+    /// the heaviest transaction of this version relayed without unconfirmed ancestors
+    pub fn max_weight(&self) -> usize {
+        match self {
+            TxVersion::Standard => MAX_STANDARD_TX_WEIGHT,
+            TxVersion::Truc => TRUC_MAX_VSIZE * 4,
+        }
+    }
+}
+
+/// the BIP 431 limits for `tx` spending outputs of `unconfirmed_ancestors` mempool
+/// transactions, other versions pass
+pub fn check_truc(
+    tx: &Transaction,
+    prevouts: &[TxOut],
+    unconfirmed_ancestors: usize,
+) -> Result<(), BuildError> {
+    if tx.version != TRUC_VERSION {
+        return Ok(());
+    }
+    if unconfirmed_ancestors > 1 {
+        return Err(BuildError::TrucAncestors(unconfirmed_ancestors));
+    }
+
+    let vsize = weight::signed_vsize(tx, prevouts);
+    let max = if unconfirmed_ancestors == 1 {
+        TRUC_CHILD_MAX_VSIZE
+    } else {
+        TRUC_MAX_VSIZE
+    };
+    if vsize > max {
+        return Err(BuildError::TrucTooLarge { vsize, max });
+    }
+    Ok(())
+}
+
+/// per script type dust limits, the defaults are bitcoin core's at a 3 sat/vB dust relay fee
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DustThreshold {
+    pub p2pkh: Amount,
+    pub p2sh: Amount,
+    pub p2wpkh: Amount,
+    pub p2wsh: Amount,
+    pub p2tr: Amount,
+}
+
+impl Default for DustThreshold {
+    fn default() -> Self {
+        Self {
+            p2pkh: Amount::from_sat(546),
+            p2sh: Amount::from_sat(540),
+            p2wpkh: Amount::from_sat(294),
+            p2wsh: Amount::from_sat(330),
+            p2tr: Amount::from_sat(330),
+        }
+    }
+}
+
+impl DustThreshold {
+    pub fn for_script(&self, script_pubkey: &Script) -> Amount {
+        if script_pubkey.is_op_return() {
+            Amount::ZERO
+        } else if script_pubkey.is_p2tr() {
+            self.p2tr
+        } else if script_pubkey.is_p2wsh() {
+            self.p2wsh
+        } else if script_pubkey.is_p2wpkh() {
+            self.p2wpkh
+        } else if script_pubkey.is_p2sh() {
+            self.p2sh
+        } else if script_pubkey.is_p2pkh() {
+            self.p2pkh
+        } else {
+            script_pubkey.minimal_non_dust()
+        }
+    }
+
+    pub fn is_dust(&self, out: &TxOut) -> bool {
+        out.value < self.for_script(&out.script_pubkey)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datatypes::fixtures;
+
+    #[test]
+    fn default_matches_core() {
+        let dust = DustThreshold::default();
+        for hex in [
+            "76a914000000000000000000000000000000000000000088ac",
+            "a914000000000000000000000000000000000000000087",
+            "00140000000000000000000000000000000000000000",
+            "00200000000000000000000000000000000000000000000000000000000000000000",
+            "51200000000000000000000000000000000000000000000000000000000000000000",
+        ] {
+            let script = ScriptBuf::from_hex(hex).unwrap();
+            assert_eq!(dust.for_script(&script), script.minimal_non_dust());
+        }
+    }
+
+    #[test]
+    fn truc_limits() {
+        let script_pubkey =
+            ScriptBuf::from_hex("00140000000000000000000000000000000000000000").unwrap();
+        let utxos: Vec<types::Utxo> = (0..20)
+            .map(|vout| fixtures::utxo(&script_pubkey, vout, 10_000))
+            .collect();
+        let outputs = vec![TxOut {
+            value: Amount::from_sat(1_000),
+            script_pubkey,
+        }];
+        let (mut tx, prevouts) = builder::base::assemble_tx(&utxos, outputs);
+        // v2 has no such limits
+        assert!(check_truc(&tx, &prevouts, 3).is_ok());
+
+        tx.version = TxVersion::Truc.version();
+        assert!(check_truc(&tx, &prevouts, 0).is_ok());
+        assert_eq!(
+            check_truc(&tx, &prevouts, 1),
+            Err(BuildError::TrucTooLarge {
+                vsize: weight::signed_vsize(&tx, &prevouts),
+                max: TRUC_CHILD_MAX_VSIZE
+            })
+        );
+        assert_eq!(
+            check_truc(&tx, &prevouts, 2),
+            Err(BuildError::TrucAncestors(2))
+        );
+    }
+}
@@ -0,0 +1,366 @@
+use super::*;
+use bitcoin::psbt::{Input, Psbt, PsbtSighashType};
+use bitcoin::taproot::TaprootSpendInfo;
+use bitcoin::{Txid, XOnlyPublicKey};
+use secp256k1::Secp256k1;
+
+/// wrap a builder's unsigned transaction and prevouts into a psbt.
+/// inputs the builder already filled a script_sig or witness for (anchors, foreign
+/// unsigned inputs) are exported as finalized so other tools leave them alone.
+/// legacy inputs carry their funding tx from `prev_txs` as non_witness_utxo, the
+/// others their prevout as witness_utxo
+pub fn from_tx(tx: Transaction, prevouts: &[TxOut], prev_txs: &[Transaction]) -> Result<Psbt> {
+    if tx.input.len() != prevouts.len() {
+        bail!(
+            "tx has {} inputs but {} prevouts",
+            tx.input.len(),
+            prevouts.len()
+        );
+    }
+
+    let mut unsigned_tx = tx;
+    let mut satisfactions = vec![];
+    for input in unsigned_tx.input.iter_mut() {
+        satisfactions.push((
+            std::mem::take(&mut input.script_sig),
+            std::mem::take(&mut input.witness),
+        ));
+    }
+
+    let mut psbt = Psbt::from_unsigned_tx(unsigned_tx)?;
+    for (idx, ((input, prevout), (script_sig, witness))) in psbt
+        .inputs
+        .iter_mut()
+        .zip(prevouts)
+        .zip(satisfactions)
+        .enumerate()
+    {
+        if is_legacy(&prevout.script_pubkey) {
+            let out_point = psbt.unsigned_tx.input[idx].previous_output;
+            let prev_tx = prev_txs
+                .iter()
+                .find(|tx| {
+                    tx.compute_txid() == out_point.txid
+                        && tx.output.get(out_point.vout as usize) == Some(prevout)
+                })
+                .ok_or_else(|| anyhow!("no funding tx for legacy input {}", idx))?;
+            input.non_witness_utxo = Some(prev_tx.clone());
+        } else {
+            input.witness_utxo = Some(prevout.clone());
+        }
+        if witness.is_empty() && script_sig.is_empty() {
+            input.sighash_type = Some(sighash_type(
+                &prevout.script_pubkey,
+                signer::SighashFlag::All,
+            ));
+            continue;
+        }
+
+        if !script_sig.is_empty() {
+            input.final_script_sig = Some(script_sig);
+        }
+        if witness.is_empty() {
+            continue;
+        }
+        if prevout.script_pubkey.is_p2wsh() {
+            input.witness_script = witness.last().map(|s| ScriptBuf::from_bytes(s.to_vec()));
+        }
+        input.final_script_witness = Some(witness);
+    }
+
+    Ok(psbt)
+}
+
+/// import a base64 psbt, every input needs its prevout so taproot sighashes can be
+/// computed: a witness_utxo, or the non_witness_utxo legacy inputs get from bitcoin core
+pub fn from_base64(s: &str) -> Result<Psbt> {
+    let psbt = Psbt::from_str(s.trim())?;
+    prevouts(&psbt)?;
+    Ok(psbt)
+}
+
+/// record `internal_key` on the taproot key-spend inputs and outputs it controls
+pub fn set_tap_internal_key(psbt: &mut Psbt, internal_key: XOnlyPublicKey) {
+    let secp = Secp256k1::verification_only();
+    let script_pubkey = ScriptBuf::new_p2tr(&secp, internal_key, None);
+
+    for input in psbt.inputs.iter_mut() {
+        let owned = input
+            .witness_utxo
+            .as_ref()
+            .is_some_and(|out| out.script_pubkey == script_pubkey);
+        if owned && !is_finalized(input) {
+            input.tap_internal_key = Some(internal_key);
+        }
+    }
+
+    for (output, txout) in psbt.outputs.iter_mut().zip(&psbt.unsigned_tx.output) {
+        if txout.script_pubkey == script_pubkey {
+            output.tap_internal_key = Some(internal_key);
+        }
+    }
+}
+
+/// record the internal keys of the taproot key-spend inputs a wallet owns, fails when
+/// one of its taproot inputs to sign spends none of `internal_keys`
+pub fn set_wallet_inputs(psbt: &mut Psbt, internal_keys: &[XOnlyPublicKey]) -> Result<()> {
+    for internal_key in internal_keys {
+        set_tap_internal_key(psbt, *internal_key);
+    }
+
+    for (idx, input) in psbt.inputs.iter().enumerate() {
+        let is_p2tr = input
+            .witness_utxo
+            .as_ref()
+            .is_some_and(|out| out.script_pubkey.is_p2tr());
+        if is_p2tr && !is_finalized(input) && input.tap_internal_key.is_none() {
+            bail!("no internal key for taproot input {}", idx);
+        }
+    }
+    Ok(())
+}
+
+/// txids funding the legacy inputs of `tx`, `from_tx` needs them as non_witness_utxo
+pub fn legacy_prev_txids(tx: &Transaction, prevouts: &[TxOut]) -> Vec<Txid> {
+    tx.input
+        .iter()
+        .zip(prevouts)
+        .filter(|(_, prevout)| is_legacy(&prevout.script_pubkey))
+        .map(|(tx_in, _)| tx_in.previous_output.txid)
+        .collect()
+}
+
+/// spends no witness program, p2sh is taken for the nested segwit our wallets use
+fn is_legacy(script_pubkey: &Script) -> bool {
+    !script_pubkey.is_witness_program() && !script_pubkey.is_p2sh()
+}
+
+/// record the script tree behind input `idx` so the signer can use the key or a leaf
+pub fn set_taproot_spend_info(
+    psbt: &mut Psbt,
+    idx: usize,
+    spend_info: &TaprootSpendInfo,
+) -> Result<()> {
+    let input = psbt
+        .inputs
+        .get_mut(idx)
+        .ok_or_else(|| anyhow!("psbt has no input {}", idx))?;
+    input.tap_internal_key = Some(spend_info.internal_key());
+    input.tap_merkle_root = spend_info.merkle_root();
+    for leaf in spend_info.script_map().keys() {
+        if let Some(control_block) = spend_info.control_block(leaf) {
+            input.tap_scripts.insert(control_block, leaf.clone());
+        }
+    }
+    Ok(())
+}
+
+/// record the sighash flag input `idx` is to be signed with
+pub fn set_sighash(psbt: &mut Psbt, idx: usize, sighash: signer::SighashFlag) -> Result<()> {
+    let script_pubkey = prevout(psbt, idx)?.script_pubkey;
+    if is_finalized(&psbt.inputs[idx]) {
+        bail!("psbt input {} is already finalized", idx);
+    }
+    psbt.inputs[idx].sighash_type = Some(sighash_type(&script_pubkey, sighash));
+    Ok(())
+}
+
+/// the sighash flag recorded on a psbt input, ALL when unset
+pub fn sighash(input: &Input) -> Result<signer::SighashFlag> {
+    let Some(ty) = input.sighash_type else {
+        return Ok(signer::SighashFlag::All);
+    };
+    let is_p2tr = input
+        .witness_utxo
+        .as_ref()
+        .is_some_and(|out| out.script_pubkey.is_p2tr());
+    if is_p2tr {
+        Ok(ty.taproot_hash_ty()?.into())
+    } else {
+        Ok(ty.ecdsa_hash_ty()?.into())
+    }
+}
+
+pub fn is_finalized(input: &Input) -> bool {
+    input.final_script_witness.is_some() || input.final_script_sig.is_some()
+}
+
+/// the output input `idx` spends: its witness_utxo, or the output of its
+/// non_witness_utxo once that is checked to be the tx spent
+pub fn prevout(psbt: &Psbt, idx: usize) -> Result<TxOut> {
+    let input = psbt
+        .inputs
+        .get(idx)
+        .ok_or_else(|| anyhow!("psbt has no input {}", idx))?;
+    if let Some(prevout) = &input.witness_utxo {
+        return Ok(prevout.clone());
+    }
+    let prev_tx = input
+        .non_witness_utxo
+        .as_ref()
+        .ok_or_else(|| anyhow!("psbt input {} has no witness_utxo or non_witness_utxo", idx))?;
+    let out_point = psbt.unsigned_tx.input[idx].previous_output;
+    if prev_tx.compute_txid() != out_point.txid {
+        bail!("psbt input {} has the non_witness_utxo of another tx", idx);
+    }
+    prev_tx
+        .output
+        .get(out_point.vout as usize)
+        .cloned()
+        .ok_or_else(|| anyhow!("psbt input {} spends a missing output", idx))
+}
+
+/// prevouts of every input in order, used for taproot sighashes
+pub fn prevouts(psbt: &Psbt) -> Result<Vec<TxOut>> {
+    (0..psbt.inputs.len())
+        .map(|idx| prevout(psbt, idx))
+        .collect()
+}
+
+fn sighash_type(script_pubkey: &Script, sighash: signer::SighashFlag) -> PsbtSighashType {
+    if script_pubkey.is_p2tr() {
+        sighash.taproot().into()
+    } else {
+        sighash.ecdsa().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::key::TapTweak;
+    use bitcoin::sighash::{Prevouts, SighashCache};
+    use bitcoin::{hashes::Hash, OutPoint, PrivateKey, TapSighashType};
+    use builder::anchor;
+    use datatypes::fixtures;
+
+    #[test]
+    fn anchor_inputs_are_exported_final() {
+        let my_script = ScriptBuf::from_hex(
+            "51200000000000000000000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+        let payload = vec![2u8; 33];
+        let anchor_script = anchor::build_anchor_redeem_script(&payload).to_p2wsh();
+        let (tx, prevouts) = anchor::build_lightning_anchor_tx(
+            &fixtures::utxo(&my_script, 0, 10_000),
+            vec![fixtures::utxo(&anchor_script, 1, 330)],
+            vec![payload.clone()],
+            my_script.clone(),
+            fee_rate::FeeRate::try_from(1.0).unwrap(),
+            &fee_policy::SweepFeePolicy::default(),
+        )
+        .unwrap();
+
+        let psbt = from_tx(tx, &prevouts, &[]).unwrap();
+        let psbt = from_base64(&psbt.to_string()).unwrap();
+
+        assert!(!is_finalized(&psbt.inputs[0]));
+        assert_eq!(
+            psbt.inputs[0].sighash_type,
+            Some(TapSighashType::Default.into())
+        );
+        assert!(is_finalized(&psbt.inputs[1]));
+        assert_eq!(
+            psbt.inputs[1].witness_script,
+            Some(anchor::build_anchor_redeem_script(&payload))
+        );
+        assert!(psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .all(|input| input.witness.is_empty()));
+    }
+
+    #[test]
+    fn sign_and_finalize_round_trip() {
+        let secp = Secp256k1::new();
+        let private_key = PrivateKey::from_slice(&[7u8; 32], Network::Regtest).unwrap();
+        let (internal_key, _) = private_key.inner.x_only_public_key(&secp);
+        let sender = Address::p2tr(&secp, internal_key, None, Network::Regtest);
+        let (tx, prevouts) = builder::batch::build_batch_tx(
+            &sender.to_string(),
+            &[(sender.to_string(), 10_000)],
+            None,
+            fee_rate::FeeRate::try_from(1.0).unwrap(),
+            vec![fixtures::utxo(&ScriptBuf::new(), 0, 50_000)],
+            &policy::DustThreshold::default(),
+            Some(Network::Regtest),
+        )
+        .unwrap();
+
+        let mut psbt = from_tx(tx, &prevouts, &[]).unwrap();
+        set_tap_internal_key(&mut psbt, internal_key);
+        assert!(psbt.outputs.iter().all(|o| o.tap_internal_key.is_some()));
+        assert!(signer::finalize_psbt(&mut psbt.clone()).is_err());
+
+        let mut psbt = from_base64(&psbt.to_string()).unwrap();
+        let signed = signer::sign_psbt(private_key.to_wif(), &mut psbt).unwrap();
+        assert_eq!(signed.indexes(), vec![0]);
+        signer::finalize_psbt(&mut psbt).unwrap();
+        assert!(psbt.inputs[0].tap_key_sig.is_none());
+
+        let tx = psbt.extract_tx().unwrap();
+        let witness = &tx.input[0].witness;
+        assert_eq!(witness.len(), 1);
+
+        let sighash = SighashCache::new(&tx)
+            .taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), TapSighashType::Default)
+            .unwrap();
+        let sig = secp256k1::schnorr::Signature::from_slice(&witness[0]).unwrap();
+        let (output_key, _) = internal_key.tap_tweak(&secp, None);
+        secp.verify_schnorr(
+            &sig,
+            &secp256k1::Message::from_digest(sighash.to_byte_array()),
+            &output_key.to_x_only_public_key(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn wallet_inputs_get_their_key_and_funding_tx() {
+        let secp = Secp256k1::new();
+        let private_key = PrivateKey::from_slice(&[7u8; 32], Network::Regtest).unwrap();
+        let (internal_key, _) = private_key.inner.x_only_public_key(&secp);
+        let p2tr = ScriptBuf::new_p2tr(&secp, internal_key, None);
+        let p2pkh = ScriptBuf::new_p2pkh(&private_key.public_key(&secp).pubkey_hash());
+        let (funding, _) = builder::base::assemble_tx(
+            &[fixtures::utxo(&ScriptBuf::new(), 0, 20_000)],
+            vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: p2pkh.clone(),
+            }],
+        );
+        let legacy = types::Utxo {
+            out_point: OutPoint {
+                txid: funding.compute_txid(),
+                vout: 0,
+            },
+            value: Amount::from_sat(10_000),
+            script_pubkey: p2pkh,
+        };
+        let utxos = [fixtures::utxo(&p2tr, 1, 10_000), legacy];
+        let (tx, prevouts) = builder::base::assemble_tx(&utxos, vec![]);
+        assert_eq!(
+            legacy_prev_txids(&tx, &prevouts),
+            vec![funding.compute_txid()]
+        );
+        assert!(from_tx(tx.clone(), &prevouts, &[]).is_err());
+
+        let mut psbt = from_tx(tx, &prevouts, std::slice::from_ref(&funding)).unwrap();
+        assert_eq!(psbt.inputs[1].non_witness_utxo, Some(funding));
+        assert!(psbt.inputs[1].witness_utxo.is_none());
+        assert_eq!(prevout(&psbt, 1).unwrap(), prevouts[1]);
+
+        assert!(set_wallet_inputs(&mut psbt.clone(), &[]).is_err());
+        set_wallet_inputs(&mut psbt, &[internal_key]).unwrap();
+        assert_eq!(psbt.inputs[0].tap_internal_key, Some(internal_key));
+    }
+
+    #[test]
+    fn mismatched_prevouts_are_rejected() {
+        let (tx, _) =
+            builder::base::assemble_tx(&[fixtures::utxo(&ScriptBuf::new(), 0, 1_000)], vec![]);
+        assert!(from_tx(tx, &[], &[]).is_err());
+    }
+}
@@ -0,0 +1,288 @@
+use super::*;
+use bitcoin::{OutPoint, Txid};
+use error::BuildError;
+use fee_rate::FeeRate;
+use policy::DustThreshold;
+use std::collections::HashSet;
+
+/// bitcoin core's default -incrementalrelayfee in sat/vB
+pub const INCREMENTAL_RELAY_FEE: f64 = 1.0;
+
+/// a BIP125 replacement, the inputs in `sign_idx` still need our signature
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replacement {
+    pub tx: Transaction,
+    pub prevouts: Vec<TxOut>,
+    pub sign_idx: Vec<usize>,
+    pub fee: Amount,
+}
+
+/// replace our unconfirmed `original` with a transaction paying `fee_rate`.
+///
+/// the replacement spends the same inputs, our `sign_idx` inputs are stripped for
+/// re-signing and every other witness is kept. the extra fee comes out of output
+/// `change`; when that would leave it dust, `extra` wallet utxos are added, largest
+/// first. BIP125 forbids new unconfirmed inputs, so extra utxos created by `original`
+/// or by the `unconfirmed` transactions are left out
+#[allow(clippy::too_many_arguments)]
+pub fn bump_fee(
+    original: &Transaction,
+    prevouts: &[TxOut],
+    sign_idx: &[usize],
+    change: usize,
+    fee_rate: FeeRate,
+    extra: &[types::Utxo],
+    unconfirmed: &HashSet<Txid>,
+    dust: &DustThreshold,
+) -> Result<Replacement> {
+    if !original.is_explicitly_rbf() {
+        bail!(
+            "{} does not signal BIP125 replaceability",
+            original.compute_txid()
+        );
+    }
+    if original.input.len() != prevouts.len() {
+        bail!(
+            "tx has {} inputs but {} prevouts",
+            original.input.len(),
+            prevouts.len()
+        );
+    }
+    let change_script = original
+        .output
+        .get(change)
+        .ok_or_else(|| anyhow!("tx has no output {}", change))?
+        .script_pubkey
+        .clone();
+
+    let input_value = |prevouts: &[TxOut]| prevouts.iter().map(|out| out.value).sum::<Amount>();
+    let output_value: Amount = original.output.iter().map(|out| out.value).sum();
+    let original_fee = input_value(prevouts)
+        .checked_sub(output_value)
+        .ok_or_else(|| anyhow!("outputs spend more than the inputs"))?;
+    let incremental = FeeRate::try_from(INCREMENTAL_RELAY_FEE)?;
+
+    let mut tx = original.clone();
+    let mut prevouts = prevouts.to_vec();
+    let mut sign_idx = sign_idx.to_vec();
+    for idx in sign_idx.iter() {
+        let input = tx
+            .input
+            .get_mut(*idx)
+            .ok_or_else(|| anyhow!("tx has no input {}", idx))?;
+        input.script_sig = ScriptBuf::new();
+        input.witness = Witness::new();
+    }
+
+    let spent: Vec<OutPoint> = tx.input.iter().map(|input| input.previous_output).collect();
+    let txid = original.compute_txid();
+    let mut extra: Vec<&types::Utxo> = extra
+        .iter()
+        .filter(|utxo| !spent.contains(&utxo.out_point))
+        .filter(|utxo| utxo.out_point.txid != txid && !unconfirmed.contains(&utxo.out_point.txid))
+        .collect();
+    extra.sort_by_key(|utxo| std::cmp::Reverse(utxo.value));
+    let mut extra = extra.into_iter();
+
+    // everything but the change stays as it is
+    let fixed_outputs = output_value - original.output[change].value;
+    let min_change = dust.for_script(&change_script);
+    loop {
+        let vsize = weight::signed_vsize(&tx, &prevouts);
+        // BIP125 rules 3 and 4: at least the old fee plus relay fee for our own size
+        let fee = fee_rate
+            .fee(vsize)
+            .max(original_fee + incremental.fee(vsize));
+        let available = input_value(&prevouts);
+        let needed = fixed_outputs + fee + min_change;
+        if available >= needed {
+            tx.output[change].value = available - fixed_outputs - fee;
+            info!(
+                "replacing {} fee {} with {}",
+                original.compute_txid(),
+                original_fee,
+                fee
+            );
+            return Ok(Replacement {
+                tx,
+                prevouts,
+                sign_idx,
+                fee,
+            });
+        }
+
+        let Some(utxo) = extra.next() else {
+            return Err(BuildError::InsufficientFunds { needed, available }.into());
+        };
+        sign_idx.push(tx.input.len());
+        tx.input.push(TxIn {
+            previous_output: utxo.out_point,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        });
+        prevouts.push(TxOut {
+            value: utxo.value,
+            script_pubkey: utxo.script_pubkey.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::PrivateKey;
+    use datatypes::fixtures;
+
+    fn signed_sweep(private_key: &PrivateKey, output: u64) -> (Transaction, Vec<TxOut>) {
+        let secp = secp256k1::Secp256k1::new();
+        let (internal_key, _) = private_key.inner.x_only_public_key(&secp);
+        let script_pubkey = ScriptBuf::new_p2tr(&secp, internal_key, None);
+        let outputs = vec![TxOut {
+            value: Amount::from_sat(output),
+            script_pubkey: script_pubkey.clone(),
+        }];
+        let (mut tx, prevouts) =
+            builder::base::assemble_tx(&[fixtures::utxo(&script_pubkey, 0, 50_000)], outputs);
+        tx.input[0].sequence = Sequence::ENABLE_RBF_NO_LOCKTIME;
+        let tx = signer::sign_tx(private_key.to_wif(), tx, prevouts.clone(), vec![0]).unwrap();
+        (tx, prevouts)
+    }
+
+    #[test]
+    fn pays_target_rate_from_change() {
+        let private_key = PrivateKey::from_slice(&[7u8; 32], Network::Regtest).unwrap();
+        let (original, prevouts) = signed_sweep(&private_key, 49_000);
+        let fee_rate = FeeRate::try_from(20.0).unwrap();
+        let dust = DustThreshold::default();
+
+        let replacement = bump_fee(
+            &original,
+            &prevouts,
+            &[0],
+            0,
+            fee_rate,
+            &[],
+            &HashSet::new(),
+            &dust,
+        )
+        .unwrap();
+        assert_eq!(replacement.sign_idx, vec![0]);
+        assert!(replacement.tx.input[0].witness.is_empty());
+        let signed = signer::sign_tx(
+            private_key.to_wif(),
+            replacement.tx,
+            replacement.prevouts,
+            replacement.sign_idx,
+        )
+        .unwrap();
+        // the key spend is estimated with a sighash byte the default flag leaves out
+        assert_eq!(
+            replacement.fee,
+            fee_rate.fee((signed.weight().to_wu() as usize + 1).div_ceil(4))
+        );
+        assert_eq!(
+            signed.output[0].value,
+            Amount::from_sat(50_000) - replacement.fee
+        );
+
+        // a tiny target still has to beat the old fee by the incremental relay fee
+        let low = FeeRate::try_from(1.0).unwrap();
+        let replacement = bump_fee(
+            &original,
+            &prevouts,
+            &[0],
+            0,
+            low,
+            &[],
+            &HashSet::new(),
+            &dust,
+        )
+        .unwrap();
+        assert_eq!(
+            replacement.fee,
+            Amount::from_sat(1_000)
+                + Amount::from_sat(
+                    weight::signed_vsize(&replacement.tx, &replacement.prevouts) as u64
+                )
+        );
+
+        let mut final_tx = original.clone();
+        final_tx.input[0].sequence = Sequence::MAX;
+        assert!(bump_fee(
+            &final_tx,
+            &prevouts,
+            &[0],
+            0,
+            fee_rate,
+            &[],
+            &HashSet::new(),
+            &dust
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn adds_wallet_inputs_when_change_runs_dry() {
+        let private_key = PrivateKey::from_slice(&[7u8; 32], Network::Regtest).unwrap();
+        let (original, prevouts) = signed_sweep(&private_key, 49_000);
+        let fee_rate = FeeRate::try_from(500.0).unwrap();
+        let dust = DustThreshold::default();
+        let script_pubkey = prevouts[0].script_pubkey.clone();
+
+        assert!(bump_fee(
+            &original,
+            &prevouts,
+            &[0],
+            0,
+            fee_rate,
+            &[],
+            &HashSet::new(),
+            &dust
+        )
+        .is_err());
+
+        let extra = vec![
+            fixtures::utxo(&script_pubkey, 1, 1_000),
+            fixtures::utxo(&script_pubkey, 2, 40_000),
+            fixtures::utxo(&script_pubkey, 0, 50_000),
+        ];
+        // outputs of mempool transactions can not be added
+        let unconfirmed = HashSet::from([fixtures::out_point(0).txid]);
+        assert!(bump_fee(
+            &original,
+            &prevouts,
+            &[0],
+            0,
+            fee_rate,
+            &extra,
+            &unconfirmed,
+            &dust
+        )
+        .is_err());
+
+        let replacement = bump_fee(
+            &original,
+            &prevouts,
+            &[0],
+            0,
+            fee_rate,
+            &extra,
+            &HashSet::new(),
+            &dust,
+        )
+        .unwrap();
+        // the input already spent is not added twice, the largest new one comes first
+        assert_eq!(replacement.sign_idx, vec![0, 1]);
+        assert_eq!(replacement.tx.input[1].previous_output.vout, 2);
+        assert!(replacement.tx.is_explicitly_rbf());
+        let signed = signer::sign_tx(
+            private_key.to_wif(),
+            replacement.tx,
+            replacement.prevouts,
+            replacement.sign_idx,
+        )
+        .unwrap();
+        assert_eq!(replacement.fee, fee_rate.fee(signed.vsize()));
+    }
+}
@@ -0,0 +1,287 @@
+use super::*;
+use bitcoin::sighash::{EcdsaSighashType, TapSighashType};
+use std::fmt;
+
+/// the outputs a signature commits to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SighashBase {
+    All,
+    /// none, whoever relays the input picks the outputs
+    None,
+    /// only the output at the index of the input
+    Single,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureKind {
+    Ecdsa,
+    Schnorr,
+}
+
+/// where a signature sits in its input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureLocation {
+    ScriptSig(usize),
+    Witness(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignatureSighash {
+    pub kind: SignatureKind,
+    pub location: SignatureLocation,
+    /// the flag byte as found, 0x00 for 64 byte schnorr signatures
+    pub flag: u8,
+    pub base: SighashBase,
+    pub anyone_can_pay: bool,
+}
+
+impl SignatureSighash {
+    fn ecdsa(location: SignatureLocation, flag: u8) -> Self {
+        // consensus only looks at the low bits and the anyone can pay bit
+        let (base, anyone_can_pay) = match EcdsaSighashType::from_consensus(u32::from(flag)) {
+            EcdsaSighashType::All => (SighashBase::All, false),
+            EcdsaSighashType::None => (SighashBase::None, false),
+            EcdsaSighashType::Single => (SighashBase::Single, false),
+            EcdsaSighashType::AllPlusAnyoneCanPay => (SighashBase::All, true),
+            EcdsaSighashType::NonePlusAnyoneCanPay => (SighashBase::None, true),
+            EcdsaSighashType::SinglePlusAnyoneCanPay => (SighashBase::Single, true),
+        };
+        Self {
+            kind: SignatureKind::Ecdsa,
+            location,
+            flag,
+            base,
+            anyone_can_pay,
+        }
+    }
+
+    fn schnorr(location: SignatureLocation, flag: u8) -> Option<Self> {
+        let (base, anyone_can_pay) = match TapSighashType::from_consensus_u8(flag).ok()? {
+            TapSighashType::Default | TapSighashType::All => (SighashBase::All, false),
+            TapSighashType::None => (SighashBase::None, false),
+            TapSighashType::Single => (SighashBase::Single, false),
+            TapSighashType::AllPlusAnyoneCanPay => (SighashBase::All, true),
+            TapSighashType::NonePlusAnyoneCanPay => (SighashBase::None, true),
+            TapSighashType::SinglePlusAnyoneCanPay => (SighashBase::Single, true),
+        };
+        Some(Self {
+            kind: SignatureKind::Schnorr,
+            location,
+            flag,
+            base,
+            anyone_can_pay,
+        })
+    }
+}
+
+impl fmt::Display for SignatureSighash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let base = match self.base {
+            SighashBase::All => "ALL",
+            SighashBase::None => "NONE",
+            SighashBase::Single => "SINGLE",
+        };
+        write!(f, "SIGHASH_{}", base)?;
+        if self.anyone_can_pay {
+            write!(f, "|ANYONECANPAY")?;
+        }
+        Ok(())
+    }
+}
+
+/// the signatures found in one input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputSighash {
+    pub idx: usize,
+    pub signatures: Vec<SignatureSighash>,
+    /// SIGHASH_SINGLE on an input without an output at its index, the signature
+    /// commits to no output at all
+    pub single_without_output: bool,
+}
+
+impl InputSighash {
+    /// a signature leaving the outputs open to whoever relays the input
+    pub fn is_weak(&self) -> bool {
+        self.single_without_output
+            || self
+                .signatures
+                .iter()
+                .any(|signature| signature.base == SighashBase::None)
+    }
+}
+
+/// sighash flags of every signature in `tx`, by input
+pub fn input_sighashes(tx: &Transaction) -> Vec<InputSighash> {
+    tx.input
+        .iter()
+        .enumerate()
+        .map(|(idx, input)| {
+            let signatures = signatures(input);
+            let single_without_output = idx >= tx.output.len()
+                && signatures
+                    .iter()
+                    .any(|signature| signature.base == SighashBase::Single);
+            InputSighash {
+                idx,
+                signatures,
+                single_without_output,
+            }
+        })
+        .collect()
+}
+
+/// the inputs of `tx` signed with SIGHASH_NONE, or SIGHASH_SINGLE without a matching output
+pub fn weak_inputs(tx: &Transaction) -> Vec<InputSighash> {
+    input_sighashes(tx)
+        .into_iter()
+        .filter(|input| input.is_weak())
+        .collect()
+}
+
+fn signatures(input: &TxIn) -> Vec<SignatureSighash> {
+    let mut found = vec![];
+    for (idx, instruction) in input.script_sig.instructions().enumerate() {
+        if let Ok(Instruction::PushBytes(bytes)) = instruction {
+            if let Some(flag) = ecdsa_flag(bytes.as_bytes()) {
+                found.push(SignatureSighash::ecdsa(
+                    SignatureLocation::ScriptSig(idx),
+                    flag,
+                ));
+            }
+        }
+    }
+
+    if let Some(spend) = witness::parse_tapscript_spend(&input.witness, None) {
+        // the leaf and control block hold no signature
+        for (idx, arg) in spend.args.iter().enumerate() {
+            if let Some(signature) = schnorr(SignatureLocation::Witness(idx), arg) {
+                found.push(signature);
+            }
+        }
+        return found;
+    }
+
+    let elements: Vec<&[u8]> = input.witness.iter().collect();
+    match elements.as_slice() {
+        [element] if element.len() == 64 || element.len() == 65 => {
+            // key path
+            found.extend(schnorr(SignatureLocation::Witness(0), element));
+        }
+        _ => {
+            for (idx, element) in elements.iter().enumerate() {
+                if let Some(flag) = ecdsa_flag(element) {
+                    found.push(SignatureSighash::ecdsa(
+                        SignatureLocation::Witness(idx),
+                        flag,
+                    ));
+                }
+            }
+        }
+    }
+    found
+}
+
+/// the sighash byte of a DER encoded ecdsa signature
+fn ecdsa_flag(element: &[u8]) -> Option<u8> {
+    let (flag, der) = element.split_last()?;
+    if der.first() != Some(&0x30) {
+        return None;
+    }
+    secp256k1::ecdsa::Signature::from_der(der).ok()?;
+    Some(*flag)
+}
+
+fn schnorr(location: SignatureLocation, element: &[u8]) -> Option<SignatureSighash> {
+    match element.len() {
+        64 => SignatureSighash::schnorr(location, 0x00),
+        // an explicit 0x00 is invalid, SIGHASH_DEFAULT is spelled by omitting the byte
+        65 if element[64] != 0x00 => SignatureSighash::schnorr(location, element[64]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::script::{Builder, PushBytesBuf};
+    use bitcoin::{OutPoint, PrivateKey, Txid};
+    use secp256k1::{Message, Secp256k1};
+
+    fn ecdsa_signature(flag: u8) -> Vec<u8> {
+        let secp = Secp256k1::new();
+        let private_key = PrivateKey::from_slice(&[7u8; 32], Network::Regtest).unwrap();
+        let msg = Message::from_digest([1u8; 32]);
+        let mut signature = secp
+            .sign_ecdsa(&msg, &private_key.inner)
+            .serialize_der()
+            .to_vec();
+        signature.push(flag);
+        signature
+    }
+
+    fn input(script_sig: ScriptBuf, witness: Vec<Vec<u8>>) -> TxIn {
+        TxIn {
+            previous_output: OutPoint {
+                txid: Txid::from_str(
+                    "0000000000000000000000000000000000000000000000000000000000000001",
+                )
+                .unwrap(),
+                vout: 0,
+            },
+            script_sig,
+            sequence: Sequence::MAX,
+            witness: Witness::from_slice(&witness),
+        }
+    }
+
+    #[test]
+    fn reports_weak_flags_per_input() {
+        let pubkey = vec![2u8; 33];
+        let p2pkh_none = Builder::new()
+            .push_slice(PushBytesBuf::try_from(ecdsa_signature(0x02)).unwrap())
+            .push_slice(PushBytesBuf::try_from(pubkey.clone()).unwrap())
+            .into_script();
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![
+                input(p2pkh_none, vec![]),
+                input(
+                    ScriptBuf::new(),
+                    vec![ecdsa_signature(0x01), pubkey.clone()],
+                ),
+                input(ScriptBuf::new(), vec![vec![1u8; 64]]),
+                // single with no output at index 3
+                input(ScriptBuf::new(), vec![ecdsa_signature(0x83), pubkey]),
+                input(ScriptBuf::new(), {
+                    let mut schnorr = vec![1u8; 64];
+                    schnorr.push(0x82);
+                    vec![schnorr]
+                }),
+            ],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: ScriptBuf::new_op_return([]),
+            }],
+        };
+
+        let reports = input_sighashes(&tx);
+        assert_eq!(reports[0].signatures[0].base, SighashBase::None);
+        assert_eq!(
+            reports[0].signatures[0].location,
+            SignatureLocation::ScriptSig(0)
+        );
+        assert_eq!(reports[1].signatures[0].base, SighashBase::All);
+        assert_eq!(reports[2].signatures[0].kind, SignatureKind::Schnorr);
+        assert_eq!(reports[2].signatures[0].base, SighashBase::All);
+        assert!(reports[3].single_without_output);
+        assert_eq!(
+            reports[3].signatures[0].to_string(),
+            "SIGHASH_SINGLE|ANYONECANPAY"
+        );
+        assert!(reports[4].signatures[0].anyone_can_pay);
+
+        let weak: Vec<usize> = weak_inputs(&tx).iter().map(|input| input.idx).collect();
+        assert_eq!(weak, vec![0, 3, 4]);
+    }
+}
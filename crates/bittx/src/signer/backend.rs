@@ -0,0 +1,174 @@
+use super::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// a request or response never gets near this, anything larger is dropped
+pub const MAX_MESSAGE_SIZE: u64 = 4 * 1024 * 1024;
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// signs the wallet inputs of the transactions our builders return.
+/// a signer may block on io, async code goes through `spawn_sign_tx`
+pub trait Signer: fmt::Debug + Send + Sync {
+    /// sign inputs `sign_idx` of `tx` with SIGHASH_ALL
+    fn sign_tx(
+        &self,
+        tx: Transaction,
+        prevouts: Vec<TxOut>,
+        sign_idx: Vec<usize>,
+    ) -> Result<Transaction>;
+}
+
+/// `Signer::sign_tx` on the blocking pool, so a remote signer waiting on its socket
+/// does not stall the runtime
+pub async fn spawn_sign_tx(
+    signer: Arc<dyn Signer>,
+    tx: Transaction,
+    prevouts: Vec<TxOut>,
+    sign_idx: Vec<usize>,
+) -> Result<Transaction> {
+    tokio::task::spawn_blocking(move || signer.sign_tx(tx, prevouts, sign_idx)).await?
+}
+
+/// keeps the wif inside this process
+pub struct LocalSigner {
+    wif: String,
+}
+
+impl LocalSigner {
+    pub fn new(wif: String) -> Self {
+        Self { wif }
+    }
+}
+
+impl fmt::Debug for LocalSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalSigner").finish_non_exhaustive()
+    }
+}
+
+impl Signer for LocalSigner {
+    fn sign_tx(
+        &self,
+        tx: Transaction,
+        prevouts: Vec<TxOut>,
+        sign_idx: Vec<usize>,
+    ) -> Result<Transaction> {
+        sign_tx(self.wif.clone(), tx, prevouts, sign_idx)
+    }
+}
+
+/// hands the transaction as a psbt to the signer daemon listening on `socket`,
+/// the key and the signing policy live in that process
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    socket: PathBuf,
+    timeout: Duration,
+}
+
+impl RemoteSigner {
+    pub fn new(socket: impl Into<PathBuf>) -> Self {
+        Self {
+            socket: socket.into(),
+            timeout: REMOTE_TIMEOUT,
+        }
+    }
+
+    pub fn request(&self, request: &SignRequest) -> Result<SignResponse> {
+        let stream = UnixStream::connect(&self.socket)
+            .map_err(|e| anyhow!("connect signer {}: {}", self.socket.display(), e))?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        write_message(&stream, request)?;
+        read_message(&stream)
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn sign_tx(
+        &self,
+        tx: Transaction,
+        prevouts: Vec<TxOut>,
+        sign_idx: Vec<usize>,
+    ) -> Result<Transaction> {
+        let psbt = psbt::from_tx(tx, &prevouts, &[])?;
+        let request = SignRequest {
+            psbt: psbt.to_string(),
+            inputs: sign_idx,
+        };
+
+        match self.request(&request)? {
+            SignResponse::Signed { psbt: signed } => {
+                let mut signed = psbt::from_base64(&signed)?;
+                if signed.unsigned_tx != psbt.unsigned_tx {
+                    bail!("signer returned a different transaction");
+                }
+                finalize_psbt(&mut signed)?;
+                let tx = signed.extract_tx_unchecked_fee_rate();
+                info!("{}", serialize_hex(&tx));
+                Ok(tx)
+            }
+            SignResponse::Rejected { reason } => bail!("signer rejected the tx: {}", reason),
+        }
+    }
+}
+
+/// asks the daemon to sign `inputs` of the base64 `psbt`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignRequest {
+    pub psbt: String,
+    pub inputs: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SignResponse {
+    /// the psbt with signatures on the requested inputs, not finalized
+    Signed {
+        psbt: String,
+    },
+    Rejected {
+        reason: String,
+    },
+}
+
+/// messages are single json lines
+pub fn write_message<T: Serialize, W: Write>(mut writer: W, message: &T) -> Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn read_message<T: DeserializeOwned, R: Read>(reader: R) -> Result<T> {
+    let mut line = String::new();
+    BufReader::new(reader.take(MAX_MESSAGE_SIZE)).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        bail!("signer message is truncated");
+    }
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_is_json_lines() {
+        let mut buf = vec![];
+        let response = SignResponse::Rejected {
+            reason: "fee".to_string(),
+        };
+        write_message(&mut buf, &response).unwrap();
+        assert_eq!(buf, b"{\"status\":\"rejected\",\"reason\":\"fee\"}\n");
+        assert_eq!(read_message::<SignResponse, _>(&buf[..]).unwrap(), response);
+        assert!(read_message::<SignResponse, _>(&buf[..buf.len() - 1]).is_err());
+
+        let local = LocalSigner::new("secret".to_string());
+        assert!(!format!("{:?}", local).contains("secret"));
+    }
+}
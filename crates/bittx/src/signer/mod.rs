@@ -0,0 +1,1079 @@
+use super::*;
+use bitcoin::hashes::Hash;
+use bitcoin::key::TapTweak;
+use bitcoin::psbt::{Input, Psbt};
+use bitcoin::script::Builder;
+use bitcoin::sighash::SighashCache;
+use bitcoin::taproot::{ControlBlock, LeafVersion, TapNodeHash, TaprootSpendInfo};
+use bitcoin::{
+    consensus::encode::serialize_hex, sighash::Prevouts, taproot::Signature, PrivateKey,
+};
+use bitcoin::{
+    ecdsa, EcdsaSighashType, PublicKey, TapLeafHash, TapSighash, TapSighashType, XOnlyPublicKey,
+};
+use secp256k1::{All, Keypair, Secp256k1};
+use std::borrow::Borrow;
+use std::fmt;
+use tracing::error;
+
+pub mod backend;
+
+pub use backend::{spawn_sign_tx, LocalSigner, RemoteSigner, Signer};
+
+/// sighash flags a caller can pick per input, for schnorr and ecdsa signatures alike
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SighashFlag {
+    #[default]
+    All,
+    None,
+    Single,
+    AllPlusAnyoneCanPay,
+    NonePlusAnyoneCanPay,
+    SinglePlusAnyoneCanPay,
+}
+
+impl SighashFlag {
+    pub fn ecdsa(self) -> EcdsaSighashType {
+        match self {
+            SighashFlag::All => EcdsaSighashType::All,
+            SighashFlag::None => EcdsaSighashType::None,
+            SighashFlag::Single => EcdsaSighashType::Single,
+            SighashFlag::AllPlusAnyoneCanPay => EcdsaSighashType::AllPlusAnyoneCanPay,
+            SighashFlag::NonePlusAnyoneCanPay => EcdsaSighashType::NonePlusAnyoneCanPay,
+            SighashFlag::SinglePlusAnyoneCanPay => EcdsaSighashType::SinglePlusAnyoneCanPay,
+        }
+    }
+
+    /// ALL is signed as SIGHASH_DEFAULT, which commits to the same data without the flag byte
+    pub fn taproot(self) -> TapSighashType {
+        match self {
+            SighashFlag::All => TapSighashType::Default,
+            SighashFlag::None => TapSighashType::None,
+            SighashFlag::Single => TapSighashType::Single,
+            SighashFlag::AllPlusAnyoneCanPay => TapSighashType::AllPlusAnyoneCanPay,
+            SighashFlag::NonePlusAnyoneCanPay => TapSighashType::NonePlusAnyoneCanPay,
+            SighashFlag::SinglePlusAnyoneCanPay => TapSighashType::SinglePlusAnyoneCanPay,
+        }
+    }
+
+    pub fn is_single(self) -> bool {
+        matches!(
+            self,
+            SighashFlag::Single | SighashFlag::SinglePlusAnyoneCanPay
+        )
+    }
+}
+
+impl From<EcdsaSighashType> for SighashFlag {
+    fn from(ty: EcdsaSighashType) -> Self {
+        match ty {
+            EcdsaSighashType::All => SighashFlag::All,
+            EcdsaSighashType::None => SighashFlag::None,
+            EcdsaSighashType::Single => SighashFlag::Single,
+            EcdsaSighashType::AllPlusAnyoneCanPay => SighashFlag::AllPlusAnyoneCanPay,
+            EcdsaSighashType::NonePlusAnyoneCanPay => SighashFlag::NonePlusAnyoneCanPay,
+            EcdsaSighashType::SinglePlusAnyoneCanPay => SighashFlag::SinglePlusAnyoneCanPay,
+        }
+    }
+}
+
+impl From<TapSighashType> for SighashFlag {
+    fn from(ty: TapSighashType) -> Self {
+        match ty {
+            TapSighashType::Default | TapSighashType::All => SighashFlag::All,
+            TapSighashType::None => SighashFlag::None,
+            TapSighashType::Single => SighashFlag::Single,
+            TapSighashType::AllPlusAnyoneCanPay => SighashFlag::AllPlusAnyoneCanPay,
+            TapSighashType::NonePlusAnyoneCanPay => SighashFlag::NonePlusAnyoneCanPay,
+            TapSighashType::SinglePlusAnyoneCanPay => SighashFlag::SinglePlusAnyoneCanPay,
+        }
+    }
+}
+
+impl fmt::Display for SighashFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            SighashFlag::All => "ALL",
+            SighashFlag::None => "NONE",
+            SighashFlag::Single => "SINGLE",
+            SighashFlag::AllPlusAnyoneCanPay => "ALL|ANYONECANPAY",
+            SighashFlag::NonePlusAnyoneCanPay => "NONE|ANYONECANPAY",
+            SighashFlag::SinglePlusAnyoneCanPay => "SINGLE|ANYONECANPAY",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpendKind {
+    TaprootKeyPath,
+    TaprootScriptPath,
+    P2wpkh,
+    P2shP2wpkh,
+    P2pkh,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignedInput {
+    pub idx: usize,
+    pub kind: SpendKind,
+    pub sighash: SighashFlag,
+}
+
+/// what the signer did to each input it signed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SigningReport {
+    pub inputs: Vec<SignedInput>,
+}
+
+impl SigningReport {
+    pub fn indexes(&self) -> Vec<usize> {
+        self.inputs.iter().map(|input| input.idx).collect()
+    }
+
+    fn push(&mut self, idx: usize, kind: SpendKind, sighash: SighashFlag) {
+        info!(
+            "signed input {} as {:?} with SIGHASH_{}",
+            idx, kind, sighash
+        );
+        self.inputs.push(SignedInput { idx, kind, sighash });
+    }
+}
+
+pub fn sign_tx(
+    wif: String,
+    tx: Transaction,
+    prevouts: Vec<TxOut>,
+    sign_idx: Vec<usize>,
+) -> Result<Transaction> {
+    let inputs = sign_idx
+        .into_iter()
+        .map(|idx| (idx, SighashFlag::All))
+        .collect();
+    let (tx, _) = sign_tx_with_sighash(wif, tx, prevouts, inputs)?;
+    Ok(tx)
+}
+
+/// sign each `(index, flag)` input with its own sighash flag
+pub fn sign_tx_with_sighash(
+    wif: String,
+    tx: Transaction,
+    prevouts: Vec<TxOut>,
+    inputs: Vec<(usize, SighashFlag)>,
+) -> Result<(Transaction, SigningReport)> {
+    if prevouts.is_empty() {
+        return Err(anyhow!("no prevouts"));
+    }
+
+    if inputs.is_empty() {
+        return Err(anyhow!("no sign index"));
+    }
+
+    if wif.is_empty() {
+        return Err(anyhow!("wif is empty"));
+    }
+
+    let private_key = PrivateKey::from_wif(wif.as_str()).unwrap();
+    let mut tx = tx;
+    let mut report = SigningReport::default();
+    for (idx, sighash) in inputs.into_iter() {
+        let kind = sign_input(private_key, &mut tx, &prevouts, idx, sighash)?;
+        report.push(idx, kind, sighash);
+    }
+
+    info!("{}", serialize_hex(&tx));
+    Ok((tx, report))
+}
+
+/// SIGHASH_SINGLE needs an output at the index of the input
+fn check_single_output(tx: &Transaction, idx: usize, sighash: SighashFlag) -> Result<()> {
+    if sighash.is_single() && idx >= tx.output.len() {
+        bail!(
+            "input {} has no matching output for SIGHASH_{}",
+            idx,
+            sighash
+        );
+    }
+    Ok(())
+}
+
+/// sign input `idx` the way its prevout script_pubkey asks for
+fn sign_input(
+    private_key: PrivateKey,
+    tx: &mut Transaction,
+    prevouts: &[TxOut],
+    idx: usize,
+    sighash: SighashFlag,
+) -> Result<SpendKind> {
+    let prevout = prevouts
+        .get(idx)
+        .ok_or_else(|| anyhow!("no prevout for input {}", idx))?;
+    check_single_output(tx, idx, sighash)?;
+    if prevout.script_pubkey.is_p2tr() {
+        sign_taproot_key_spend(private_key, tx, prevouts, idx, sighash.taproot())?;
+        return Ok(SpendKind::TaprootKeyPath);
+    }
+
+    let secp256k1 = Secp256k1::new();
+    let public_key = private_key.public_key(&secp256k1);
+    let spend = EcdsaSpend::for_key(&public_key, &prevout.script_pubkey).ok_or_else(|| {
+        anyhow!(
+            "input {} script {} is not spendable by the wif key",
+            idx,
+            prevout.script_pubkey
+        )
+    })?;
+    let sig = ecdsa_signature(
+        &secp256k1,
+        &private_key,
+        &mut SighashCache::new(&*tx),
+        &spend,
+        prevout,
+        idx,
+        sighash.ecdsa(),
+    )?;
+
+    let (script_sig, witness) = spend.satisfy(&sig, &public_key);
+    tx.input[idx].script_sig = script_sig;
+    tx.input[idx].witness = witness;
+    Ok(spend.kind())
+}
+
+/// single key ecdsa spends
+#[derive(Debug, Clone, PartialEq)]
+enum EcdsaSpend {
+    P2pkh,
+    P2wpkh,
+    /// p2wpkh nested in p2sh, holds the redeem script
+    P2shP2wpkh(ScriptBuf),
+}
+
+impl EcdsaSpend {
+    fn kind(&self) -> SpendKind {
+        match self {
+            EcdsaSpend::P2pkh => SpendKind::P2pkh,
+            EcdsaSpend::P2wpkh => SpendKind::P2wpkh,
+            EcdsaSpend::P2shP2wpkh(_) => SpendKind::P2shP2wpkh,
+        }
+    }
+
+    fn for_key(public_key: &PublicKey, script_pubkey: &Script) -> Option<Self> {
+        if *script_pubkey == ScriptBuf::new_p2pkh(&public_key.pubkey_hash()) {
+            return Some(EcdsaSpend::P2pkh);
+        }
+
+        // segwit only commits to compressed keys
+        let p2wpkh = ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash().ok()?);
+        if *script_pubkey == p2wpkh {
+            Some(EcdsaSpend::P2wpkh)
+        } else if *script_pubkey == p2wpkh.to_p2sh() {
+            Some(EcdsaSpend::P2shP2wpkh(p2wpkh))
+        } else {
+            None
+        }
+    }
+
+    /// script_sig and witness carrying `sig`
+    fn satisfy(&self, sig: &ecdsa::Signature, public_key: &PublicKey) -> (ScriptBuf, Witness) {
+        match self {
+            EcdsaSpend::P2pkh => {
+                let script_sig = Builder::new()
+                    .push_slice(sig.serialize())
+                    .push_key(public_key)
+                    .into_script();
+                (script_sig, Witness::new())
+            }
+            EcdsaSpend::P2wpkh => (ScriptBuf::new(), Witness::p2wpkh(sig, &public_key.inner)),
+            EcdsaSpend::P2shP2wpkh(redeem_script) => {
+                let redeem: &PushBytes = redeem_script
+                    .as_bytes()
+                    .try_into()
+                    .expect("p2wpkh script is 22 bytes");
+                let script_sig = Builder::new().push_slice(redeem).into_script();
+                (script_sig, Witness::p2wpkh(sig, &public_key.inner))
+            }
+        }
+    }
+}
+
+/// BIP143 sighash for the segwit spends, the legacy one for p2pkh. signed with low-r grinding
+fn ecdsa_signature<T: Borrow<Transaction>>(
+    secp256k1: &Secp256k1<All>,
+    private_key: &PrivateKey,
+    sighash_cache: &mut SighashCache<T>,
+    spend: &EcdsaSpend,
+    prevout: &TxOut,
+    idx: usize,
+    sighash_type: EcdsaSighashType,
+) -> Result<ecdsa::Signature> {
+    let digest = match spend {
+        EcdsaSpend::P2pkh => sighash_cache
+            .legacy_signature_hash(idx, &prevout.script_pubkey, sighash_type.to_u32())?
+            .to_byte_array(),
+        EcdsaSpend::P2wpkh => sighash_cache
+            .p2wpkh_signature_hash(idx, &prevout.script_pubkey, prevout.value, sighash_type)?
+            .to_byte_array(),
+        EcdsaSpend::P2shP2wpkh(redeem_script) => sighash_cache
+            .p2wpkh_signature_hash(idx, redeem_script, prevout.value, sighash_type)?
+            .to_byte_array(),
+    };
+
+    let msg = secp256k1::Message::from_digest(digest);
+    Ok(ecdsa::Signature {
+        signature: secp256k1.sign_ecdsa_low_r(&msg, &private_key.inner),
+        sighash_type,
+    })
+}
+
+/// how a taproot input is spent
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaprootSpend {
+    /// key path, the key is tweaked with the merkle root of the script tree if there is one
+    KeyPath { merkle_root: Option<TapNodeHash> },
+    /// script path through a single signature leaf, proven by `control_block`
+    ScriptPath {
+        script: ScriptBuf,
+        control_block: ControlBlock,
+    },
+}
+
+impl TaprootSpend {
+    pub fn key_path(spend_info: &TaprootSpendInfo) -> Self {
+        TaprootSpend::KeyPath {
+            merkle_root: spend_info.merkle_root(),
+        }
+    }
+
+    pub fn script_path(spend_info: &TaprootSpendInfo, script: ScriptBuf) -> Result<Self> {
+        let control_block = spend_info
+            .control_block(&(script.clone(), LeafVersion::TapScript))
+            .ok_or_else(|| anyhow!("script {} is not a leaf of the tree", script))?;
+        Ok(TaprootSpend::ScriptPath {
+            script,
+            control_block,
+        })
+    }
+}
+
+pub fn sign_taproot(
+    private_key: PrivateKey,
+    tx: &mut Transaction,
+    prevouts: Vec<TxOut>,
+    idx: usize,
+    spend: TaprootSpend,
+    sighash: SighashFlag,
+) -> Result<SpendKind> {
+    if prevouts.is_empty() {
+        error!("previous outputs is empty");
+        return Err(anyhow!("previous outputs is empty"));
+    }
+    let prevout = prevouts
+        .get(idx)
+        .ok_or_else(|| anyhow!("no prevout for input {}", idx))?;
+    check_single_output(tx, idx, sighash)?;
+    let output_key = taproot_output_key(&prevout.script_pubkey)?;
+
+    let secp256k1 = Secp256k1::new();
+    let keypair = Keypair::from_secret_key(&secp256k1, &private_key.inner);
+    let (internal_key, _) = keypair.x_only_public_key();
+    let kind = match spend {
+        TaprootSpend::KeyPath { .. } => SpendKind::TaprootKeyPath,
+        TaprootSpend::ScriptPath { .. } => SpendKind::TaprootScriptPath,
+    };
+    let witness = match spend {
+        TaprootSpend::KeyPath { merkle_root } => {
+            info!("sign taproot key spend");
+            let (tweaked, _) = internal_key.tap_tweak(&secp256k1, merkle_root);
+            if tweaked.to_x_only_public_key() != output_key {
+                bail!("input {} is not committed to the wif key", idx);
+            }
+            let (_, sig) = taproot_key_spend_signature(
+                &secp256k1,
+                &keypair,
+                &mut SighashCache::new(&*tx),
+                &prevouts,
+                idx,
+                sighash.taproot(),
+                merkle_root,
+            )?;
+            Witness::from_slice(&[sig.to_vec()])
+        }
+        TaprootSpend::ScriptPath {
+            script,
+            control_block,
+        } => {
+            info!("sign taproot script spend");
+            if !control_block.verify_taproot_commitment(&secp256k1, output_key, &script) {
+                bail!(
+                    "control block of input {} does not commit to {}",
+                    idx,
+                    script
+                );
+            }
+            let leaf_hash = TapLeafHash::from_script(&script, control_block.leaf_version);
+            let (_, sig) = taproot_script_spend_signature(
+                &secp256k1,
+                &keypair,
+                &mut SighashCache::new(&*tx),
+                &prevouts,
+                idx,
+                leaf_hash,
+                sighash.taproot(),
+            )?;
+            Witness::from_slice(&[sig.to_vec(), script.to_bytes(), control_block.serialize()])
+        }
+    };
+
+    tx.input[idx].witness = witness;
+    Ok(kind)
+}
+
+fn taproot_output_key(script_pubkey: &Script) -> Result<XOnlyPublicKey> {
+    if !script_pubkey.is_p2tr() {
+        bail!("{} is not a taproot output", script_pubkey);
+    }
+    Ok(XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..])?)
+}
+
+fn taproot_script_spend_signature<T: Borrow<Transaction>>(
+    secp256k1: &Secp256k1<All>,
+    keypair: &Keypair,
+    sighash_cache: &mut SighashCache<T>,
+    prevouts: &[TxOut],
+    idx: usize,
+    leaf_hash: TapLeafHash,
+    sighash_type: TapSighashType,
+) -> Result<(TapSighash, Signature)> {
+    let sighash = sighash_cache.taproot_script_spend_signature_hash(
+        idx,
+        &Prevouts::All(prevouts),
+        leaf_hash,
+        sighash_type,
+    )?;
+
+    let msg = secp256k1::Message::from_digest(sighash.to_byte_array());
+    let sig = secp256k1.sign_schnorr(&msg, keypair);
+
+    Ok((
+        sighash,
+        Signature {
+            signature: sig,
+            sighash_type,
+        },
+    ))
+}
+
+fn sign_taproot_key_spend(
+    private_key: PrivateKey,
+    tx: &mut Transaction,
+    prevouts: &[TxOut],
+    idx: usize,
+    sighash_type: TapSighashType,
+) -> Result<TapSighash> {
+    let mut sighash_cache = SighashCache::new(tx);
+    let secp256k1 = Secp256k1::new();
+    let keypair = Keypair::from_secret_key(&secp256k1, &private_key.inner);
+    let (sighash, sig) = taproot_key_spend_signature(
+        &secp256k1,
+        &keypair,
+        &mut sighash_cache,
+        prevouts,
+        idx,
+        sighash_type,
+        None,
+    )?;
+    let witness = sighash_cache
+        .witness_mut(idx)
+        .expect("getting mutable witness reference should work");
+
+    witness.push(sig.to_vec());
+
+    Ok(sighash)
+}
+
+fn taproot_key_spend_signature<T: Borrow<Transaction>>(
+    secp256k1: &Secp256k1<All>,
+    keypair: &Keypair,
+    sighash_cache: &mut SighashCache<T>,
+    prevouts: &[TxOut],
+    idx: usize,
+    sighash_type: TapSighashType,
+    merkle_root: Option<TapNodeHash>,
+) -> Result<(TapSighash, Signature)> {
+    let sighash = sighash_cache.taproot_key_spend_signature_hash(
+        idx,
+        &Prevouts::All(prevouts),
+        sighash_type,
+    )?;
+    let tweaked = keypair.tap_tweak(secp256k1, merkle_root);
+
+    let msg = secp256k1::Message::from_digest_slice(sighash.as_ref())
+        .expect("should be cryptographically secure hash");
+    let sig = secp256k1.sign_schnorr(&msg, &tweaked.to_keypair());
+
+    Ok((
+        sighash,
+        Signature {
+            signature: sig,
+            sighash_type,
+        },
+    ))
+}
+
+/// sign every input of `psbt` locked to the wif key: taproot key path (tweaked with
+/// `tap_merkle_root`), taproot leaves in `tap_scripts` holding the key, or single key ecdsa.
+/// each input is signed with its recorded `sighash_type`, ALL when unset.
+/// finalized inputs are skipped
+pub fn sign_psbt(wif: String, psbt: &mut Psbt) -> Result<SigningReport> {
+    sign_psbt_with(wif, psbt, |_| true)
+}
+
+/// like `sign_psbt` but only touches `inputs`, each of which has to be signable by the wif key
+pub fn sign_psbt_inputs(wif: String, psbt: &mut Psbt, inputs: &[usize]) -> Result<SigningReport> {
+    let report = sign_psbt_with(wif, psbt, |idx| inputs.contains(&idx))?;
+    for idx in inputs.iter() {
+        if !report.indexes().contains(idx) {
+            bail!("psbt input {} was not signed", idx);
+        }
+    }
+    Ok(report)
+}
+
+fn sign_psbt_with(
+    wif: String,
+    psbt: &mut Psbt,
+    wanted: impl Fn(usize) -> bool,
+) -> Result<SigningReport> {
+    if wif.is_empty() {
+        return Err(anyhow!("wif is empty"));
+    }
+
+    let private_key = PrivateKey::from_wif(wif.as_str())?;
+    let secp256k1 = Secp256k1::new();
+    let keypair = Keypair::from_secret_key(&secp256k1, &private_key.inner);
+    let (internal_key, _) = keypair.x_only_public_key();
+    let public_key = private_key.public_key(&secp256k1);
+
+    let prevouts = psbt::prevouts(psbt)?;
+    let mut sighash_cache = SighashCache::new(psbt.unsigned_tx.clone());
+    let mut report = SigningReport::default();
+    for (idx, input) in psbt.inputs.iter_mut().enumerate() {
+        if !wanted(idx) || psbt::is_finalized(input) {
+            continue;
+        }
+        check_single_output(&psbt.unsigned_tx, idx, psbt::sighash(input)?)?;
+
+        let script_pubkey = &prevouts[idx].script_pubkey;
+        let key_path = ScriptBuf::new_p2tr(&secp256k1, internal_key, input.tap_merkle_root);
+        if *script_pubkey == key_path {
+            let sighash_type = match input.sighash_type {
+                Some(ty) => ty.taproot_hash_ty()?,
+                None => TapSighashType::Default,
+            };
+            let (_, sig) = taproot_key_spend_signature(
+                &secp256k1,
+                &keypair,
+                &mut sighash_cache,
+                &prevouts,
+                idx,
+                sighash_type,
+                input.tap_merkle_root,
+            )?;
+            input.tap_internal_key = Some(internal_key);
+            input.tap_key_sig = Some(sig);
+            report.push(idx, SpendKind::TaprootKeyPath, sighash_type.into());
+        } else if script_pubkey.is_p2tr() {
+            let sighash_type = match input.sighash_type {
+                Some(ty) => ty.taproot_hash_ty()?,
+                None => TapSighashType::Default,
+            };
+            let leaves: Vec<TapLeafHash> = input
+                .tap_scripts
+                .values()
+                .filter(|(script, _)| leaf_has_key(script, &internal_key))
+                .map(|(script, leaf_version)| TapLeafHash::from_script(script, *leaf_version))
+                .collect();
+            for leaf_hash in leaves.iter() {
+                let (_, sig) = taproot_script_spend_signature(
+                    &secp256k1,
+                    &keypair,
+                    &mut sighash_cache,
+                    &prevouts,
+                    idx,
+                    *leaf_hash,
+                    sighash_type,
+                )?;
+                input
+                    .tap_script_sigs
+                    .insert((internal_key, *leaf_hash), sig);
+            }
+            if !leaves.is_empty() {
+                report.push(idx, SpendKind::TaprootScriptPath, sighash_type.into());
+            }
+        } else if let Some(spend) = EcdsaSpend::for_key(&public_key, script_pubkey) {
+            let sighash_type = match input.sighash_type {
+                Some(ty) => ty.ecdsa_hash_ty()?,
+                None => EcdsaSighashType::All,
+            };
+            let sig = ecdsa_signature(
+                &secp256k1,
+                &private_key,
+                &mut sighash_cache,
+                &spend,
+                &prevouts[idx],
+                idx,
+                sighash_type,
+            )?;
+            if let EcdsaSpend::P2shP2wpkh(redeem_script) = &spend {
+                input.redeem_script = Some(redeem_script.clone());
+            }
+            input.partial_sigs.insert(public_key, sig);
+            report.push(idx, spend.kind(), sighash_type.into());
+        }
+    }
+
+    if report.inputs.is_empty() {
+        info!("no psbt input is spendable by {}", public_key);
+    }
+    Ok(report)
+}
+
+fn leaf_has_key(script: &Script, key: &XOnlyPublicKey) -> bool {
+    let key = key.serialize();
+    script.instructions().any(|ins| {
+        matches!(ins, Ok(Instruction::PushBytes(bytes)) if bytes.as_bytes() == key.as_slice())
+    })
+}
+
+/// witness of the first single signature leaf we hold a signature for
+fn taproot_script_path_witness(input: &Input) -> Option<Witness> {
+    input
+        .tap_scripts
+        .iter()
+        .find_map(|(control_block, (script, leaf_version))| {
+            let leaf_hash = TapLeafHash::from_script(script, *leaf_version);
+            let (_, sig) = input
+                .tap_script_sigs
+                .iter()
+                .find(|((_, hash), _)| *hash == leaf_hash)?;
+            Some(Witness::from_slice(&[
+                sig.to_vec(),
+                script.to_bytes(),
+                control_block.serialize(),
+            ]))
+        })
+}
+
+/// turn every signed psbt input into its final script_sig and witness,
+/// fails if any input is still unsigned
+pub fn finalize_psbt(psbt: &mut Psbt) -> Result<()> {
+    let prevouts = psbt::prevouts(psbt)?;
+    for (idx, input) in psbt.inputs.iter_mut().enumerate() {
+        if psbt::is_finalized(input) {
+            continue;
+        }
+
+        let (script_sig, witness) = if let Some(sig) = input.tap_key_sig {
+            (ScriptBuf::new(), Witness::from_slice(&[sig.to_vec()]))
+        } else if let Some(witness) = taproot_script_path_witness(input) {
+            (ScriptBuf::new(), witness)
+        } else if let Some((public_key, sig)) = input.partial_sigs.iter().next() {
+            let spend = EcdsaSpend::for_key(public_key, &prevouts[idx].script_pubkey)
+                .ok_or_else(|| anyhow!("psbt input {} is not a single key spend", idx))?;
+            spend.satisfy(sig, public_key)
+        } else {
+            bail!("psbt input {} is not signed", idx);
+        };
+
+        // BIP174: the finalizer drops everything but the utxo and final fields
+        *input = Input {
+            non_witness_utxo: input.non_witness_utxo.take(),
+            witness_utxo: input.witness_utxo.take(),
+            final_script_sig: (!script_sig.is_empty()).then_some(script_sig),
+            final_script_witness: (!witness.is_empty()).then_some(witness),
+            unknown: std::mem::take(&mut input.unknown),
+            ..Default::default()
+        };
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use builder::base::assemble_tx;
+    use datatypes::fixtures;
+
+    fn wallet_utxos(public_key: &PublicKey) -> Vec<types::Utxo> {
+        let p2wpkh = ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash().unwrap());
+        [
+            p2wpkh.clone(),
+            p2wpkh.to_p2sh(),
+            ScriptBuf::new_p2pkh(&public_key.pubkey_hash()),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(vout, script_pubkey)| {
+            fixtures::utxo(&script_pubkey, vout as u32, 10_000 * (vout as u64 + 1))
+        })
+        .collect()
+    }
+
+    fn unsigned_tx(public_key: &PublicKey) -> (Transaction, Vec<TxOut>) {
+        let outputs = vec![TxOut {
+            value: Amount::from_sat(50_000),
+            script_pubkey: ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash().unwrap()),
+        }];
+        assemble_tx(&wallet_utxos(public_key), outputs)
+    }
+
+    #[test]
+    fn signs_single_key_ecdsa_inputs() {
+        let secp = Secp256k1::new();
+        let private_key = PrivateKey::from_slice(&[9u8; 32], Network::Regtest).unwrap();
+        let public_key = private_key.public_key(&secp);
+        let (tx, prevouts) = unsigned_tx(&public_key);
+        let estimated = weight::signed_vsize(&tx, &prevouts);
+
+        let signed = sign_tx(private_key.to_wif(), tx, prevouts.clone(), vec![0, 1, 2]).unwrap();
+        assert!(signed.vsize() <= estimated);
+
+        // p2wpkh and nested p2wpkh carry <sig> <pubkey> in the witness
+        assert!(signed.input[0].script_sig.is_empty());
+        assert_eq!(signed.input[0].witness.len(), 2);
+        assert_eq!(signed.input[1].script_sig.len(), 23);
+        assert_eq!(signed.input[1].witness.len(), 2);
+        // p2pkh carries them in the script_sig
+        assert!(signed.input[2].witness.is_empty());
+
+        let mut cache = SighashCache::new(&signed);
+        for (idx, spend) in [
+            EcdsaSpend::for_key(&public_key, &prevouts[0].script_pubkey),
+            EcdsaSpend::for_key(&public_key, &prevouts[1].script_pubkey),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let spend = spend.unwrap();
+            let sig = ecdsa::Signature::from_slice(&signed.input[idx].witness[0]).unwrap();
+            assert!(sig.to_vec().len() <= weight::ECDSA_SIGNATURE_SIZE);
+            let digest = match spend {
+                EcdsaSpend::P2shP2wpkh(redeem_script) => cache
+                    .p2wpkh_signature_hash(
+                        idx,
+                        &redeem_script,
+                        prevouts[idx].value,
+                        EcdsaSighashType::All,
+                    )
+                    .unwrap(),
+                _ => cache
+                    .p2wpkh_signature_hash(
+                        idx,
+                        &prevouts[idx].script_pubkey,
+                        prevouts[idx].value,
+                        EcdsaSighashType::All,
+                    )
+                    .unwrap(),
+            };
+            let msg = secp256k1::Message::from_digest(digest.to_byte_array());
+            secp.verify_ecdsa(&msg, &sig.signature, &public_key.inner)
+                .unwrap();
+        }
+
+        let legacy = cache
+            .legacy_signature_hash(2, &prevouts[2].script_pubkey, 1)
+            .unwrap();
+        let mut pushes = signed.input[2].script_sig.instructions();
+        let Some(Ok(Instruction::PushBytes(sig))) = pushes.next() else {
+            panic!("p2pkh script_sig starts with the signature");
+        };
+        let sig = ecdsa::Signature::from_slice(sig.as_bytes()).unwrap();
+        let msg = secp256k1::Message::from_digest(legacy.to_byte_array());
+        secp.verify_ecdsa(&msg, &sig.signature, &public_key.inner)
+            .unwrap();
+    }
+
+    #[test]
+    fn psbt_signing_matches_sign_tx() {
+        let secp = Secp256k1::new();
+        let private_key = PrivateKey::from_slice(&[9u8; 32], Network::Regtest).unwrap();
+        let public_key = private_key.public_key(&secp);
+        let (mut tx, prevouts) = unsigned_tx(&public_key);
+        // the p2pkh input spends a real funding tx, as bitcoin core exports it
+        let (funding, _) = assemble_tx(
+            &[fixtures::utxo(&ScriptBuf::new(), 9, 40_000)],
+            vec![prevouts[2].clone()],
+        );
+        tx.input[2].previous_output = bitcoin::OutPoint::new(funding.compute_txid(), 0);
+        assert!(psbt::from_tx(tx.clone(), &prevouts, &[]).is_err());
+
+        let psbt = psbt::from_tx(tx.clone(), &prevouts, &[funding]).unwrap();
+        assert!(psbt.inputs[2].witness_utxo.is_none());
+        let mut psbt = psbt::from_base64(&psbt.to_string()).unwrap();
+        let signed = sign_psbt(private_key.to_wif(), &mut psbt).unwrap();
+        assert_eq!(signed.indexes(), vec![0, 1, 2]);
+        assert!(psbt.inputs[1].redeem_script.is_some());
+        finalize_psbt(&mut psbt).unwrap();
+        assert!(psbt.inputs[2].non_witness_utxo.is_some());
+        verify::verify_tx(&psbt.clone().extract_tx_unchecked_fee_rate(), &prevouts).unwrap();
+
+        let expected = sign_tx(private_key.to_wif(), tx, prevouts, vec![0, 1, 2]).unwrap();
+        assert_eq!(psbt.extract_tx_unchecked_fee_rate(), expected);
+    }
+
+    #[test]
+    fn ecdsa_sighash_flags() {
+        let secp = Secp256k1::new();
+        let private_key = PrivateKey::from_slice(&[9u8; 32], Network::Regtest).unwrap();
+        let public_key = private_key.public_key(&secp);
+        let (tx, prevouts) = unsigned_tx(&public_key);
+
+        let flags = vec![
+            (0, SighashFlag::Single),
+            (1, SighashFlag::AllPlusAnyoneCanPay),
+            (2, SighashFlag::NonePlusAnyoneCanPay),
+        ];
+        let (signed, report) =
+            sign_tx_with_sighash(private_key.to_wif(), tx.clone(), prevouts.clone(), flags)
+                .unwrap();
+        assert_eq!(
+            report.inputs[2],
+            SignedInput {
+                idx: 2,
+                kind: SpendKind::P2pkh,
+                sighash: SighashFlag::NonePlusAnyoneCanPay,
+            }
+        );
+
+        let sig = ecdsa::Signature::from_slice(&signed.input[0].witness[0]).unwrap();
+        assert_eq!(sig.sighash_type, EcdsaSighashType::Single);
+        let digest = SighashCache::new(&signed)
+            .p2wpkh_signature_hash(
+                0,
+                &prevouts[0].script_pubkey,
+                prevouts[0].value,
+                EcdsaSighashType::Single,
+            )
+            .unwrap();
+        let msg = secp256k1::Message::from_digest(digest.to_byte_array());
+        secp.verify_ecdsa(&msg, &sig.signature, &public_key.inner)
+            .unwrap();
+        let sig = ecdsa::Signature::from_slice(&signed.input[1].witness[0]).unwrap();
+        assert_eq!(sig.sighash_type, EcdsaSighashType::AllPlusAnyoneCanPay);
+
+        // there is no output 1 for SIGHASH_SINGLE to commit to
+        let single = vec![(1, SighashFlag::Single)];
+        assert!(sign_tx_with_sighash(private_key.to_wif(), tx, prevouts, single).is_err());
+    }
+
+    #[test]
+    fn taproot_sighash_flags_recorded_in_psbt() {
+        let secp = Secp256k1::new();
+        let private_key = PrivateKey::from_slice(&[7u8; 32], Network::Regtest).unwrap();
+        let (internal_key, _) = private_key.inner.x_only_public_key(&secp);
+        let script_pubkey = ScriptBuf::new_p2tr(&secp, internal_key, None);
+        let utxo = fixtures::utxo(&script_pubkey, 0, 10_000);
+        let outputs = vec![TxOut {
+            value: Amount::from_sat(9_000),
+            script_pubkey,
+        }];
+        let (tx, prevouts) = assemble_tx(&[utxo], outputs);
+
+        let (signed, _) = sign_tx_with_sighash(
+            private_key.to_wif(),
+            tx.clone(),
+            prevouts.clone(),
+            vec![(0, SighashFlag::All)],
+        )
+        .unwrap();
+        // ALL goes out as SIGHASH_DEFAULT without the trailing byte
+        assert_eq!(signed.input[0].witness[0].len(), SCHNORR_SIGNATURE_SIZE);
+
+        let mut psbt = psbt::from_tx(tx, &prevouts, &[]).unwrap();
+        psbt::set_sighash(&mut psbt, 0, SighashFlag::NonePlusAnyoneCanPay).unwrap();
+        let mut psbt = psbt::from_base64(&psbt.to_string()).unwrap();
+        let report = sign_psbt(private_key.to_wif(), &mut psbt).unwrap();
+        assert_eq!(report.inputs[0].sighash, SighashFlag::NonePlusAnyoneCanPay);
+        assert_eq!(report.inputs[0].sighash.to_string(), "NONE|ANYONECANPAY");
+        finalize_psbt(&mut psbt).unwrap();
+
+        let tx = psbt.extract_tx().unwrap();
+        let sig = Signature::from_slice(&tx.input[0].witness[0]).unwrap();
+        assert_eq!(sig.sighash_type, TapSighashType::NonePlusAnyoneCanPay);
+        assert_eq!(sig.to_vec().len(), SCHNORR_SIGNATURE_SIZE + 1);
+        let sighash = SighashCache::new(&tx)
+            .taproot_key_spend_signature_hash(
+                0,
+                &Prevouts::All(&prevouts),
+                TapSighashType::NonePlusAnyoneCanPay,
+            )
+            .unwrap();
+        let (output_key, _) = internal_key.tap_tweak(&secp, None);
+        secp.verify_schnorr(
+            &sig.signature,
+            &secp256k1::Message::from_digest(sighash.to_byte_array()),
+            &output_key.to_x_only_public_key(),
+        )
+        .unwrap();
+    }
+
+    fn leaf(key: &PrivateKey) -> ScriptBuf {
+        let secp = Secp256k1::new();
+        let (xonly, _) = key.inner.x_only_public_key(&secp);
+        Builder::new()
+            .push_x_only_key(&xonly)
+            .push_opcode(OP_CHECKSIG)
+            .into_script()
+    }
+
+    /// two single signature leaves, `internal` is the key path
+    fn tree(internal: &PrivateKey, a: &PrivateKey, b: &PrivateKey) -> TaprootSpendInfo {
+        let secp = Secp256k1::new();
+        let (internal_key, _) = internal.inner.x_only_public_key(&secp);
+        bitcoin::taproot::TaprootBuilder::new()
+            .add_leaf(1, leaf(a))
+            .unwrap()
+            .add_leaf(1, leaf(b))
+            .unwrap()
+            .finalize(&secp, internal_key)
+            .unwrap()
+    }
+
+    fn spend_tree(spend_info: &TaprootSpendInfo) -> (Transaction, Vec<TxOut>) {
+        let utxo = fixtures::utxo(
+            &ScriptBuf::new_p2tr_tweaked(spend_info.output_key()),
+            0,
+            100_000,
+        );
+        let outputs = vec![TxOut {
+            value: Amount::from_sat(99_000),
+            script_pubkey: utxo.script_pubkey.clone(),
+        }];
+        assemble_tx(&[utxo], outputs)
+    }
+
+    #[test]
+    fn taproot_key_path_with_merkle_root() {
+        let secp = Secp256k1::new();
+        let a = PrivateKey::from_slice(&[1u8; 32], Network::Regtest).unwrap();
+        let b = PrivateKey::from_slice(&[2u8; 32], Network::Regtest).unwrap();
+        let spend_info = tree(&a, &a, &b);
+        assert_eq!(
+            Address::p2tr_tweaked(spend_info.output_key(), Network::Regtest).to_string(),
+            "bcrt1p0p2zeuprvzw5k643aw3c203cgv26yh3wm5359hyxe9xpmt6nsppq75syw9"
+        );
+
+        let (mut tx, prevouts) = spend_tree(&spend_info);
+        let untweaked = tx.clone();
+        let no_root = TaprootSpend::KeyPath { merkle_root: None };
+        assert!(sign_taproot(
+            a,
+            &mut tx.clone(),
+            prevouts.clone(),
+            0,
+            no_root,
+            SighashFlag::All
+        )
+        .is_err());
+
+        let spend = TaprootSpend::key_path(&spend_info);
+        sign_taproot(a, &mut tx, prevouts.clone(), 0, spend, SighashFlag::All).unwrap();
+        assert_eq!(tx.input[0].witness.len(), 1);
+        assert_eq!(
+            (tx.weight().to_wu() as usize + 1).div_ceil(4),
+            weight::signed_vsize(&untweaked, &prevouts)
+        );
+
+        let sighash = SighashCache::new(&tx)
+            .taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), TapSighashType::Default)
+            .unwrap();
+        let sig = Signature::from_slice(&tx.input[0].witness[0]).unwrap();
+        secp.verify_schnorr(
+            &sig.signature,
+            &secp256k1::Message::from_digest(sighash.to_byte_array()),
+            &spend_info.output_key().to_x_only_public_key(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn taproot_script_path_with_control_block() {
+        let secp = Secp256k1::new();
+        let a = PrivateKey::from_slice(&[1u8; 32], Network::Regtest).unwrap();
+        let b = PrivateKey::from_slice(&[2u8; 32], Network::Regtest).unwrap();
+        let spend_info = tree(&b, &a, &b);
+        assert_eq!(
+            Address::p2tr_tweaked(spend_info.output_key(), Network::Regtest).to_string(),
+            "bcrt1pvx8ttkeckjgn2advwm2nzc0h0mujvzck53wnu9n98hm6fl80et5qhyzaat"
+        );
+        assert!(TaprootSpend::script_path(&spend_info, ScriptBuf::new()).is_err());
+
+        let (mut tx, prevouts) = spend_tree(&spend_info);
+        let spend = TaprootSpend::script_path(&spend_info, leaf(&a)).unwrap();
+        let TaprootSpend::ScriptPath { control_block, .. } = spend.clone() else {
+            unreachable!()
+        };
+        sign_taproot(a, &mut tx, prevouts.clone(), 0, spend, SighashFlag::All).unwrap();
+
+        let witness = &tx.input[0].witness;
+        assert_eq!(witness.len(), 3);
+        assert_eq!(witness[1], leaf(&a).to_bytes());
+        assert_eq!(witness[2], control_block.serialize());
+        let estimated = weight::Satisfaction::TaprootScriptSpend {
+            stack: vec![64],
+            script: leaf(&a),
+            depth: 1,
+        };
+        assert_eq!(
+            weight::tx_weight(&[estimated], &tx.output),
+            tx.weight().to_wu() as usize
+        );
+
+        let leaf_hash = TapLeafHash::from_script(&leaf(&a), LeafVersion::TapScript);
+        let sighash = SighashCache::new(&tx)
+            .taproot_script_spend_signature_hash(
+                0,
+                &Prevouts::All(&prevouts),
+                leaf_hash,
+                TapSighashType::Default,
+            )
+            .unwrap();
+        let sig = Signature::from_slice(&witness[0]).unwrap();
+        let (a_key, _) = a.inner.x_only_public_key(&secp);
+        secp.verify_schnorr(
+            &sig.signature,
+            &secp256k1::Message::from_digest(sighash.to_byte_array()),
+            &a_key,
+        )
+        .unwrap();
+
+        // a psbt carrying the tree is signed through the same leaf
+        let (tx, prevouts) = spend_tree(&spend_info);
+        let mut psbt = psbt::from_tx(tx, &prevouts, &[]).unwrap();
+        psbt::set_taproot_spend_info(&mut psbt, 0, &spend_info).unwrap();
+        let report = sign_psbt(a.to_wif(), &mut psbt).unwrap();
+        assert_eq!(
+            report.inputs,
+            vec![SignedInput {
+                idx: 0,
+                kind: SpendKind::TaprootScriptPath,
+                sighash: SighashFlag::All,
+            }]
+        );
+        assert!(psbt.inputs[0].tap_key_sig.is_none());
+        finalize_psbt(&mut psbt).unwrap();
+        let witness = psbt.inputs[0].final_script_witness.as_ref().unwrap();
+        assert_eq!(witness[2], control_block.serialize());
+    }
+
+    #[test]
+    fn foreign_script_is_an_error() {
+        let secp = Secp256k1::new();
+        let private_key = PrivateKey::from_slice(&[9u8; 32], Network::Regtest).unwrap();
+        let other = PrivateKey::from_slice(&[8u8; 32], Network::Regtest).unwrap();
+        let (tx, prevouts) = unsigned_tx(&other.public_key(&secp));
+
+        assert!(sign_tx(private_key.to_wif(), tx, prevouts, vec![0]).is_err());
+    }
+}
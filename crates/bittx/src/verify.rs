@@ -0,0 +1,401 @@
+use super::*;
+use bitcoin::consensus::{encode, verify_script};
+use bitcoin::hashes::Hash;
+use bitcoin::opcodes::all::*;
+use bitcoin::opcodes::Opcode;
+use bitcoin::script::{read_scriptbool, read_scriptint, Instruction};
+use bitcoin::sighash::{Prevouts, SighashCache};
+use bitcoin::taproot::{self, LeafVersion, TapLeafHash};
+use error::VerifyError;
+use secp256k1::{Message, Secp256k1, Verification, XOnlyPublicKey};
+use tracing::debug;
+
+/// check every input of `tx` against its prevout, nothing failing here may be broadcast.
+///
+/// libbitcoinconsensus 25 predates taproot and passes any p2tr spend, those inputs are
+/// checked here instead: key path signatures in full, script path spends for the
+/// commitment of the leaf and by running it. leaves using opcodes `run_tapscript` does
+/// not know are rejected as not verified rather than passed
+pub fn verify_tx(tx: &Transaction, prevouts: &[TxOut]) -> Result<(), VerifyError> {
+    if tx.input.len() != prevouts.len() {
+        return Err(VerifyError::PrevoutCount {
+            inputs: tx.input.len(),
+            prevouts: prevouts.len(),
+        });
+    }
+
+    let serialized = encode::serialize(tx);
+    let secp = Secp256k1::verification_only();
+    let mut cache = SighashCache::new(tx);
+    for (idx, (input, prevout)) in tx.input.iter().zip(prevouts).enumerate() {
+        let checked = if prevout.script_pubkey.is_p2tr() {
+            verify_taproot(&secp, tx, &mut cache, idx, prevouts)
+        } else {
+            verify_script(&prevout.script_pubkey, idx, prevout.value, &serialized)
+                .map_err(|e| format!("script rejected by libbitcoinconsensus: {:?}", e))
+        };
+        checked.map_err(|reason| VerifyError::Input {
+            idx,
+            outpoint: input.previous_output,
+            reason,
+        })?;
+    }
+    debug!(
+        "verified {} inputs of {}",
+        tx.input.len(),
+        tx.compute_txid()
+    );
+    Ok(())
+}
+
+/// post `tx` to mempool space once it passes `verify_tx`, mempool space takes anything
+/// and may drop it silently
+pub async fn send_to_mempool(tx: &Transaction, prevouts: &[TxOut]) -> Result<String> {
+    verify_tx(tx, prevouts)?;
+    mempool::tx::post_tx(tx).await
+}
+
+fn verify_taproot<C: Verification>(
+    secp: &Secp256k1<C>,
+    tx: &Transaction,
+    cache: &mut SighashCache<&Transaction>,
+    idx: usize,
+    prevouts: &[TxOut],
+) -> Result<(), String> {
+    let witness = &tx.input[idx].witness;
+    let output_key = XOnlyPublicKey::from_slice(&prevouts[idx].script_pubkey.as_bytes()[2..])
+        .map_err(|e| format!("invalid output key: {}", e))?;
+    let (stack, annex) = witness::split_annex(witness);
+    if annex.is_some() {
+        return Err("annex is non-standard".to_string());
+    }
+
+    match stack.as_slice() {
+        [] => Err("empty witness".to_string()),
+        [signature] => {
+            let signature = taproot::Signature::from_slice(signature)
+                .map_err(|e| format!("invalid signature: {}", e))?;
+            let sighash = cache
+                .taproot_key_spend_signature_hash(
+                    idx,
+                    &Prevouts::All(prevouts),
+                    signature.sighash_type,
+                )
+                .map_err(|e| e.to_string())?;
+            let msg = Message::from_digest(sighash.to_byte_array());
+            secp.verify_schnorr(&signature.signature, &msg, &output_key)
+                .map_err(|_| "key path signature does not verify".to_string())
+        }
+        _ => {
+            let spend = witness::parse_tapscript_spend(witness, Some(&prevouts[idx]))
+                .ok_or_else(|| "invalid control block".to_string())?;
+            if !spend
+                .control_block
+                .verify_taproot_commitment(secp, output_key, &spend.leaf)
+            {
+                return Err("leaf is not committed to by the output key".to_string());
+            }
+            if spend.control_block.leaf_version != LeafVersion::TapScript {
+                return Err(format!(
+                    "script path not verified: leaf version {}",
+                    spend.control_block.leaf_version
+                ));
+            }
+            run_tapscript(secp, tx, cache, idx, prevouts, &spend.leaf, &spend.args)
+        }
+    }
+}
+
+/// run a tapscript leaf against the spending input, covering the opcodes of the leaves
+/// we build and sign: pushes, OP_CSV/OP_CLTV, OP_CHECKSIG and OP_CHECKSIGADD
+fn run_tapscript<C: Verification>(
+    secp: &Secp256k1<C>,
+    tx: &Transaction,
+    cache: &mut SighashCache<&Transaction>,
+    idx: usize,
+    prevouts: &[TxOut],
+    leaf: &Script,
+    args: &[Vec<u8>],
+) -> Result<(), String> {
+    let leaf_hash = TapLeafHash::from_script(leaf, LeafVersion::TapScript);
+    let mut stack = args.to_vec();
+    let mut checksig = |stack: &mut Vec<Vec<u8>>| -> Result<bool, String> {
+        let key = pop(stack)?;
+        let signature = pop(stack)?;
+        if signature.is_empty() {
+            return Ok(false);
+        }
+        let key = XOnlyPublicKey::from_slice(&key)
+            .map_err(|_| "script path not verified: non 32 byte key".to_string())?;
+        let signature = taproot::Signature::from_slice(&signature)
+            .map_err(|e| format!("invalid signature: {}", e))?;
+        let sighash = cache
+            .taproot_script_spend_signature_hash(
+                idx,
+                &Prevouts::All(prevouts),
+                leaf_hash,
+                signature.sighash_type,
+            )
+            .map_err(|e| e.to_string())?;
+        let msg = Message::from_digest(sighash.to_byte_array());
+        secp.verify_schnorr(&signature.signature, &msg, &key)
+            .map_err(|_| "script path signature does not verify".to_string())?;
+        Ok(true)
+    };
+
+    for instruction in leaf.instructions() {
+        let op = match instruction.map_err(|e| format!("undecodable leaf: {}", e))? {
+            Instruction::PushBytes(bytes) => {
+                stack.push(bytes.as_bytes().to_vec());
+                continue;
+            }
+            Instruction::Op(op) => op,
+        };
+        match op {
+            OP_PUSHNUM_NEG1 => stack.push(vec![0x81]),
+            op if (OP_PUSHNUM_1.to_u8()..=OP_PUSHNUM_16.to_u8()).contains(&op.to_u8()) => {
+                stack.push(vec![op.to_u8() - OP_PUSHNUM_1.to_u8() + 1])
+            }
+            OP_DROP => {
+                pop(&mut stack)?;
+            }
+            OP_VERIFY => verify_top(&mut stack, op)?,
+            OP_CSV => check_sequence(tx, idx, top_num(&stack)?)?,
+            OP_CLTV => check_lock_time(tx, idx, top_num(&stack)?)?,
+            OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+                let signed = checksig(&mut stack)?;
+                stack.push(if signed { vec![1] } else { vec![] });
+                if op == OP_CHECKSIGVERIFY {
+                    verify_top(&mut stack, op)?;
+                }
+            }
+            OP_CHECKSIGADD => {
+                let key = pop(&mut stack)?;
+                let n = num(&pop(&mut stack)?)?;
+                stack.push(key);
+                let signed = checksig(&mut stack)?;
+                let mut buf = [0u8; 8];
+                let len = bitcoin::script::write_scriptint(&mut buf, n + i64::from(signed));
+                stack.push(buf[..len].to_vec());
+            }
+            OP_NUMEQUAL | OP_NUMEQUALVERIFY => {
+                let equal = num(&pop(&mut stack)?)? == num(&pop(&mut stack)?)?;
+                stack.push(if equal { vec![1] } else { vec![] });
+                if op == OP_NUMEQUALVERIFY {
+                    verify_top(&mut stack, op)?;
+                }
+            }
+            op => return Err(format!("script path not verified: {:?} is not run", op)),
+        }
+    }
+
+    // BIP 342 clean stack: exactly one true element is left
+    match stack.as_slice() {
+        [top] if read_scriptbool(top) => Ok(()),
+        [_] => Err("leaf leaves false on the stack".to_string()),
+        _ => Err(format!("leaf leaves {} stack elements", stack.len())),
+    }
+}
+
+/// BIP 112, `n` relative blocks or time units must have passed per the input's nSequence
+fn check_sequence(tx: &Transaction, idx: usize, n: i64) -> Result<(), String> {
+    const DISABLE: i64 = 1 << 31;
+    const TYPE: u32 = 1 << 22;
+    const MASK: u32 = 0x0000ffff;
+    if n < 0 {
+        return Err("negative OP_CSV argument".to_string());
+    }
+    if n & DISABLE != 0 {
+        return Ok(());
+    }
+    let sequence = tx.input[idx].sequence.0;
+    let n = n as u32;
+    if tx.version.0 < 2 || sequence & DISABLE as u32 != 0 {
+        return Err("OP_CSV needs a version 2 tx and a relative locktime".to_string());
+    }
+    if n & TYPE != sequence & TYPE || n & MASK > sequence & MASK {
+        return Err(format!(
+            "nSequence {:#x} does not satisfy OP_CSV {:#x}",
+            sequence, n
+        ));
+    }
+    Ok(())
+}
+
+/// BIP 65, the tx locktime must be of the same kind as `n` and reach it
+fn check_lock_time(tx: &Transaction, idx: usize, n: i64) -> Result<(), String> {
+    const THRESHOLD: i64 = 500_000_000;
+    let lock_time = i64::from(tx.lock_time.to_consensus_u32());
+    if n < 0 {
+        return Err("negative OP_CLTV argument".to_string());
+    }
+    if (n < THRESHOLD) != (lock_time < THRESHOLD) || n > lock_time {
+        return Err(format!(
+            "locktime {} does not satisfy OP_CLTV {}",
+            lock_time, n
+        ));
+    }
+    if tx.input[idx].sequence.is_final() {
+        return Err("OP_CLTV needs a non final nSequence".to_string());
+    }
+    Ok(())
+}
+
+fn pop(stack: &mut Vec<Vec<u8>>) -> Result<Vec<u8>, String> {
+    stack.pop().ok_or_else(|| "stack underflow".to_string())
+}
+
+fn verify_top(stack: &mut Vec<Vec<u8>>, op: Opcode) -> Result<(), String> {
+    if read_scriptbool(&pop(stack)?) {
+        Ok(())
+    } else {
+        Err(format!("{:?} fails", op))
+    }
+}
+
+/// timelock arguments may be 5 bytes long, BIP 65/112
+fn top_num(stack: &[Vec<u8>]) -> Result<i64, String> {
+    num(stack.last().ok_or("stack underflow")?)
+}
+
+fn num(bytes: &[u8]) -> Result<i64, String> {
+    if bytes.len() > 5 {
+        return Err("number longer than 5 bytes".to_string());
+    }
+    read_scriptint(bytes).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::PrivateKey;
+    use datatypes::fixtures;
+
+    fn signed(script_pubkey: ScriptBuf, private_key: &PrivateKey) -> (Transaction, Vec<TxOut>) {
+        let utxos = [0, 1].map(|vout| fixtures::utxo(&script_pubkey, vout, 10_000));
+        let outputs = vec![TxOut {
+            value: Amount::from_sat(19_000),
+            script_pubkey,
+        }];
+        let (tx, prevouts) = builder::base::assemble_tx(&utxos, outputs);
+        let tx = signer::sign_tx(private_key.to_wif(), tx, prevouts.clone(), vec![0, 1]).unwrap();
+        (tx, prevouts)
+    }
+
+    #[test]
+    fn reports_the_failing_input() {
+        let secp = Secp256k1::new();
+        let private_key = PrivateKey::from_slice(&[7u8; 32], Network::Regtest).unwrap();
+        let (internal_key, _) = private_key.inner.x_only_public_key(&secp);
+        let p2tr = ScriptBuf::new_p2tr(&secp, internal_key, None);
+        let p2wpkh = ScriptBuf::new_p2wpkh(&private_key.public_key(&secp).wpubkey_hash().unwrap());
+
+        for script_pubkey in [p2tr, p2wpkh] {
+            let (tx, prevouts) = signed(script_pubkey, &private_key);
+            verify_tx(&tx, &prevouts).unwrap();
+
+            // a wrong amount breaks the signature committing to it
+            let mut wrong_amount = prevouts.clone();
+            wrong_amount[1].value = Amount::from_sat(9_999);
+            assert!(matches!(
+                verify_tx(&tx, &wrong_amount),
+                Err(VerifyError::Input { .. })
+            ));
+
+            let mut unsigned = tx.clone();
+            unsigned.input[1].witness = Witness::new();
+            match verify_tx(&unsigned, &prevouts) {
+                Err(VerifyError::Input { idx, .. }) => assert_eq!(idx, 1),
+                res => panic!("unexpected {:?}", res),
+            }
+            assert!(verify_tx(&tx, &prevouts[..1]).is_err());
+        }
+    }
+
+    fn spend_leaf(spend_info: &taproot::TaprootSpendInfo) -> (Transaction, Vec<TxOut>) {
+        let script_pubkey = ScriptBuf::new_p2tr_tweaked(spend_info.output_key());
+        let outputs = vec![TxOut {
+            value: Amount::from_sat(9_000),
+            script_pubkey: script_pubkey.clone(),
+        }];
+        builder::base::assemble_tx(&[fixtures::utxo(&script_pubkey, 0, 10_000)], outputs)
+    }
+
+    fn reason(res: Result<(), VerifyError>) -> String {
+        match res {
+            Err(VerifyError::Input { reason, .. }) => reason,
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+
+    #[test]
+    fn runs_taproot_leaves() {
+        use bitcoin::script::Builder;
+        use bitcoin::taproot::TaprootBuilder;
+        use builder::anchor;
+
+        let secp = Secp256k1::new();
+        let private_key = PrivateKey::from_slice(&[7u8; 32], Network::Regtest).unwrap();
+        let (internal_key, _) = private_key.inner.x_only_public_key(&secp);
+
+        // the anchor leaf only passes 16 blocks in
+        let spend_info = anchor::taproot_anchor_spend_info(internal_key).unwrap();
+        let (mut tx, prevouts) = spend_leaf(&spend_info);
+        tx.input[0].witness = anchor::build_taproot_anchor_witness(internal_key).unwrap();
+        assert!(reason(verify_tx(&tx, &prevouts)).contains("OP_CSV"));
+        tx.input[0].sequence = Sequence(15);
+        assert!(reason(verify_tx(&tx, &prevouts)).contains("OP_CSV"));
+        tx.input[0].sequence = Sequence(16);
+        verify_tx(&tx, &prevouts).unwrap();
+
+        // a signature leaf checks the signature
+        let leaf = Builder::new()
+            .push_x_only_key(&internal_key)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let spend_info = TaprootBuilder::new()
+            .add_leaf(0, leaf.clone())
+            .unwrap()
+            .finalize(&secp, internal_key)
+            .unwrap();
+        let (mut tx, prevouts) = spend_leaf(&spend_info);
+        let spend = signer::TaprootSpend::script_path(&spend_info, leaf).unwrap();
+        signer::sign_taproot(
+            private_key,
+            &mut tx,
+            prevouts.clone(),
+            0,
+            spend,
+            signer::SighashFlag::All,
+        )
+        .unwrap();
+        verify_tx(&tx, &prevouts).unwrap();
+        let mut wrong_amount = prevouts.clone();
+        wrong_amount[0].value = Amount::from_sat(9_999);
+        assert!(reason(verify_tx(&tx, &wrong_amount)).contains("does not verify"));
+        let mut unsigned = tx.clone();
+        let mut witness = unsigned.input[0].witness.to_vec();
+        witness[0] = vec![];
+        unsigned.input[0].witness = Witness::from_slice(&witness);
+        assert!(reason(verify_tx(&unsigned, &prevouts)).contains("false"));
+
+        // nothing outside what is run passes unchecked
+        let leaf = Builder::new()
+            .push_opcode(OP_SHA256)
+            .push_slice([0u8; 32])
+            .push_opcode(OP_EQUAL)
+            .into_script();
+        let spend_info = TaprootBuilder::new()
+            .add_leaf(0, leaf.clone())
+            .unwrap()
+            .finalize(&secp, internal_key)
+            .unwrap();
+        let (mut tx, prevouts) = spend_leaf(&spend_info);
+        let control_block = spend_info
+            .control_block(&(leaf.clone(), LeafVersion::TapScript))
+            .unwrap();
+        tx.input[0].witness =
+            Witness::from_slice(&[vec![1u8], leaf.to_bytes(), control_block.serialize()]);
+        assert!(reason(verify_tx(&tx, &prevouts)).contains("script path not verified"));
+    }
+}
@@ -1,14 +1,28 @@
 use bitcoin::blockdata::transaction::Transaction;
 use bitcoin::consensus::encode::serialize;
 
+/// BIP141: weight is the stripped size times three plus the full size
 pub fn get_tx_vsize(tx: Transaction) -> usize {
-    let non_witness_size = serialize(&tx).len();
-    let witness_size = tx
-        .input
-        .iter()
-        .map(|input| input.witness.len())
-        .sum::<usize>();
-
-    let weight = 3 * non_witness_size + witness_size;
-    (weight + 3) / 4
+    let total_size = serialize(&tx).len();
+    let mut stripped = tx;
+    for input in stripped.input.iter_mut() {
+        input.witness.clear();
+    }
+    let base_size = serialize(&stripped).len();
+
+    let weight = 3 * base_size + total_size;
+    weight.div_ceil(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::consensus::encode::deserialize_hex;
+
+    #[test]
+    fn counts_witness_bytes() {
+        let raw_tx = "0200000000010181dd8a52943508faea2249011bde2dcd04f79f315fdb57cf98eea132606221d90000000000f6108e80044a01000000000000220020a7edf64af17d189aa4fb72d0b470992598b9ccd4aca635de0ac457bba37435984a01000000000000220020ed1cf49beec11792218db3cb0260758ce9694c2dd3771233bec2154040b24cb03046000000000000220020375931514d22204f7b6bc404358ac59b380bd422f1e42a6474eb368057bb5b6e8a7e010000000000220020e4aa6f21574d8694eea0816ed7ecf9d907d105f5cea53a581d51f8a1aa1199370400473044022061e4234dbddcbe867296bec0a046235015782d8931ba30f4f341ea00f4c1d32d02205c5e3320d94a11fc48b6a40f4eafe6d2d971194a5ade591765671f37277d03a8014730440220522bc4661a9078e3bf70b8a46b1efb0404f05ff58a615220fbcc409e06560246022049ee2ef64811c17da6f063da4e92c37c3d4c85461bec19525df878a8ca7605ba014752210267d509df9e48bbe2b2f79efb266784b3cd2b643973ffa587a9ba67d30a5bb9ee21039bac7c4389aa48950d3511eefa7b892ec140013a339768571d4db094f2f26c0d52ae6c57fb20";
+        let tx = deserialize_hex::<Transaction>(raw_tx).unwrap();
+        assert_eq!(get_tx_vsize(tx.clone()), tx.vsize());
+    }
 }
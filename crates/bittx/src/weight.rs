@@ -0,0 +1,276 @@
+use super::*;
+use bitcoin::consensus::encode::VarInt;
+
+/// low-r der signature plus the sighash byte
+pub const ECDSA_SIGNATURE_SIZE: usize = 72;
+pub const COMPRESSED_PUBKEY_SIZE: usize = 33;
+/// schnorr signature plus the sighash byte, only SIGHASH_DEFAULT leaves it out
+pub const SCHNORR_SIGNATURE_WITH_SIGHASH_SIZE: usize = SCHNORR_SIGNATURE_SIZE + 1;
+/// <pubkey> OP_CHECKSIG OP_IFDUP OP_NOTIF OP_16 OP_CSV OP_ENDIF
+pub const ANCHOR_SCRIPT_SIZE: usize = 40;
+
+/// how an input is going to be satisfied once it is signed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Satisfaction {
+    /// one schnorr signature, counted with a sighash byte so any flag fits
+    TaprootKeySpend,
+    /// `stack` element sizes, then the leaf script and a control block for a leaf at `depth`
+    TaprootScriptSpend {
+        stack: Vec<usize>,
+        script: ScriptBuf,
+        depth: usize,
+    },
+    P2wpkh,
+    P2shP2wpkh,
+    P2pkh,
+    /// lightning anchor swept by anyone after 16 blocks, empty signature and the anchor script
+    Anchor,
+    /// keyless pay-to-anchor, spent with an empty witness
+    P2a,
+    /// any witness script, `stack` holds the sizes of the elements pushed before it
+    P2wsh {
+        stack: Vec<usize>,
+        witness_script: ScriptBuf,
+    },
+    /// an input whose script_sig and witness are already known
+    Satisfied {
+        script_sig: ScriptBuf,
+        witness: Witness,
+    },
+}
+
+impl Satisfaction {
+    /// the satisfaction of a wallet owned single key script
+    pub fn for_script_pubkey(script_pubkey: &Script) -> Self {
+        if script_pubkey.is_p2tr() {
+            Satisfaction::TaprootKeySpend
+        } else if builder::p2a::is_p2a(script_pubkey) {
+            Satisfaction::P2a
+        } else if script_pubkey.is_p2pkh() {
+            Satisfaction::P2pkh
+        } else if script_pubkey.is_p2sh() {
+            Satisfaction::P2shP2wpkh
+        } else {
+            Satisfaction::P2wpkh
+        }
+    }
+
+    pub fn script_sig_len(&self) -> usize {
+        match self {
+            Satisfaction::P2pkh => {
+                push_len(ECDSA_SIGNATURE_SIZE) + push_len(COMPRESSED_PUBKEY_SIZE)
+            }
+            // push of the 22 bytes p2wpkh redeem script
+            Satisfaction::P2shP2wpkh => push_len(22),
+            Satisfaction::Satisfied { script_sig, .. } => script_sig.len(),
+            _ => 0,
+        }
+    }
+
+    /// sizes of the witness stack elements
+    pub fn witness_stack(&self) -> Vec<usize> {
+        match self {
+            Satisfaction::TaprootKeySpend => vec![SCHNORR_SIGNATURE_WITH_SIGHASH_SIZE],
+            Satisfaction::TaprootScriptSpend {
+                stack,
+                script,
+                depth,
+            } => {
+                let mut elements = stack.clone();
+                elements.push(script.len());
+                elements.push(33 + 32 * depth);
+                elements
+            }
+            Satisfaction::P2wpkh | Satisfaction::P2shP2wpkh => {
+                vec![ECDSA_SIGNATURE_SIZE, COMPRESSED_PUBKEY_SIZE]
+            }
+            Satisfaction::P2pkh | Satisfaction::P2a => vec![],
+            Satisfaction::Anchor => vec![0, ANCHOR_SCRIPT_SIZE],
+            Satisfaction::P2wsh {
+                stack,
+                witness_script,
+            } => {
+                let mut elements = stack.clone();
+                elements.push(witness_script.len());
+                elements
+            }
+            Satisfaction::Satisfied { witness, .. } => witness.iter().map(|e| e.len()).collect(),
+        }
+    }
+
+    pub fn has_witness(&self) -> bool {
+        !self.witness_stack().is_empty()
+    }
+
+    /// serialized witness size, an empty witness still takes its one byte count
+    pub fn witness_weight(&self) -> usize {
+        let stack = self.witness_stack();
+        VarInt(stack.len() as u64).size()
+            + stack
+                .iter()
+                .map(|len| VarInt(*len as u64).size() + len)
+                .sum::<usize>()
+    }
+
+    /// weight of the input inside a segwit transaction
+    pub fn input_weight(&self) -> usize {
+        let script_sig_len = self.script_sig_len();
+        // outpoint, script_sig, sequence
+        let base = 36 + VarInt(script_sig_len as u64).size() + script_sig_len + 4;
+        base * 4 + self.witness_weight()
+    }
+}
+
+fn push_len(len: usize) -> usize {
+    // every element we push is below OP_PUSHDATA1
+    1 + len
+}
+
+/// vsize an input spending `script_pubkey` adds to a segwit transaction
+pub fn input_vsize(script_pubkey: &Script) -> usize {
+    Satisfaction::for_script_pubkey(script_pubkey)
+        .input_weight()
+        .div_ceil(4)
+}
+
+/// vsize of a transaction carrying `outputs` and no inputs, segwit marker included
+pub fn base_vsize(outputs: &[TxOut]) -> usize {
+    (tx_weight(&[], outputs) + 2).div_ceil(4)
+}
+
+/// weight of the transaction once every input is satisfied
+pub fn tx_weight(inputs: &[Satisfaction], outputs: &[TxOut]) -> usize {
+    // version and lock time
+    let base = 8
+        + VarInt(inputs.len() as u64).size()
+        + VarInt(outputs.len() as u64).size()
+        + outputs.iter().map(|out| out.size()).sum::<usize>();
+    let inputs_weight: usize = inputs.iter().map(|input| input.input_weight()).sum();
+
+    if inputs.iter().any(|input| input.has_witness()) {
+        // segwit marker and flag
+        base * 4 + 2 + inputs_weight
+    } else {
+        // legacy transactions carry no witness count per input
+        base * 4 + inputs_weight - inputs.len()
+    }
+}
+
+pub fn tx_vsize(inputs: &[Satisfaction], outputs: &[TxOut]) -> usize {
+    tx_weight(inputs, outputs).div_ceil(4)
+}
+
+/// vsize of `tx` once signed. inputs already carrying a script_sig or witness
+/// are counted as they are, the others from the script they spend
+pub fn signed_vsize(tx: &Transaction, prevouts: &[TxOut]) -> usize {
+    let inputs: Vec<Satisfaction> = tx
+        .input
+        .iter()
+        .zip(prevouts)
+        .map(|(input, prevout)| {
+            if input.witness.is_empty() && input.script_sig.is_empty() {
+                Satisfaction::for_script_pubkey(&prevout.script_pubkey)
+            } else {
+                Satisfaction::Satisfied {
+                    script_sig: input.script_sig.clone(),
+                    witness: input.witness.clone(),
+                }
+            }
+        })
+        .collect();
+    tx_vsize(&inputs, &tx.output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::consensus::encode::deserialize_hex;
+    use bitcoin::PrivateKey;
+    use builder::anchor;
+    use datatypes::fixtures;
+
+    fn satisfied(tx: &Transaction) -> Vec<Satisfaction> {
+        tx.input
+            .iter()
+            .map(|input| Satisfaction::Satisfied {
+                script_sig: input.script_sig.clone(),
+                witness: input.witness.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn satisfied_inputs_match_consensus_weight() {
+        let raw_tx = "0200000000010181dd8a52943508faea2249011bde2dcd04f79f315fdb57cf98eea132606221d90000000000f6108e80044a01000000000000220020a7edf64af17d189aa4fb72d0b470992598b9ccd4aca635de0ac457bba37435984a01000000000000220020ed1cf49beec11792218db3cb0260758ce9694c2dd3771233bec2154040b24cb03046000000000000220020375931514d22204f7b6bc404358ac59b380bd422f1e42a6474eb368057bb5b6e8a7e010000000000220020e4aa6f21574d8694eea0816ed7ecf9d907d105f5cea53a581d51f8a1aa1199370400473044022061e4234dbddcbe867296bec0a046235015782d8931ba30f4f341ea00f4c1d32d02205c5e3320d94a11fc48b6a40f4eafe6d2d971194a5ade591765671f37277d03a8014730440220522bc4661a9078e3bf70b8a46b1efb0404f05ff58a615220fbcc409e06560246022049ee2ef64811c17da6f063da4e92c37c3d4c85461bec19525df878a8ca7605ba014752210267d509df9e48bbe2b2f79efb266784b3cd2b643973ffa587a9ba67d30a5bb9ee21039bac7c4389aa48950d3511eefa7b892ec140013a339768571d4db094f2f26c0d52ae6c57fb20";
+        let tx = deserialize_hex::<Transaction>(raw_tx).unwrap();
+        assert_eq!(
+            tx_weight(&satisfied(&tx), &tx.output),
+            tx.weight().to_wu() as usize
+        );
+
+        let mut legacy = tx.clone();
+        legacy.input[0].witness = Witness::new();
+        legacy.input[0].script_sig = ScriptBuf::from_bytes(vec![0; 107]);
+        assert_eq!(
+            tx_weight(&satisfied(&legacy), &legacy.output),
+            legacy.weight().to_wu() as usize
+        );
+    }
+
+    #[test]
+    fn anchor_matches_built_witness() {
+        let payload = vec![2u8; 33];
+        let witness = anchor::build_anchor_witness(&payload);
+        let built = Satisfaction::Satisfied {
+            script_sig: ScriptBuf::new(),
+            witness,
+        };
+        assert_eq!(Satisfaction::Anchor.input_weight(), built.input_weight());
+
+        // BOLT3 anchor: push_slice writes the OP_PUSHBYTES_33 prefix of the funding key itself
+        assert_eq!(
+            anchor::build_anchor_redeem_script(&payload).to_hex_string(),
+            format!("21{}ac736460b268", "02".repeat(33))
+        );
+    }
+
+    #[test]
+    fn taproot_key_spend_matches_signed_tx() {
+        let private_key = PrivateKey::from_slice(&[7u8; 32], Network::Regtest).unwrap();
+        let secp = secp256k1::Secp256k1::new();
+        let (internal_key, _) = private_key.inner.x_only_public_key(&secp);
+        let script_pubkey = ScriptBuf::new_p2tr(&secp, internal_key, None);
+        let utxos: Vec<types::Utxo> = (0..3)
+            .map(|vout| fixtures::utxo(&script_pubkey, vout, 10_000))
+            .collect();
+        let outputs = vec![TxOut {
+            value: Amount::from_sat(20_000),
+            script_pubkey: script_pubkey.clone(),
+        }];
+
+        let (tx, prevouts) = builder::base::assemble_tx(&utxos, outputs);
+        let estimated = signed_vsize(&tx, &prevouts);
+        let signed = signer::sign_tx(private_key.to_wif(), tx, prevouts, vec![0, 1, 2]).unwrap();
+        // default sighash signatures come one byte short of the estimate
+        assert_eq!(
+            estimated,
+            (signed.weight().to_wu() as usize + 3).div_ceil(4)
+        );
+    }
+
+    #[test]
+    fn script_spend_and_wallet_scripts() {
+        let leaf = ScriptBuf::from_bytes(vec![0; 34]);
+        let script_spend = Satisfaction::TaprootScriptSpend {
+            stack: vec![64],
+            script: leaf,
+            depth: 1,
+        };
+        // count, sig, leaf script, control block
+        assert_eq!(script_spend.witness_weight(), 1 + 65 + 35 + 66);
+
+        assert_eq!(Satisfaction::P2wpkh.input_weight(), 41 * 4 + 108);
+        assert_eq!(Satisfaction::P2shP2wpkh.input_weight(), 64 * 4 + 108);
+        assert_eq!(Satisfaction::P2pkh.input_weight(), 148 * 4 + 1);
+    }
+}
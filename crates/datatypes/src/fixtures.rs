@@ -0,0 +1,38 @@
+use bitcoin::{
+    absolute::LockTime, hashes::Hash, transaction::Version, Amount, OutPoint, Script, ScriptBuf,
+    Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+};
+
+use crate::types;
+
+/// output `vout` of the all zero txid
+pub fn out_point(vout: u32) -> OutPoint {
+    OutPoint {
+        txid: Txid::all_zeros(),
+        vout,
+    }
+}
+
+/// `sats` paid to `script_pubkey` at `out_point(vout)`
+pub fn utxo(script_pubkey: &Script, vout: u32, sats: u64) -> types::Utxo {
+    types::Utxo {
+        out_point: out_point(vout),
+        value: Amount::from_sat(sats),
+        script_pubkey: script_pubkey.to_owned(),
+    }
+}
+
+/// an unsigned version 2 spend of `input` paying `output`
+pub fn spend(input: OutPoint, sequence: Sequence, output: Vec<TxOut>) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: input,
+            script_sig: ScriptBuf::new(),
+            sequence,
+            witness: Witness::new(),
+        }],
+        output,
+    }
+}
@@ -1 +1,3 @@
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
 pub mod types;
@@ -1,4 +1,5 @@
 use bitcoin::{Amount, OutPoint, ScriptBuf, Transaction, TxOut};
+use std::fmt;
 
 #[derive(Default, Debug, Clone)]
 pub struct Utxo {
@@ -15,18 +16,28 @@ pub struct TransferInfo {
     pub feerate: f32,
 }
 
+#[derive(Clone, Debug)]
+pub struct BatchTransferInfo {
+    pub sender: String,
+    pub recipients: Vec<(String, u64)>,
+    pub op_return: Option<Vec<u8>>,
+    pub feerate: f32,
+}
+
 #[derive(Clone, Debug)]
 pub struct AnchorInfo {
     pub anchor_txid: String,
     pub unlock_bytes: Vec<Vec<u8>>,
     pub unlock_outs: Vec<(TxOut, OutPoint)>,
     pub recipient: String,
+    pub feerate: f32,
 }
 
 #[derive(Clone, Debug)]
 pub struct AnchorsInfo {
     pub details: Vec<AnchorDetail>,
     pub recipient: String,
+    pub feerate: f32,
 }
 
 #[derive(Clone, Debug)]
@@ -38,6 +49,15 @@ pub struct AnchorDetail {
     pub out_value: u64,
 }
 
+/// a keyless pay-to-anchor output
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct P2aDetail {
+    pub out_point: OutPoint,
+    pub value: u64,
+    pub parent_version: i32,
+    pub confirmed: bool,
+}
+
 #[derive(Clone, Debug)]
 pub struct AnchorUnlockInfo {
     pub unlock1: Vec<u8>,
@@ -58,4 +78,87 @@ pub struct UnsignedInfo {
     pub input_idx: u32,
     pub input_out: TxOut,
     pub recipient: String,
+    pub feerate: f32,
+}
+
+/// what a commitment transaction output pays, as far as BOLT3 lets us tell
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommitmentOutputKind {
+    /// the 330 sat anchor spendable by `funding_pubkey`, or by anyone after 16 blocks
+    Anchor {
+        funding_pubkey: Vec<u8>,
+    },
+    ToLocal,
+    ToRemote,
+    /// offered or received by the holder of the commitment
+    Htlc {
+        offered: bool,
+    },
+    /// a p2wsh whose script is not known before it is spent
+    Unknown,
+}
+
+impl fmt::Display for CommitmentOutputKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommitmentOutputKind::Anchor { .. } => write!(f, "anchor"),
+            CommitmentOutputKind::ToLocal => write!(f, "to_local"),
+            CommitmentOutputKind::ToRemote => write!(f, "to_remote"),
+            CommitmentOutputKind::Htlc { offered: true } => write!(f, "offered_htlc"),
+            CommitmentOutputKind::Htlc { offered: false } => write!(f, "received_htlc"),
+            CommitmentOutputKind::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitmentOutput {
+    pub vout: u32,
+    pub value: u64,
+    pub kind: CommitmentOutputKind,
+    /// the witness script, when it is known
+    pub redeem_script_hex: Option<String>,
+}
+
+/// a lightning commitment transaction closing a channel
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelCloseReport {
+    pub txid: String,
+    pub funding_outpoint: OutPoint,
+    /// the 2-of-2 keys in script order, BOLT3 sorts them so it says nothing of who is local
+    pub funding_pubkeys: [Vec<u8>; 2],
+    /// the commitment number xor the channel's obscuring factor
+    pub obscured_commitment_number: u64,
+    pub outputs: Vec<CommitmentOutput>,
+}
+
+impl ChannelCloseReport {
+    pub fn anchors(&self) -> impl Iterator<Item = &CommitmentOutput> {
+        self.outputs
+            .iter()
+            .filter(|output| matches!(output.kind, CommitmentOutputKind::Anchor { .. }))
+    }
+
+    /// option_anchors channels pay an anchor per funding key with a balance
+    pub fn has_anchors(&self) -> bool {
+        self.anchors().next().is_some()
+    }
+}
+
+/// a simple taproot channel commitment, the funding output is a musig2 key spent by key path
+/// so the funding keys stay hidden
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaprootChannelClose {
+    pub txid: String,
+    pub funding_outpoint: OutPoint,
+    pub obscured_commitment_number: u64,
+    pub anchors: Vec<TaprootAnchor>,
+}
+
+/// a p2tr anchor, its internal key is only learned once a leaf of the commitment reveals it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaprootAnchor {
+    pub vout: u32,
+    pub value: u64,
+    pub script_pubkey: ScriptBuf,
 }
@@ -2,7 +2,10 @@ use std::str::FromStr;
 
 use super::*;
 use anyhow::Ok;
-use bitcoin::{consensus::encode::serialize_hex, psbt::serialize, Address, Network, Transaction};
+use bitcoin::{
+    consensus::encode::{deserialize_hex, serialize_hex},
+    Address, Network, Transaction, Txid,
+};
 use datatypes::types;
 use reqwest::Client;
 
@@ -22,7 +25,8 @@ struct Status {
     _block_time: Option<u64>,
 }
 
-pub async fn send_tx(tx: &Transaction) -> Result<String> {
+/// posts `tx` unchecked, broadcasts go through `bittx::verify::send_to_mempool`
+pub async fn post_tx(tx: &Transaction) -> Result<String> {
     let url = format!("{}/api/tx", MEMPOOL_URL);
     let tx_hex = serialize_hex(tx);
     let client = Client::new();
@@ -31,6 +35,18 @@ pub async fn send_tx(tx: &Transaction) -> Result<String> {
     Ok(resp)
 }
 
+/// the raw transaction `txid`, what a legacy input needs as its psbt non_witness_utxo
+pub async fn get_tx(txid: &Txid) -> Result<Transaction> {
+    let url = format!("{}/api/tx/{}/hex", MEMPOOL_URL, txid);
+    debug!("{}", url);
+    let tx_hex = reqwest::get(url).await?.error_for_status()?.text().await?;
+    let tx: Transaction = deserialize_hex(tx_hex.trim())?;
+    if tx.compute_txid() != *txid {
+        return Err(anyhow!("mempool returned another tx for {}", txid));
+    }
+    Ok(tx)
+}
+
 pub async fn gets_uspent_utxo(addr: &str) -> Result<Vec<types::Utxo>> {
     gets_utxo(addr, true).await
 }
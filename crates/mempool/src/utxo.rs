@@ -26,6 +26,11 @@ pub async fn gets_uspent_utxo(addr: &str) -> Result<Vec<types::Utxo>> {
     gets_utxo(addr, true).await
 }
 
+/// unconfirmed utxos included
+pub async fn gets_utxo_with_pending(addr: &str) -> Result<Vec<types::Utxo>> {
+    gets_utxo(addr, false).await
+}
+
 async fn gets_utxo(addr: &str, confirmed: bool) -> Result<Vec<types::Utxo>> {
     let url = format!("{}/api/address/{}/utxo", MEMPOOL_URL, addr);
     info!("{}", url);
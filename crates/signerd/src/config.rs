@@ -0,0 +1,44 @@
+use clap::Parser;
+use serde::Deserialize;
+use std::fs;
+
+#[derive(Parser)]
+struct Cli {
+    /// Path to the configuration file
+    #[arg(short, long, default_value = "signerd.toml")]
+    config: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    /// unix socket watchdog and unsigndog connect to
+    pub socket: String,
+    pub wif: String,
+    pub policy: PolicyConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PolicyConfig {
+    #[serde(default = "default_network")]
+    pub network: String,
+    /// every output has to pay one of these, zero value OP_RETURN outputs aside
+    pub allowed_addresses: Vec<String>,
+    /// in satoshis
+    pub max_fee: u64,
+}
+
+fn default_network() -> String {
+    "bitcoin".to_string()
+}
+
+pub fn read_config() -> Config {
+    let args = Cli::parse();
+    load_config(&args.config)
+}
+
+pub(crate) fn load_config(path: &str) -> Config {
+    let config_content = fs::read_to_string(path).expect("Failed to read config file");
+    let config: Config = toml::from_str(&config_content).expect("Failed to parse config file");
+
+    config
+}
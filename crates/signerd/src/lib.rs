@@ -0,0 +1,10 @@
+pub mod config;
+pub mod policy;
+pub mod server;
+
+use anyhow::{anyhow, bail, Result};
+use bitcoin::psbt::Psbt;
+use bitcoin::{Address, Amount, Network, ScriptBuf};
+use bittx::{psbt, signer};
+use std::str::FromStr;
+use tracing::{error, info, warn};
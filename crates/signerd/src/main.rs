@@ -0,0 +1,11 @@
+use anyhow::Result;
+use signerd::{config, server};
+
+fn main() -> Result<()> {
+    tracing_subscriber::fmt().init();
+
+    let cfg = config::read_config();
+    let daemon = server::Daemon::new(&cfg)?;
+    let listener = server::bind(&cfg.socket)?;
+    daemon.serve(listener)
+}
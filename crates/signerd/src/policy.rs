@@ -0,0 +1,148 @@
+use super::*;
+use config::PolicyConfig;
+use std::collections::HashSet;
+
+/// what the daemon agrees to sign, whatever the requesting process was fed
+#[derive(Debug, Clone)]
+pub struct Policy {
+    allowed_scripts: HashSet<ScriptBuf>,
+    max_fee: Amount,
+}
+
+impl Policy {
+    pub fn new(cfg: &PolicyConfig) -> Result<Self> {
+        let network = Network::from_str(&cfg.network)?;
+        let allowed_scripts = cfg
+            .allowed_addresses
+            .iter()
+            .map(|addr| {
+                Ok(Address::from_str(addr)?
+                    .require_network(network)?
+                    .script_pubkey())
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            allowed_scripts,
+            max_fee: Amount::from_sat(cfg.max_fee),
+        })
+    }
+
+    /// fails with the reason when signing `inputs` of `psbt` would break the policy
+    pub fn check(&self, psbt: &Psbt, inputs: &[usize]) -> Result<()> {
+        if inputs.is_empty() {
+            bail!("no input to sign");
+        }
+
+        for idx in inputs.iter() {
+            let input = psbt
+                .inputs
+                .get(*idx)
+                .ok_or_else(|| anyhow!("psbt has no input {}", idx))?;
+            // anything but ALL lets whoever holds the signature change the outputs
+            let sighash = psbt::sighash(input)?;
+            if sighash != signer::SighashFlag::All {
+                bail!("input {} asks for SIGHASH_{}", idx, sighash);
+            }
+
+            let prevout = psbt::prevout(psbt, *idx)?;
+            if prevout.script_pubkey.is_p2pkh() {
+                // legacy signatures do not commit to the amount, the fee below would be a guess
+                let outpoint = psbt.unsigned_tx.input[*idx].previous_output;
+                let proven = input.non_witness_utxo.as_ref().is_some_and(|prev_tx| {
+                    prev_tx.compute_txid() == outpoint.txid
+                        && prev_tx.output.get(outpoint.vout as usize) == Some(&prevout)
+                });
+                if !proven {
+                    bail!("legacy input {} has no matching non_witness_utxo", idx);
+                }
+            }
+        }
+
+        for (vout, output) in psbt.unsigned_tx.output.iter().enumerate() {
+            if output.script_pubkey.is_op_return() && output.value == Amount::ZERO {
+                continue;
+            }
+            if !self.allowed_scripts.contains(&output.script_pubkey) {
+                bail!(
+                    "output {} pays {} which is not allowed",
+                    vout,
+                    output.script_pubkey
+                );
+            }
+        }
+
+        let input_value: Amount = psbt::prevouts(psbt)?.iter().map(|out| out.value).sum();
+        let output_value: Amount = psbt.unsigned_tx.output.iter().map(|out| out.value).sum();
+        let fee = input_value
+            .checked_sub(output_value)
+            .ok_or_else(|| anyhow!("outputs spend more than the inputs"))?;
+        if fee > self.max_fee {
+            bail!("fee {} is above the {} limit", fee, self.max_fee);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{PrivateKey, TxOut};
+    use bittx::builder::base::assemble_tx;
+    use datatypes::fixtures;
+
+    fn wallet_psbt(script_pubkey: ScriptBuf, pay_to: ScriptBuf) -> Psbt {
+        let utxo = fixtures::utxo(&script_pubkey, 0, 50_000);
+        let outputs = vec![
+            TxOut {
+                value: Amount::from_sat(49_000),
+                script_pubkey: pay_to,
+            },
+            TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::new_op_return([1u8; 4]),
+            },
+        ];
+        let (tx, prevouts) = assemble_tx(&[utxo], outputs);
+        psbt::from_tx(tx, &prevouts, &[]).unwrap()
+    }
+
+    #[test]
+    fn rejects_foreign_outputs_sighash_and_legacy_amounts() {
+        let secp = bitcoin::key::Secp256k1::new();
+        let private_key = PrivateKey::from_slice(&[9u8; 32], Network::Regtest).unwrap();
+        let public_key = private_key.public_key(&secp);
+        let address = Address::p2wpkh(&public_key.try_into().unwrap(), Network::Regtest);
+        let policy = Policy::new(&PolicyConfig {
+            network: "regtest".to_string(),
+            allowed_addresses: vec![address.to_string()],
+            max_fee: 1_000,
+        })
+        .unwrap();
+
+        let mut psbt = wallet_psbt(address.script_pubkey(), address.script_pubkey());
+        policy.check(&psbt, &[0]).unwrap();
+        assert!(policy.check(&psbt, &[]).is_err());
+        assert!(policy.check(&psbt, &[1]).is_err());
+
+        psbt::set_sighash(&mut psbt, 0, signer::SighashFlag::None).unwrap();
+        assert!(policy.check(&psbt, &[0]).is_err());
+
+        let psbt = wallet_psbt(address.script_pubkey(), ScriptBuf::new_op_return([2u8; 4]));
+        assert!(policy.check(&psbt, &[0]).is_err());
+
+        // a legacy prevout claimed without its funding tx
+        let mut psbt = wallet_psbt(address.script_pubkey(), address.script_pubkey());
+        psbt.inputs[0].witness_utxo.as_mut().unwrap().script_pubkey =
+            ScriptBuf::new_p2pkh(&public_key.pubkey_hash());
+        assert!(policy.check(&psbt, &[0]).is_err());
+
+        // the network of the allowed addresses is checked
+        assert!(Policy::new(&PolicyConfig {
+            network: "bitcoin".to_string(),
+            allowed_addresses: vec![address.to_string()],
+            max_fee: 1_000,
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use super::*;
+use config::Config;
+use policy::Policy;
+use signer::backend::{read_message, write_message, SignRequest, SignResponse};
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::time::Duration;
+
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Daemon {
+    wif: String,
+    policy: Policy,
+}
+
+impl fmt::Debug for Daemon {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Daemon")
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Daemon {
+    pub fn new(cfg: &Config) -> Result<Self> {
+        if cfg.wif.is_empty() {
+            bail!("wif is empty");
+        }
+        Ok(Self {
+            wif: cfg.wif.clone(),
+            policy: Policy::new(&cfg.policy)?,
+        })
+    }
+
+    pub fn handle(&self, request: SignRequest) -> SignResponse {
+        match self.sign(request) {
+            Ok(psbt) => SignResponse::Signed { psbt },
+            Err(e) => {
+                warn!("rejected sign request: {}", e);
+                SignResponse::Rejected {
+                    reason: e.to_string(),
+                }
+            }
+        }
+    }
+
+    fn sign(&self, request: SignRequest) -> Result<String> {
+        let mut psbt = psbt::from_base64(&request.psbt)?;
+        self.policy.check(&psbt, &request.inputs)?;
+        let report = signer::sign_psbt_inputs(self.wif.clone(), &mut psbt, &request.inputs)?;
+        info!(
+            "signed inputs {:?} of {}",
+            report.indexes(),
+            psbt.unsigned_tx.compute_txid()
+        );
+        Ok(psbt.to_string())
+    }
+
+    /// answer one request per connection, one connection at a time
+    pub fn serve(&self, listener: UnixListener) -> Result<()> {
+        info!("signer listening on {:?}", listener.local_addr()?);
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = self.serve_client(&stream) {
+                        error!("signer client failed: {}", e);
+                    }
+                }
+                Err(e) => error!("signer accept failed: {}", e),
+            }
+        }
+        Ok(())
+    }
+
+    fn serve_client(&self, stream: &UnixStream) -> Result<()> {
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+        let request = read_message(stream)?;
+        write_message(stream, &self.handle(request))
+    }
+}
+
+/// bind the socket, replacing a stale one, readable by our user only
+pub fn bind(path: impl AsRef<Path>) -> Result<UnixListener> {
+    let path = path.as_ref();
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{PrivateKey, TxOut};
+    use bittx::builder::base::assemble_tx;
+    use bittx::signer::{LocalSigner, RemoteSigner, Signer};
+    use config::PolicyConfig;
+    use datatypes::fixtures;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn remote_signer_matches_local() {
+        let secp = bitcoin::key::Secp256k1::new();
+        let private_key = PrivateKey::from_slice(&[9u8; 32], Network::Regtest).unwrap();
+        let address = Address::p2wpkh(
+            &private_key.public_key(&secp).try_into().unwrap(),
+            Network::Regtest,
+        );
+        let utxo = fixtures::utxo(&address.script_pubkey(), 0, 50_000);
+        let outputs = vec![TxOut {
+            value: Amount::from_sat(49_000),
+            script_pubkey: address.script_pubkey(),
+        }];
+        let (tx, prevouts) = assemble_tx(&[utxo], outputs);
+
+        let cfg = Config {
+            socket: std::env::temp_dir()
+                .join(format!("signerd-{}.sock", std::process::id()))
+                .to_string_lossy()
+                .to_string(),
+            wif: private_key.to_wif(),
+            policy: PolicyConfig {
+                network: "regtest".to_string(),
+                allowed_addresses: vec![address.to_string()],
+                max_fee: 500,
+            },
+        };
+        let listener = bind(&cfg.socket).unwrap();
+        let daemon = Daemon::new(&cfg).unwrap();
+        assert!(!format!("{:?}", daemon).contains(&cfg.wif));
+        thread::spawn(move || daemon.serve(listener));
+
+        let remote = RemoteSigner::new(&cfg.socket);
+        // 1000 sat fee is over the limit
+        let err = remote
+            .sign_tx(tx.clone(), prevouts.clone(), vec![0])
+            .unwrap_err();
+        assert!(err.to_string().contains("limit"));
+
+        let mut tx = tx;
+        tx.output[0].value = Amount::from_sat(49_800);
+        let local = LocalSigner::new(private_key.to_wif());
+        let expected = local
+            .sign_tx(tx.clone(), prevouts.clone(), vec![0])
+            .unwrap();
+        let signed = remote
+            .sign_tx(tx.clone(), prevouts.clone(), vec![0])
+            .unwrap();
+        assert_eq!(signed, expected);
+
+        // async callers wait on the socket off the runtime
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let signed = runtime
+            .block_on(signer::spawn_sign_tx(
+                Arc::new(remote),
+                tx,
+                prevouts,
+                vec![0],
+            ))
+            .unwrap();
+        assert_eq!(signed, expected);
+        fs::remove_file(&cfg.socket).unwrap();
+    }
+}
@@ -92,6 +92,22 @@ impl BtcCli {
         }
     }
 
+    /// sat/vB to confirm within `conf_target` blocks, the mempool min fee when core has no estimate
+    pub fn estimate_fee_rate(&self, conf_target: u16) -> Result<f32> {
+        let estimate = match self.rpc.estimate_smart_fee(conf_target, None) {
+            Ok(res) => res.fee_rate,
+            Err(e) => return Err(anyhow!("estimate smart fee failed: {}", e)),
+        };
+        let per_kvb = match estimate {
+            Some(fee_rate) => fee_rate,
+            None => match self.rpc.get_mempool_info() {
+                Ok(info) => info.mempool_min_fee,
+                Err(e) => return Err(anyhow!("get mempool info failed: {}", e)),
+            },
+        };
+        Ok(per_kvb.to_sat() as f32 / 1000.0)
+    }
+
     // pub fn get_tx(&self, tx: &bitcoin::Transaction) -> Result<Txid> {
     //     match self.rpc.get_transaction(tx) {
     //         Ok(txid) => Ok(txid),
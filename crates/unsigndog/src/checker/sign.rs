@@ -1,4 +1,5 @@
 use super::*;
+use bittx::classifier::{Classification, WitnessClassifier};
 
 pub fn is_multisig_witness(witness: &Witness) -> bool {
     if let Some(redeem_script_bytes) = witness.last() {
@@ -17,56 +18,49 @@ pub fn is_multisig_witness(witness: &Witness) -> bool {
     }
 }
 
-pub fn is_signed_witness(witness: &Witness) -> bool {
-    if let Some(redeem_script_bytes) = witness.last() {
-        let redeem_script = Script::from_bytes(redeem_script_bytes);
-        redeem_script
-            .instructions()
-            .any(|instruction| match instruction {
-                Ok(opcode) => {
-                    opcode == Instruction::Op(OP_CHECKMULTISIG)
-                        || opcode == Instruction::Op(OP_CHECKMULTISIGVERIFY)
-                        || opcode == Instruction::Op(OP_CHECKSIG)
-                        || opcode == Instruction::Op(OP_CHECKSIGVERIFY)
-                }
-                _ => false,
-            })
-    } else {
-        false
-    }
-}
-
 pub struct SignChecker {
     btccli: btcrpc::BtcCli,
+    classifier: WitnessClassifier,
 }
 
 impl SignChecker {
     pub fn new(btccli: btcrpc::BtcCli) -> Self {
-        SignChecker { btccli }
+        SignChecker {
+            btccli,
+            classifier: WitnessClassifier::new(),
+        }
     }
 
-    pub fn check_input_sign(&self, input: &TxIn) -> bool {
+    /// label the input, falling back to its witness alone when the node lacks the prevout
+    pub fn classify_input(&self, input: &TxIn) -> Classification {
         let prev_out = self
             .btccli
             .get_tx_out(&input.previous_output.txid, input.previous_output.vout);
-        match prev_out {
-            Ok(out) => witness::check_input_signed(input, Some(out)),
+        let classification = match prev_out {
+            Ok(out) => self.classifier.classify(input, Some(&out)),
             Err(e) => {
                 error!("get tx output from node failed : {}", e);
-                false
+                self.classifier.classify(input, None)
             }
-        }
+        };
+        debug!(
+            "input {} is {} ({:?}): {}",
+            input.previous_output,
+            classification.spend,
+            classification.confidence,
+            classification.reason
+        );
+        classification
+    }
+
+    pub fn check_input_sign(&self, input: &TxIn) -> bool {
+        !self.classify_input(input).is_unsigned()
     }
 
     pub fn check_sign(&self, tx: Transaction) -> Option<Vec<usize>> {
         let mut idxs = vec![];
         for (idx, input) in tx.input.iter().enumerate() {
-            let prev_out = self
-                .btccli
-                .get_tx_out(&input.previous_output.txid, input.previous_output.vout)
-                .unwrap();
-            let signed = witness::check_input_signed(input, Some(prev_out));
-            if !signed {
+            if !self.check_input_sign(input) {
                 idxs.push(idx);
             }
         }
@@ -82,13 +76,6 @@ impl SignChecker {
             return true;
         }
 
-        let input = &tx.input[0];
-        match self
-            .btccli
-            .get_tx_out(&input.previous_output.txid, input.previous_output.vout)
-        {
-            Ok(prev_out) => witness::check_input_signed(input, Some(prev_out)),
-            Err(_) => true,
-        }
+        self.check_input_sign(&tx.input[0])
     }
 }
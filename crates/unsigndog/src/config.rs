@@ -1,6 +1,12 @@
+use anyhow::{bail, Result};
+use bitcoin::Network;
+use bittx::hd::{Destination, HdWallet, Keychain, Purpose, DEFAULT_LOOKAHEAD};
+use bittx::signer::{LocalSigner, RemoteSigner, Signer};
 use clap::Parser;
 use serde::Deserialize;
 use std::fs;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
 
 #[derive(Parser)]
 struct Cli {
@@ -42,8 +48,97 @@ pub struct TgBot {
 
 #[derive(Deserialize, Debug)]
 pub struct SignConfig {
+    #[serde(default)]
+    pub backend: SignerBackend,
+    /// used by the local backend
+    #[serde(default)]
     pub wif: String,
+    /// xprv or tprv used by the local backend instead of wif, never with the external
+    /// backend which keeps every key out of this process
+    #[serde(default)]
+    pub xprv: String,
+    #[serde(default)]
+    pub purpose: Purpose,
+    #[serde(default)]
+    pub account: u32,
+    #[serde(default = "default_network")]
+    pub network: String,
+    /// next unused receive index of the xprv account
+    #[serde(default = "default_index_file")]
+    pub index_file: String,
+    /// signer daemon socket, used by the external backend
+    #[serde(default)]
+    pub socket: String,
+    /// sweep destination when no xprv is set, one of the signer daemon's allowed
+    /// addresses with the external backend
+    #[serde(default)]
     pub receiver: String,
+    #[serde(skip)]
+    wallet: OnceLock<Arc<HdWallet>>,
+}
+
+fn default_network() -> String {
+    "bitcoin".to_string()
+}
+
+fn default_index_file() -> String {
+    "hd_index".to_string()
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SignerBackend {
+    /// sign in this process with `wif`
+    #[default]
+    Local,
+    /// ask the signer daemon listening on `socket`
+    External,
+}
+
+const EXTERNAL_XPRV: &str = "sign.xprv can not be used with the external backend";
+
+impl SignConfig {
+    pub fn signer(&self) -> Result<Arc<dyn Signer>> {
+        match self.backend {
+            SignerBackend::Local if !self.xprv.is_empty() => {
+                if !self.wif.is_empty() {
+                    bail!("set either sign.wif or sign.xprv");
+                }
+                let wallet = self.hd_wallet()?.expect("xprv is set");
+                Ok(wallet)
+            }
+            SignerBackend::Local if self.wif.is_empty() => bail!("sign.wif is empty"),
+            SignerBackend::Local => Ok(Arc::new(LocalSigner::new(self.wif.clone()))),
+            SignerBackend::External if !self.xprv.is_empty() => bail!(EXTERNAL_XPRV),
+            SignerBackend::External if self.socket.is_empty() => bail!("sign.socket is empty"),
+            SignerBackend::External => Ok(Arc::new(RemoteSigner::new(&self.socket))),
+        }
+    }
+
+    pub fn destination(&self) -> Result<Destination> {
+        match self.hd_wallet()? {
+            Some(wallet) => Ok(Destination::Fresh(wallet)),
+            None if self.receiver.is_empty() => bail!("sign.receiver is empty"),
+            None => Ok(Destination::Fixed(self.receiver.clone())),
+        }
+    }
+
+    /// opened once, everything built from this config shares the receive index
+    fn hd_wallet(&self) -> Result<Option<Arc<HdWallet>>> {
+        if self.xprv.is_empty() {
+            return Ok(None);
+        }
+        if self.backend == SignerBackend::External {
+            bail!(EXTERNAL_XPRV);
+        }
+        if let Some(wallet) = self.wallet.get() {
+            return Ok(Some(wallet.clone()));
+        }
+        let network = Network::from_str(&self.network)?;
+        let keychain = Keychain::new(&self.xprv, self.purpose, self.account, network)?;
+        let wallet = HdWallet::open(keychain, &self.index_file, DEFAULT_LOOKAHEAD)?;
+        Ok(Some(self.wallet.get_or_init(|| Arc::new(wallet)).clone()))
+    }
 }
 
 pub fn read_config() -> Config {
@@ -60,7 +155,7 @@ pub(crate) fn load_config(path: &str) -> Config {
 
 #[cfg(test)]
 mod tests {
-    use super::load_config;
+    use super::*;
 
     #[test]
     fn load_config_test() {
@@ -72,4 +167,18 @@ mod tests {
         println!("Database user: {}", cfg.tgbot.chat_id);
         println!("Database name: {}", cfg.tgbot.tx_topic_id);
     }
+    #[test]
+    fn external_backend_takes_no_xprv() {
+        let config: SignConfig = toml::from_str(
+            r#"
+            backend = "external"
+            socket = "/tmp/signerd.sock"
+            xprv = "tprv8ZgxMBicQKsPd7Uf69XL1XwhmjHopUGep8GuEiJDZmbQz6o58LninorQAfcKZWARbtRtfnLcJ5MQ2AtHcQJCCRUcMRvmDUjyEmNUWwx8UbK"
+            receiver = "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080"
+            "#,
+        )
+        .unwrap();
+        assert!(config.signer().is_err());
+        assert!(config.destination().is_err());
+    }
 }
@@ -1,3 +1,6 @@
 pub mod unsign;
 
 use super::*;
+
+/// blocks the transactions we build aim to confirm in
+pub const FEE_CONF_TARGET: u16 = 2;
@@ -1,22 +1,40 @@
 use super::*;
+use bittx::hd::{AddressScan, Destination};
 use datatypes::types;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 
 pub struct UtxoUpdater {
-    address: String,
+    destination: Destination,
     share_data: Arc<RwLock<Vec<types::Utxo>>>,
+    scan: Mutex<AddressScan>,
 }
 
 impl UtxoUpdater {
     pub fn new(cfg: &config::Config, data: Arc<RwLock<Vec<types::Utxo>>>) -> Self {
         Self {
-            address: cfg.sign.receiver.clone(),
+            destination: cfg.sign.destination().expect("invalid sign config"),
             share_data: data,
+            scan: Mutex::new(AddressScan::default()),
         }
     }
 
     pub async fn update_utxo(&self) -> Result<()> {
-        let utxos = mempool::utxo::gets_uspent_utxo(&self.address).await?;
+        let watched = self.destination.watched_addresses()?;
+        let mut scan = self.scan.lock().await;
+        let mut utxos = vec![];
+        for address in scan.due(&watched) {
+            // an address without utxos is reported as an error
+            let found = match mempool::utxo::gets_uspent_utxo(&address).await {
+                Ok(found) => found,
+                Err(e) => {
+                    debug!("no utxo for {}: {}", address, e);
+                    vec![]
+                }
+            };
+            scan.record(&address, !found.is_empty());
+            utxos.extend(found);
+        }
+        scan.finish(&watched);
         if utxos.is_empty() {
             return Ok(());
         }
@@ -1,6 +1,152 @@
 use super::*;
-use bitcoin::{Block, BlockHash};
-use bitcoincore_rpc::{json::GetRawTransactionResult, Auth, Client, RpcApi};
+use bitcoin::{Block, BlockHash, Wtxid};
+use bitcoincore_rpc::{
+    json::{GetMempoolEntryResult, GetRawTransactionResult},
+    jsonrpc, Auth, Client, RpcApi,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// json-rpc code of an unknown method, nodes before 26.0 have no submitpackage on mainnet
+const RPC_METHOD_NOT_FOUND: i32 = -32601;
+
+/// json-rpc code of an unknown txid or address, what getmempoolentry answers for a tx
+/// not in the mempool
+const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
+
+/// what the node said of one transaction of a package
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageTxResult {
+    pub txid: Txid,
+    pub accepted: bool,
+    pub vsize: Option<u64>,
+    pub fee: Option<Amount>,
+    pub error: Option<String>,
+}
+
+/// the outcome of a package, `txs` in package order
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageResult {
+    pub accepted: bool,
+    pub message: String,
+    pub txs: Vec<PackageTxResult>,
+    /// sat/vB over the transactions the node reported a fee and vsize for
+    pub package_feerate: Option<f64>,
+    /// the node has no package relay, the transactions went out one by one
+    pub sequential: bool,
+}
+
+impl PackageResult {
+    fn new(message: String, txs: Vec<PackageTxResult>, sequential: bool) -> Self {
+        let reported: Vec<(u64, Amount)> = txs
+            .iter()
+            .filter_map(|tx| Some((tx.vsize?, tx.fee?)))
+            .collect();
+        let vsize: u64 = reported.iter().map(|(vsize, _)| vsize).sum();
+        let fee: Amount = reported.iter().map(|(_, fee)| *fee).sum();
+        let package_feerate = (vsize > 0).then(|| fee.to_sat() as f64 / vsize as f64);
+        Self {
+            accepted: txs.iter().all(|tx| tx.accepted),
+            message,
+            txs,
+            package_feerate,
+            sequential,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcPackageFees {
+    #[serde(with = "bitcoin::amount::serde::as_btc")]
+    base: Amount,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcSubmitPackageTx {
+    txid: Txid,
+    vsize: Option<u64>,
+    fees: Option<RpcPackageFees>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcSubmitPackage {
+    #[serde(default)]
+    package_msg: String,
+    #[serde(rename = "tx-results")]
+    tx_results: HashMap<Wtxid, RpcSubmitPackageTx>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcTestMempoolAccept {
+    txid: Txid,
+    #[serde(default)]
+    allowed: bool,
+    vsize: Option<u64>,
+    fees: Option<RpcPackageFees>,
+    #[serde(rename = "package-error")]
+    package_error: Option<String>,
+    #[serde(rename = "reject-reason")]
+    reject_reason: Option<String>,
+}
+
+fn submit_package_result(txs: &[Transaction], res: RpcSubmitPackage) -> PackageResult {
+    let mut results = res.tx_results;
+    let txs = txs
+        .iter()
+        .map(|tx| match results.remove(&tx.compute_wtxid()) {
+            Some(res) => PackageTxResult {
+                txid: res.txid,
+                accepted: res.error.is_none(),
+                vsize: res.vsize,
+                fee: res.fees.map(|fees| fees.base),
+                error: res.error,
+            },
+            None => PackageTxResult {
+                txid: tx.compute_txid(),
+                accepted: false,
+                vsize: None,
+                fee: None,
+                error: Some("missing from the package result".to_string()),
+            },
+        })
+        .collect();
+    PackageResult::new(res.package_msg, txs, false)
+}
+
+fn test_mempool_accept_result(res: Vec<RpcTestMempoolAccept>) -> PackageResult {
+    let message = res
+        .iter()
+        .find_map(|tx| tx.package_error.clone())
+        .unwrap_or_else(|| "success".to_string());
+    let txs = res
+        .into_iter()
+        .map(|tx| PackageTxResult {
+            txid: tx.txid,
+            accepted: tx.allowed,
+            vsize: tx.vsize,
+            fee: tx.fees.map(|fees| fees.base),
+            error: tx.reject_reason.or(tx.package_error),
+        })
+        .collect();
+    PackageResult::new(message, txs, false)
+}
+
+fn is_method_not_found(e: &bitcoincore_rpc::Error) -> bool {
+    matches!(
+        e,
+        bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(rpc_error))
+            if rpc_error.code == RPC_METHOD_NOT_FOUND
+    )
+}
+
+fn is_not_found(e: &bitcoincore_rpc::Error) -> bool {
+    matches!(
+        e,
+        bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(rpc_error))
+            if rpc_error.code == RPC_INVALID_ADDRESS_OR_KEY
+    )
+}
 
 #[derive(Debug)]
 pub struct BtcCli {
@@ -85,6 +231,32 @@ impl BtcCli {
         }
     }
 
+    /// spent by a mined transaction, a spend still in the mempool does not count
+    pub fn get_tx_out_spent_confirmed(&self, txid: &bitcoin::Txid, vout: u32) -> Result<bool> {
+        match self.rpc.get_tx_out(txid, vout, Some(false)) {
+            Ok(Some(_)) => Ok(false),
+            Ok(None) => Ok(true),
+            Err(e) => Err(anyhow!("Error fetching raw transaction: {}", e)),
+        }
+    }
+
+    /// fails when the tx is not in our mempool
+    pub fn get_mempool_entry(&self, txid: &bitcoin::Txid) -> Result<GetMempoolEntryResult> {
+        match self.rpc.get_mempool_entry(txid) {
+            Ok(entry) => Ok(entry),
+            Err(e) => Err(anyhow!("get mempool entry failed: {}", e)),
+        }
+    }
+
+    /// whether `txid` is in our mempool, only lookup failures are errors
+    pub fn in_mempool(&self, txid: &bitcoin::Txid) -> Result<bool> {
+        match self.rpc.get_mempool_entry(txid) {
+            Ok(_) => Ok(true),
+            Err(e) if is_not_found(&e) => Ok(false),
+            Err(e) => Err(anyhow!("get mempool entry failed: {}", e)),
+        }
+    }
+
     pub fn send_tx(&self, tx: &bitcoin::Transaction) -> Result<Txid> {
         match self.rpc.send_raw_transaction(tx) {
             Ok(txid) => Ok(txid),
@@ -92,6 +264,83 @@ impl BtcCli {
         }
     }
 
+    /// submit parents and their child together, `txs` sorted parents first.
+    ///
+    /// a node without submitpackage gets them one by one, a child of a parent below the
+    /// mempool min fee is then rejected
+    pub fn submit_package(&self, txs: &[Transaction]) -> Result<PackageResult> {
+        let hexes: Vec<String> = txs.iter().map(serialize_hex).collect();
+        match self
+            .rpc
+            .call::<RpcSubmitPackage>("submitpackage", &[serde_json::to_value(hexes)?])
+        {
+            Ok(res) => Ok(submit_package_result(txs, res)),
+            Err(e) if is_method_not_found(&e) => {
+                warn!(
+                    "node has no submitpackage, sending {} txs one by one",
+                    txs.len()
+                );
+                Ok(self.send_sequential(txs))
+            }
+            Err(e) => Err(anyhow!("submit package to node failed: {}", e)),
+        }
+    }
+
+    /// testmempoolaccept on the whole package, nothing is broadcast
+    pub fn test_package(&self, txs: &[Transaction]) -> Result<PackageResult> {
+        let hexes: Vec<String> = txs.iter().map(serialize_hex).collect();
+        match self
+            .rpc
+            .call::<Vec<RpcTestMempoolAccept>>("testmempoolaccept", &[serde_json::to_value(hexes)?])
+        {
+            Ok(res) => Ok(test_mempool_accept_result(res)),
+            Err(e) => Err(anyhow!("test package on node failed: {}", e)),
+        }
+    }
+
+    /// stops at the first rejection, the children of a rejected tx can not enter either
+    fn send_sequential(&self, txs: &[Transaction]) -> PackageResult {
+        let mut results = vec![];
+        let mut failed = None;
+        for tx in txs {
+            let error = match &failed {
+                Some(txid) => Some(format!("not sent after {} was rejected", txid)),
+                None => self.send_tx(tx).err().map(|e| e.to_string()),
+            };
+            if error.is_some() && failed.is_none() {
+                failed = Some(tx.compute_txid());
+            }
+            results.push(PackageTxResult {
+                txid: tx.compute_txid(),
+                accepted: error.is_none(),
+                vsize: Some(tx.vsize() as u64),
+                fee: None,
+                error,
+            });
+        }
+        let message = match failed {
+            Some(txid) => format!("sequential broadcast stopped at {}", txid),
+            None => "success".to_string(),
+        };
+        PackageResult::new(message, results, true)
+    }
+
+    /// sat/vB to confirm within `conf_target` blocks, the mempool min fee when core has no estimate
+    pub fn estimate_fee_rate(&self, conf_target: u16) -> Result<f32> {
+        let estimate = match self.rpc.estimate_smart_fee(conf_target, None) {
+            Ok(res) => res.fee_rate,
+            Err(e) => return Err(anyhow!("estimate smart fee failed: {}", e)),
+        };
+        let per_kvb = match estimate {
+            Some(fee_rate) => fee_rate,
+            None => match self.rpc.get_mempool_info() {
+                Ok(info) => info.mempool_min_fee,
+                Err(e) => return Err(anyhow!("get mempool info failed: {}", e)),
+            },
+        };
+        Ok(per_kvb.to_sat() as f32 / 1000.0)
+    }
+
     // pub fn get_tx(&self, tx: &bitcoin::Transaction) -> Result<Txid> {
     //     match self.rpc.get_transaction(tx) {
     //         Ok(txid) => Ok(txid),
@@ -102,11 +351,64 @@ impl BtcCli {
 
 #[cfg(test)]
 mod tests {
-    use super::BtcCli;
+    use super::*;
     use crate::config;
+    use bitcoin::consensus::encode::deserialize_hex;
     use bitcoin::OutPoint;
     use std::str::FromStr;
 
+    #[test]
+    fn reads_package_results() {
+        let raw_tx = "02000000000101caae31cea641ed5b9d93a2764fa25cee5910e2d52de46a11b8d0ae72da05810a0000000000268e2280044a01000000000000220020286b5328b8210524ac31db6be20008f7f5ee5e8ae78ef12be3b3d575effe2a4c4a010000000000002200203cdcdf9c59ea871d62eb671f3b3dda139e2fd657b68e3a21da83d0df368b57b75509010000000000220020f2824d1fd3ddfb51e5f367c53d9c5937a862aa7c5dfae9259885dd166c4b52507687010000000000220020b5469e22001cdb3845ef6cd6e153b025371db3a0597072725a1ebe492e9104ed0400473044022036ab696ccfc27b8864ac37471910e272d6fa2f5b96f83812e4ba1e89189bcb6502206154ec8d7db30799dd448784088bdfae83dc6bb97f74db1e363494ecc5638d920147304402204f4d2d8c62ee4d6ac75ec99bc0a3d110553cdb86179c914b1149123d4da8f2d902201f2b29260df0d53a1542b9c8ef8b7b7f6c4ca365a5082c887668955539ff222501475221025f1432932c9ba37ef4fd060d9f14050325fe433cd2ea49b636797a6cbec80b082102e96bfcb258f0ae9530802fea157137123e0f2f9da70119d0a25822e1ee5f398b52aea6a96b20";
+        let parent = deserialize_hex::<Transaction>(raw_tx).unwrap();
+        let mut child = parent.clone();
+        child.input[0].previous_output = OutPoint {
+            txid: parent.compute_txid(),
+            vout: 2,
+        };
+
+        let res: RpcSubmitPackage = serde_json::from_value(serde_json::json!({
+            "package_msg": "success",
+            "tx-results": {
+                parent.compute_wtxid().to_string(): {
+                    "txid": parent.compute_txid().to_string(),
+                    "vsize": 200,
+                    "fees": {"base": 0.0, "effective-feerate": 0.00005},
+                },
+                child.compute_wtxid().to_string(): {
+                    "txid": child.compute_txid().to_string(),
+                    "vsize": 100,
+                    "fees": {"base": 0.00001500, "effective-feerate": 0.00005},
+                },
+            },
+            "replaced-transactions": [],
+        }))
+        .unwrap();
+        let package = submit_package_result(&[parent.clone(), child.clone()], res);
+        assert!(package.accepted);
+        assert_eq!(package.txs[1].txid, child.compute_txid());
+        assert_eq!(package.txs[1].fee, Some(Amount::from_sat(1_500)));
+        assert_eq!(package.package_feerate, Some(5.0));
+
+        let res: Vec<RpcTestMempoolAccept> = serde_json::from_value(serde_json::json!([
+            {
+                "txid": parent.compute_txid().to_string(),
+                "wtxid": parent.compute_wtxid().to_string(),
+                "package-error": "package-not-child-with-parents",
+            },
+            {
+                "txid": child.compute_txid().to_string(),
+                "wtxid": child.compute_wtxid().to_string(),
+                "package-error": "package-not-child-with-parents",
+            },
+        ]))
+        .unwrap();
+        let package = test_mempool_accept_result(res);
+        assert!(!package.accepted);
+        assert_eq!(package.message, "package-not-child-with-parents");
+        assert_eq!(package.package_feerate, None);
+    }
+
     #[test]
     fn test_get_tx_out() {
         let cfg = config::load_config("./config.toml");
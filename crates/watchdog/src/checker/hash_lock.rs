@@ -0,0 +1,205 @@
+use bittx::hash_lock::{self, HashKind, HashLock};
+use repo::hash_lock::{HashLockScript, PreimageRecord};
+use std::{collections::HashMap, str::FromStr, sync::RwLock};
+
+use super::*;
+
+/// an output anybody can spend, its preimage is already public
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExposedOutput {
+    pub vout: usize,
+    pub lock: HashLock,
+    pub preimage: Vec<u8>,
+}
+
+/// what a transaction taught the index, and what it exposed
+#[derive(Debug, Default)]
+pub struct HashLockReport {
+    pub preimages: Vec<PreimageRecord>,
+    pub scripts: Vec<HashLockScript>,
+    pub exposed: Vec<ExposedOutput>,
+}
+
+/// remembers the preimages seen in witnesses and the outputs they unlock
+pub struct HashLockChecker {
+    preimages: RwLock<HashMap<HashLock, Vec<u8>>>,
+    /// output scripts with a hash lock only spend path
+    scripts: RwLock<HashMap<ScriptBuf, Vec<HashLock>>>,
+}
+
+impl HashLockChecker {
+    /// rebuild the index from what the repo persisted
+    pub fn new(preimages: Vec<PreimageRecord>, scripts: Vec<HashLockScript>) -> Self {
+        let mut preimage_index = HashMap::new();
+        for record in preimages {
+            match decode_lock(&record.kind, &record.hash)
+                .and_then(|lock| Ok((lock, hex::decode(&record.preimage)?)))
+            {
+                Ok((lock, preimage)) => {
+                    preimage_index.insert(lock, preimage);
+                }
+                Err(e) => warn!("skip preimage {}: {}", record.hash, e),
+            }
+        }
+
+        let mut script_index: HashMap<ScriptBuf, Vec<HashLock>> = HashMap::new();
+        for script in scripts {
+            match decode_lock(&script.kind, &script.hash)
+                .and_then(|lock| Ok((lock, ScriptBuf::from_hex(&script.script_pubkey)?)))
+            {
+                Ok((lock, script_pubkey)) => {
+                    script_index.entry(script_pubkey).or_default().push(lock);
+                }
+                Err(e) => warn!("skip hash lock script {}: {}", script.script_pubkey, e),
+            }
+        }
+        info!(
+            "loaded {} preimages and {} hash lock scripts",
+            preimage_index.len(),
+            script_index.len()
+        );
+
+        Self {
+            preimages: RwLock::new(preimage_index),
+            scripts: RwLock::new(script_index),
+        }
+    }
+
+    /// index the preimages and hash lock scripts revealed by the inputs, then look for
+    /// outputs committing to a hash lock whose preimage is already known
+    pub fn check_tx(&self, tx: &Transaction) -> HashLockReport {
+        let txid = tx.compute_txid().to_string();
+        let mut report = HashLockReport::default();
+
+        {
+            let mut preimages = self.preimages.write().unwrap();
+            let mut scripts = self.scripts.write().unwrap();
+            for input in tx.input.iter() {
+                for revealed in hash_lock::revealed_preimages(input) {
+                    if preimages.contains_key(&revealed.lock) {
+                        continue;
+                    }
+                    report.preimages.push(PreimageRecord {
+                        hash: hex::encode(&revealed.lock.hash),
+                        kind: revealed.lock.kind.to_string(),
+                        preimage: hex::encode(&revealed.preimage),
+                        tx_id: txid.clone(),
+                    });
+                    preimages.insert(revealed.lock, revealed.preimage);
+                }
+
+                let Some((script, script_pubkey)) = hash_lock::spent_script(input) else {
+                    continue;
+                };
+                for lock in hash_lock::hash_lock_only_paths(&script) {
+                    let known = scripts.entry(script_pubkey.clone()).or_default();
+                    if known.contains(&lock) {
+                        continue;
+                    }
+                    report.scripts.push(HashLockScript {
+                        script_pubkey: script_pubkey.to_hex_string(),
+                        witness_script: script.to_hex_string(),
+                        hash: hex::encode(&lock.hash),
+                        kind: lock.kind.to_string(),
+                    });
+                    known.push(lock);
+                }
+            }
+        }
+
+        let preimages = self.preimages.read().unwrap();
+        let scripts = self.scripts.read().unwrap();
+        for (vout, output) in tx.output.iter().enumerate() {
+            // bare scripts show their locks, hashed ones only once spent elsewhere
+            let locks = match scripts.get(&output.script_pubkey) {
+                Some(locks) => locks.clone(),
+                None => hash_lock::hash_lock_only_paths(&output.script_pubkey),
+            };
+            for lock in locks {
+                if let Some(preimage) = preimages.get(&lock) {
+                    report.exposed.push(ExposedOutput {
+                        vout,
+                        lock,
+                        preimage: preimage.clone(),
+                    });
+                }
+            }
+        }
+
+        report
+    }
+}
+
+fn decode_lock(kind: &str, hash: &str) -> Result<HashLock> {
+    Ok(HashLock {
+        kind: HashKind::from_str(kind)?,
+        hash: hex::decode(hash)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{
+        absolute::LockTime,
+        hashes::{sha256, Hash},
+        opcodes::all::{OP_EQUAL, OP_SHA256},
+        script::Builder,
+        transaction::Version,
+        Sequence,
+    };
+
+    fn tx(input: Vec<TxIn>, script_pubkey: ScriptBuf) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input,
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey,
+            }],
+        }
+    }
+
+    #[test]
+    fn alerts_on_outputs_with_public_preimages() {
+        let preimage = [5u8; 32];
+        let lock_script = Builder::new()
+            .push_opcode(OP_SHA256)
+            .push_slice(sha256::Hash::hash(&preimage).to_byte_array())
+            .push_opcode(OP_EQUAL)
+            .into_script();
+        let checker = HashLockChecker::new(vec![], vec![]);
+
+        // funding the script reveals nothing yet
+        let funding = tx(vec![], lock_script.to_p2wsh());
+        let report = checker.check_tx(&funding);
+        assert!(report.exposed.is_empty());
+
+        let spend = tx(
+            vec![TxIn {
+                previous_output: OutPoint {
+                    txid: funding.compute_txid(),
+                    vout: 0,
+                },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::from_slice(&[preimage.to_vec(), lock_script.to_bytes()]),
+            }],
+            ScriptBuf::new_op_return([]),
+        );
+        let report = checker.check_tx(&spend);
+        assert_eq!(report.preimages.len(), 1);
+        assert_eq!(report.scripts.len(), 1);
+
+        // reusing the address after the preimage went public
+        let reuse = tx(vec![], lock_script.to_p2wsh());
+        assert_eq!(checker.check_tx(&reuse).exposed[0].preimage, preimage);
+        let bare = tx(vec![], lock_script.clone());
+        assert_eq!(checker.check_tx(&bare).exposed.len(), 1);
+
+        // the index survives a restart through the repo rows
+        let restarted = HashLockChecker::new(report.preimages, report.scripts);
+        assert_eq!(restarted.check_tx(&reuse).exposed.len(), 1);
+    }
+}
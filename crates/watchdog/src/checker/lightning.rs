@@ -1,26 +1,63 @@
 use bitcoin::hex::{Case, DisplayHex};
-use datatypes::types::AnchorUnlockInfo;
-use repo::anchor::AnchorTxOut;
+use bitcoin::OutPoint;
+use datatypes::types::{AnchorUnlockInfo, ChannelCloseReport, CommitmentOutputKind};
+use repo::{anchor::AnchorTxOut, p2a::P2aTxOut};
+use std::{collections::HashMap, sync::RwLock};
 
 use super::*;
 
-pub struct LightningChecker {}
+pub struct LightningChecker {
+    /// known witness scripts by their p2wsh output script, they label commitment outputs
+    scripts: RwLock<HashMap<ScriptBuf, ScriptBuf>>,
+}
 
 impl Default for LightningChecker {
     fn default() -> Self {
-        Self::new()
+        Self::new(vec![])
     }
 }
 
 impl LightningChecker {
-    pub fn new() -> Self {
-        LightningChecker {}
+    pub fn new(scripts: Vec<ScriptBuf>) -> Self {
+        let scripts = scripts
+            .into_iter()
+            .map(|script| (script.to_p2wsh(), script))
+            .collect();
+        LightningChecker {
+            scripts: RwLock::new(scripts),
+        }
     }
 
     pub fn check_input_sign(&self, tx: &Transaction) -> Option<AnchorUnlockInfo> {
         bittx::lightning::check_lightning_channel_close(tx)
     }
 
+    pub fn check_channel_close(&self, tx: &Transaction) -> Option<ChannelCloseReport> {
+        bittx::lightning::decode_commitment(tx, &self.known_scripts(tx))
+    }
+
+    /// remember the commitment output scripts `tx` reveals, with the outputs they spend
+    pub fn learn_scripts(&self, tx: &Transaction) -> Vec<(OutPoint, CommitmentOutputKind)> {
+        let revealed = bittx::lightning::commitment_witness_scripts(tx);
+        let mut scripts = self.scripts.write().unwrap();
+        revealed
+            .into_iter()
+            .map(|(out_point, script, kind)| {
+                scripts.insert(script.to_p2wsh(), script);
+                (out_point, kind)
+            })
+            .collect()
+    }
+
+    /// the known witness scripts paid by the outputs of `tx`
+    fn known_scripts(&self, tx: &Transaction) -> Vec<ScriptBuf> {
+        let scripts = self.scripts.read().unwrap();
+        tx.output
+            .iter()
+            .filter_map(|out| scripts.get(&out.script_pubkey).cloned())
+            .collect()
+    }
+
     pub fn check_anchor(&self, tx: &Transaction) -> Option<Vec<AnchorTxOut>> {
         if let Some(unlock_info) = bittx::lightning::check_lightning_channel_close(tx) {
             info!("find anchor {:?}", unlock_info);
@@ -50,8 +87,81 @@ impl LightningChecker {
         None
     }
 
+    /// the p2tr anchors of a simple taproot channel close, their internal key left empty
+    pub fn check_taproot_anchor(&self, tx: &Transaction) -> Option<Vec<AnchorTxOut>> {
+        let close = bittx::lightning::decode_taproot_commitment(tx)?;
+        info!("find taproot anchor {:?}", close);
+        let anchor_tx_outs = close
+            .anchors
+            .iter()
+            .map(|anchor| AnchorTxOut {
+                tx_id: close.txid.clone(),
+                vout: anchor.vout as i32,
+                value: anchor.value as i64,
+                unlock_info: String::new(),
+                script_pubkey: anchor.script_pubkey.to_hex_string(),
+                spent: false,
+                confirmed_block_height: 0,
+            })
+            .collect();
+
+        Some(anchor_tx_outs)
+    }
+
+    /// the internal keys `tx` reveals for the recorded taproot anchors of the commitments it spends
+    pub fn match_taproot_anchor_keys(
+        &self,
+        tx: &Transaction,
+        anchors: &[AnchorTxOut],
+    ) -> Vec<(usize, String)> {
+        let keys: Vec<_> = tx
+            .input
+            .iter()
+            .flat_map(bittx::lightning::revealed_anchor_keys)
+            .collect();
+        if keys.is_empty() {
+            return vec![];
+        }
+
+        anchors
+            .iter()
+            .enumerate()
+            .filter(|(_, anchor)| anchor.unlock_info.is_empty())
+            .filter_map(|(idx, anchor)| {
+                let script_pubkey = ScriptBuf::from_hex(&anchor.script_pubkey).ok()?;
+                let key = bittx::lightning::taproot_anchor_key(&script_pubkey, &keys)?;
+                Some((idx, key.to_string()))
+            })
+            .collect()
+    }
+
+    /// the keyless pay-to-anchor outputs of `tx`
+    pub fn check_p2a(&self, tx: &Transaction) -> Option<Vec<P2aTxOut>> {
+        let anchors = bittx::builder::p2a::p2a_outputs(tx);
+        if anchors.is_empty() {
+            return None;
+        }
+
+        info!("find p2a anchor {:?}", anchors);
+        let p2a_tx_outs = anchors
+            .iter()
+            .map(|anchor| P2aTxOut {
+                tx_id: anchor.out_point.txid.to_string(),
+                vout: anchor.out_point.vout as i32,
+                value: anchor.value as i64,
+                tx_version: anchor.parent_version,
+                spent: false,
+                confirmed_block_height: 0,
+            })
+            .collect();
+
+        Some(p2a_tx_outs)
+    }
+
     pub fn check_anchor_closed(&self, tx: &Transaction) -> Option<Vec<AnchorTxOut>> {
-        if let Ok(unlock_info) = bittx::lightning::check_lightning_channel_closed(tx) {
+        if let Ok(unlock_info) =
+            bittx::lightning::check_lightning_channel_closed(tx, &self.known_scripts(tx))
+        {
             if unlock_info.is_empty() {
                 return None;
             }
@@ -90,8 +200,68 @@ mod tests {
     fn test_check_anchor() {
         let raw_tx = "02000000000101caae31cea641ed5b9d93a2764fa25cee5910e2d52de46a11b8d0ae72da05810a0000000000268e2280044a01000000000000220020286b5328b8210524ac31db6be20008f7f5ee5e8ae78ef12be3b3d575effe2a4c4a010000000000002200203cdcdf9c59ea871d62eb671f3b3dda139e2fd657b68e3a21da83d0df368b57b75509010000000000220020f2824d1fd3ddfb51e5f367c53d9c5937a862aa7c5dfae9259885dd166c4b52507687010000000000220020b5469e22001cdb3845ef6cd6e153b025371db3a0597072725a1ebe492e9104ed0400473044022036ab696ccfc27b8864ac37471910e272d6fa2f5b96f83812e4ba1e89189bcb6502206154ec8d7db30799dd448784088bdfae83dc6bb97f74db1e363494ecc5638d920147304402204f4d2d8c62ee4d6ac75ec99bc0a3d110553cdb86179c914b1149123d4da8f2d902201f2b29260df0d53a1542b9c8ef8b7b7f6c4ca365a5082c887668955539ff222501475221025f1432932c9ba37ef4fd060d9f14050325fe433cd2ea49b636797a6cbec80b082102e96bfcb258f0ae9530802fea157137123e0f2f9da70119d0a25822e1ee5f398b52aea6a96b20";
         let tx = deserialize_hex::<Transaction>(&raw_tx).unwrap();
-        let res = LightningChecker::new().check_anchor(&tx);
+        let res = LightningChecker::default().check_anchor(&tx);
         assert_eq!(true, res.is_some());
         println!("{:?}", res);
     }
+
+    #[test]
+    fn labels_outputs_with_learned_scripts() {
+        use bitcoin::opcodes::all::*;
+        use bitcoin::script::Builder;
+
+        let raw_tx = "02000000000101caae31cea641ed5b9d93a2764fa25cee5910e2d52de46a11b8d0ae72da05810a0000000000268e2280044a01000000000000220020286b5328b8210524ac31db6be20008f7f5ee5e8ae78ef12be3b3d575effe2a4c4a010000000000002200203cdcdf9c59ea871d62eb671f3b3dda139e2fd657b68e3a21da83d0df368b57b75509010000000000220020f2824d1fd3ddfb51e5f367c53d9c5937a862aa7c5dfae9259885dd166c4b52507687010000000000220020b5469e22001cdb3845ef6cd6e153b025371db3a0597072725a1ebe492e9104ed0400473044022036ab696ccfc27b8864ac37471910e272d6fa2f5b96f83812e4ba1e89189bcb6502206154ec8d7db30799dd448784088bdfae83dc6bb97f74db1e363494ecc5638d920147304402204f4d2d8c62ee4d6ac75ec99bc0a3d110553cdb86179c914b1149123d4da8f2d902201f2b29260df0d53a1542b9c8ef8b7b7f6c4ca365a5082c887668955539ff222501475221025f1432932c9ba37ef4fd060d9f14050325fe433cd2ea49b636797a6cbec80b082102e96bfcb258f0ae9530802fea157137123e0f2f9da70119d0a25822e1ee5f398b52aea6a96b20";
+        let mut commitment = deserialize_hex::<Transaction>(raw_tx).unwrap();
+        let to_remote = Builder::new()
+            .push_slice([2u8; 33])
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .push_int(1)
+            .push_opcode(OP_CSV)
+            .into_script();
+        commitment.output[3].script_pubkey = to_remote.to_p2wsh();
+        let sweep = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(commitment.compute_txid(), 3),
+                script_sig: ScriptBuf::new(),
+                sequence: bitcoin::Sequence(1),
+                witness: bitcoin::Witness::from_slice(&[vec![1u8; 71], to_remote.to_bytes()]),
+            }],
+            output: vec![],
+        };
+
+        let checker = LightningChecker::default();
+        let report = checker.check_channel_close(&commitment).unwrap();
+        assert_eq!(report.outputs[3].kind, CommitmentOutputKind::Unknown);
+
+        assert_eq!(
+            checker.learn_scripts(&sweep),
+            vec![(
+                sweep.input[0].previous_output,
+                CommitmentOutputKind::ToRemote
+            )]
+        );
+        let report = checker.check_channel_close(&commitment).unwrap();
+        assert_eq!(report.outputs[3].kind, CommitmentOutputKind::ToRemote);
+        assert_eq!(report.outputs[2].kind, CommitmentOutputKind::Unknown);
+    }
+
+    #[test]
+    fn test_check_p2a() {
+        let raw_tx = "02000000000101caae31cea641ed5b9d93a2764fa25cee5910e2d52de46a11b8d0ae72da05810a0000000000268e2280044a01000000000000220020286b5328b8210524ac31db6be20008f7f5ee5e8ae78ef12be3b3d575effe2a4c4a010000000000002200203cdcdf9c59ea871d62eb671f3b3dda139e2fd657b68e3a21da83d0df368b57b75509010000000000220020f2824d1fd3ddfb51e5f367c53d9c5937a862aa7c5dfae9259885dd166c4b52507687010000000000220020b5469e22001cdb3845ef6cd6e153b025371db3a0597072725a1ebe492e9104ed0400473044022036ab696ccfc27b8864ac37471910e272d6fa2f5b96f83812e4ba1e89189bcb6502206154ec8d7db30799dd448784088bdfae83dc6bb97f74db1e363494ecc5638d920147304402204f4d2d8c62ee4d6ac75ec99bc0a3d110553cdb86179c914b1149123d4da8f2d902201f2b29260df0d53a1542b9c8ef8b7b7f6c4ca365a5082c887668955539ff222501475221025f1432932c9ba37ef4fd060d9f14050325fe433cd2ea49b636797a6cbec80b082102e96bfcb258f0ae9530802fea157137123e0f2f9da70119d0a25822e1ee5f398b52aea6a96b20";
+        let mut tx = deserialize_hex::<Transaction>(raw_tx).unwrap();
+        let checker = LightningChecker::default();
+        assert!(checker.check_p2a(&tx).is_none());
+
+        tx.version = bitcoin::transaction::Version(3);
+        tx.output.push(TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::new_p2a(),
+        });
+        let res = checker.check_p2a(&tx).unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].vout, 4);
+        assert_eq!(res[0].tx_version, 3);
+    }
 }
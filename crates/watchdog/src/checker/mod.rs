@@ -1,4 +1,6 @@
+pub mod hash_lock;
 pub mod lightning;
+pub mod sighash;
 pub mod sign;
 
 use super::*;
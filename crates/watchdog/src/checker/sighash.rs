@@ -0,0 +1,26 @@
+use bittx::sighash::{self, InputSighash};
+
+use super::*;
+
+pub struct SighashChecker {}
+
+impl Default for SighashChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SighashChecker {
+    pub fn new() -> Self {
+        SighashChecker {}
+    }
+
+    /// inputs whose signatures leave the outputs open, SIGHASH_NONE or a dangling SIGHASH_SINGLE
+    pub fn check_weak_sighash(&self, tx: &Transaction) -> Option<Vec<InputSighash>> {
+        let weak = sighash::weak_inputs(tx);
+        if weak.is_empty() {
+            return None;
+        }
+        Some(weak)
+    }
+}
@@ -1,11 +1,4 @@
 pub mod unsign;
 
 use super::*;
-use crate::{
-    btcrpc::BtcCli,
-    checker::{lightning::LightningChecker, sign::SignChecker},
-    config, lightning,
-    repo::{self, Dao},
-};
-
-use bitcoin::Transaction;
+use crate::{checker::sighash::SighashChecker, config};
@@ -10,15 +10,12 @@ pub mod syncer;
 pub mod utxo;
 
 use anyhow::{anyhow, Result};
-use bitcoin::blockdata::opcodes::all::{
-    OP_CHECKMULTISIG, OP_CHECKMULTISIGVERIFY, OP_CHECKSIG, OP_CHECKSIGVERIFY,
-};
+use bitcoin::blockdata::opcodes::all::{OP_CHECKMULTISIG, OP_CHECKMULTISIGVERIFY};
 use bitcoin::script::Instruction;
 use bitcoin::{
     consensus::{deserialize, encode::serialize_hex},
     Amount, OutPoint, Script, ScriptBuf, Transaction, TxIn, TxOut, Txid, Witness,
 };
-use bittx::witness;
 use repo::Dao;
 use std::{sync::Arc, time::Duration};
 use tgbot::TgBot;
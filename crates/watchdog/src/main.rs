@@ -3,8 +3,8 @@ use std::{sync::Arc, time::Duration};
 
 use tokio::{
     signal::unix::{signal, SignalKind},
-    sync::{broadcast, RwLock},
-    time::sleep,
+    sync::broadcast,
+    time::{interval, sleep},
 };
 
 use tracing::{debug, error, info};
@@ -18,7 +18,13 @@ use tracing_subscriber::{
     util::SubscriberInitExt,
     EnvFilter, Layer, Registry,
 };
-use watchdog::{config, receiver::TxReceiver, sender::tx::TxSender, syncer::Syncer, utxo};
+use watchdog::{
+    config,
+    receiver::TxReceiver,
+    sender::{schedule::AnchorSchedule, tx::TxSender},
+    syncer::Syncer,
+    utxo,
+};
 use zmq::Context;
 
 #[tokio::main]
@@ -28,6 +34,7 @@ async fn main() -> Result<()> {
 
     let (tx, mut rx) = broadcast::channel(1);
     let (tx_send, mut tx_msg_rcv) = broadcast::channel(1024);
+    let (block_send, mut block_rcv) = broadcast::channel(16);
     let mut sigterm = signal(SignalKind::terminate())?;
     let mut sigint = signal(SignalKind::interrupt())?;
     let cfg = config::read_config();
@@ -36,10 +43,10 @@ async fn main() -> Result<()> {
     let zmq_url = format!("tcp://{}:{}", cfg.bitcoin.zmq, cfg.bitcoin.zmq_port);
     subscriber.connect(zmq_url.as_str()).unwrap();
     subscriber.set_subscribe(b"rawtx").unwrap();
+    subscriber.set_subscribe(b"hashblock").unwrap();
 
-    let shared_data = Arc::new(RwLock::new(Vec::new()));
-    let shared_data2 = shared_data.clone();
-    let utxo_updater = utxo::UtxoUpdater::new(&cfg, shared_data2);
+    let fee_pool = Arc::new(utxo::FeeUtxoPool::default());
+    let utxo_updater = utxo::UtxoUpdater::new(&cfg, fee_pool.clone());
     let utxo_update_task = tokio::spawn(async move {
         match utxo_updater.update_utxo().await {
             Ok(_) => {}
@@ -79,6 +86,12 @@ async fn main() -> Result<()> {
                         continue;
                     }
 
+                    if topic.as_str().unwrap() == "hashblock" {
+                        let _hash = subscriber.recv_bytes(0).unwrap();
+                        let _ = block_send.send(());
+                        continue;
+                    }
+
                     if topic.as_str().unwrap() != "rawtx" {
                         continue;
                     }
@@ -109,6 +122,7 @@ async fn main() -> Result<()> {
                 _ = sleep(Duration::from_secs(10)) => {
                     info!("Start Syncer ...");
                     anchor_syncer.sync_anchor().await;
+                    anchor_syncer.sync_p2a().await;
                 }
                 _ = rx2.recv() => {
                     info!("Received SIGTERM, sync task shutting down gracefully...");
@@ -118,30 +132,35 @@ async fn main() -> Result<()> {
         }
     });
 
-    let tx_sender = TxSender::new(&cfg).await;
+    let tx_sender = TxSender::new(&cfg, fee_pool.clone()).await;
     let mut rx3 = tx.subscribe();
-    let shared_data3 = Arc::clone(&shared_data);
     let sender_task = tokio::spawn(async move {
+        let mut anchor_schedule = AnchorSchedule::default();
+        // other branches firing must not push the stuck check back
+        let mut stuck_check = interval(Duration::from_secs(60));
         loop {
             tokio::select! {
-                // _ = sleep(Duration::from_secs(3)) => {
-                //     info!("Start Tx Sender Anchor...");
-                //     let my_utxos = shared_data3.read().await;
-                //     let my_utxos = my_utxos.clone();
-                //     match tx_sender.anchor_closed_task(my_utxos).await{
-                //         Ok(_)=> {},
-                //         Err(e) => {
-                //             error!("Error Sender Anchor: {:?}", e);
-                //         }
-                //     }
-                // }
+                Ok(_) = block_rcv.recv() => {
+                    if let Err(e) = tx_sender.scheduled_anchor_task(&mut anchor_schedule).await {
+                        error!("Error Sender Anchor: {:?}", e);
+                    }
+                    if let Err(e) = tx_sender.p2a_task().await {
+                        error!("Error Sender P2A: {:?}", e);
+                    }
+                }
+                _ = stuck_check.tick() => {
+                    if let Err(e) = tx_sender.bump_stuck_sweeps().await {
+                        error!("Error bumping stuck sweeps: {:?}", e);
+                    }
+                    if let Err(e) = tx_sender.accelerate_stuck_parents().await {
+                        error!("Error accelerating stuck parents: {:?}", e);
+                    }
+                }
                 info = tx_msg_rcv.recv() => {
                     info!("Start Tx Sender Unsigned : {:?}", info);
-                    let my_utxos = shared_data3.read().await;
-                    let my_utxos = my_utxos.clone();
                     match info{
                         Ok((tx,idx))=> {
-                            match tx_sender.send_unsigned_tx(tx, idx, my_utxos).await{
+                            match tx_sender.send_unsigned_tx(tx, idx).await{
                                 Ok(txid)=> {info!("send unsigned transaction : {}",txid);},
                                 Err(err) => {
                                     error!("Error Sender Unsigned task: {:?}", err);
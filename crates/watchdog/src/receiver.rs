@@ -6,8 +6,10 @@ use tracing::debug;
 use super::*;
 use crate::{
     btcrpc::BtcCli,
-    checker::{lightning::LightningChecker, sign::SignChecker},
-    config, lightning,
+    checker::{hash_lock::HashLockChecker, lightning::LightningChecker, sign::SignChecker},
+    config,
+    dog::unsign::SigHashNone,
+    lightning,
 };
 
 pub struct TxReceiver {
@@ -15,6 +17,8 @@ pub struct TxReceiver {
     bot: Arc<TgBot>,
     sign_checker: Arc<SignChecker>,
     lightning_checker: Arc<LightningChecker>,
+    hash_lock_checker: Arc<HashLockChecker>,
+    sighash_dog: Arc<SigHashNone>,
     dao: Arc<repo::Dao>,
 }
 
@@ -23,13 +27,23 @@ impl TxReceiver {
         let bot = TgBot::new(&cfg.tgbot.token, cfg.tgbot.chat_id, cfg.tgbot.tx_topic_id);
         let btccli = BtcCli::new(&cfg.bitcoin.endpoint, &cfg.bitcoin.user, &cfg.bitcoin.pass);
         let sign_checker = SignChecker::new(btccli);
-        let lightning_checker = LightningChecker::new();
         let conn_pool = repo::conn_pool(&cfg.database).await.unwrap();
         let dao = Dao::new(conn_pool);
+        let hash_lock_scripts = dao.get_hash_lock_scripts().await.unwrap();
+        let lightning_checker = LightningChecker::new(
+            hash_lock_scripts
+                .iter()
+                .filter_map(|script| ScriptBuf::from_hex(&script.witness_script).ok())
+                .collect(),
+        );
+        let hash_lock_checker =
+            HashLockChecker::new(dao.get_preimages().await.unwrap(), hash_lock_scripts);
         Self {
             bot: Arc::new(bot),
             sign_checker: Arc::new(sign_checker),
             lightning_checker: Arc::new(lightning_checker),
+            hash_lock_checker: Arc::new(hash_lock_checker),
+            sighash_dog: Arc::new(SigHashNone::new(cfg)),
             dao: Arc::new(dao),
         }
     }
@@ -64,8 +78,24 @@ impl TxReceiver {
                     handle_tx_lightning(tx2, my_bot2, my_lightning_checker, dao).await;
                 });
 
+                let my_hash_lock_checker = self.hash_lock_checker.clone();
+                let tx3 = tx.clone();
+                let my_bot3 = self.bot.clone();
+                let dao3 = self.dao.clone();
+                let hash_lock_handle = tokio::spawn(async {
+                    handle_tx_hash_lock(tx3, my_bot3, my_hash_lock_checker, dao3).await;
+                });
+
+                let sighash_dog = self.sighash_dog.clone();
+                let tx4 = tx.clone();
+                let sighash_handle = tokio::spawn(async move {
+                    sighash_dog.handle_tx(&tx4).await;
+                });
+
                 sign_handle.await?;
                 lightning_handle.await?;
+                hash_lock_handle.await?;
+                sighash_handle.await?;
             }
             Err(e) => {
                 error!(
@@ -175,7 +205,17 @@ async fn handle_tx_thread(
             continue;
         }
 
-        if checker.check_input_sign(input) {
+        let classification = checker.classify_input(input);
+        if !classification.is_unsigned() {
+            continue;
+        }
+
+        // anchors are swept by the syncer, they are no witness to reuse
+        if classification.template == Some("lightning_anchor") {
+            debug!(
+                "Received transaction hash: {}. Swept Lightning Anchor",
+                txid
+            );
             continue;
         }
 
@@ -204,13 +244,36 @@ async fn handle_tx_lightning(
     checker: Arc<LightningChecker>,
     dao: Arc<Dao>,
 ) {
+    if !tx.is_coinbase() {
+        handle_taproot_anchor_keys(&tx, &checker, &dao).await;
+        for (out_point, kind) in checker.learn_scripts(&tx) {
+            info!("commitment output {} swept as {}", out_point, kind);
+        }
+    }
+    handle_tx_p2a(&tx, &checker, &dao).await;
+
     let txid = tx.compute_txid();
     let input_idx = 0;
-    let lightning_info = checker.check_anchor_closed(&tx);
+    let lightning_info = checker
+        .check_anchor_closed(&tx)
+        .or_else(|| checker.check_taproot_anchor(&tx));
     if lightning_info.is_none() {
         return;
     }
     let infos = lightning_info.unwrap();
+    let outputs = match checker.check_channel_close(&tx) {
+        Some(report) => report
+            .outputs
+            .iter()
+            .map(|output| format!("{}:{}", output.vout, output.kind))
+            .collect::<Vec<_>>()
+            .join(","),
+        None => infos
+            .iter()
+            .map(|info| format!("{}:taproot_anchor", info.vout))
+            .collect::<Vec<_>>()
+            .join(","),
+    };
 
     for info in infos {
         match dao.insert_anchor_tx_out(info).await {
@@ -223,9 +286,114 @@ async fn handle_tx_lightning(
         "Received transaction hash: {}, idx : {}, lightning channel closed",
         txid, input_idx
     );
-    let msg = format!("Lightning channel close, txid:{}, idx:{}", txid, input_idx,);
+    let msg = format!(
+        "Lightning channel close, txid:{}, idx:{}, outputs:{}",
+        txid, input_idx, outputs
+    );
     match bot.send_msg_to_topic(msg.as_str()).await {
         Ok(_) => {}
         Err(e) => error!("send msg to tg failed. {}", e),
     };
 }
+
+/// pay-to-anchor outputs are free for anyone to spend, keep them for the sweeper.
+/// every TRUC transaction may carry one, so they are only logged
+async fn handle_tx_p2a(tx: &Transaction, checker: &LightningChecker, dao: &Dao) {
+    let Some(outs) = checker.check_p2a(tx) else {
+        return;
+    };
+
+    let txid = tx.compute_txid();
+    for out in outs {
+        info!(
+            "Received transaction hash: {}, vout : {}, p2a anchor, value : {}",
+            txid, out.vout, out.value
+        );
+        match dao.insert_p2a_tx_out(out).await {
+            Ok(_) => {}
+            Err(e) => error!("Error Insert p2a tx out: {:?}", e),
+        }
+    }
+}
+
+/// a to_local or to_remote sweep of a taproot channel names the keys its anchors need
+async fn handle_taproot_anchor_keys(tx: &Transaction, checker: &LightningChecker, dao: &Dao) {
+    let mut commitments = vec![];
+    for input in tx.input.iter() {
+        let txid = input.previous_output.txid;
+        if commitments.contains(&txid) || bittx::lightning::revealed_anchor_keys(input).is_empty() {
+            continue;
+        }
+        commitments.push(txid);
+    }
+
+    for commitment in commitments {
+        let anchors = match dao.get_anchor_tx_out_by_tx_id(commitment.to_string()).await {
+            Ok(anchors) => anchors,
+            Err(e) => {
+                error!("get anchor tx out {} failed: {:?}", commitment, e);
+                continue;
+            }
+        };
+        for (idx, key) in checker.match_taproot_anchor_keys(tx, &anchors) {
+            let anchor = &anchors[idx];
+            info!(
+                "taproot anchor {}:{} internal key revealed by {}",
+                anchor.tx_id,
+                anchor.vout,
+                tx.compute_txid()
+            );
+            match dao
+                .update_anchor_tx_out_unlock_info(anchor.tx_id.clone(), anchor.vout, key)
+                .await
+            {
+                Ok(_) => {}
+                Err(e) => error!("update_anchor_tx_out_unlock_info failed : {}", e),
+            }
+        }
+    }
+}
+
+async fn handle_tx_hash_lock(
+    tx: Transaction,
+    bot: Arc<TgBot>,
+    checker: Arc<HashLockChecker>,
+    dao: Arc<Dao>,
+) {
+    if tx.is_coinbase() {
+        return;
+    }
+
+    let txid = tx.compute_txid();
+    let report = checker.check_tx(&tx);
+    for preimage in report.preimages {
+        match dao.insert_preimage(preimage).await {
+            Ok(_) => {}
+            Err(e) => error!("Error Insert preimage: {:?}", e),
+        }
+    }
+    for script in report.scripts {
+        match dao.insert_hash_lock_script(script).await {
+            Ok(_) => {}
+            Err(e) => error!("Error Insert hash lock script: {:?}", e),
+        }
+    }
+
+    for exposed in report.exposed {
+        warn!(
+            "Received transaction hash: {}, vout : {}, {} preimage already public",
+            txid, exposed.vout, exposed.lock.kind
+        );
+        let msg = format!(
+            "Hash lock output with public preimage, txid:{}, vout:{}, {}:{}",
+            txid,
+            exposed.vout,
+            exposed.lock.kind,
+            hex::encode(&exposed.lock.hash)
+        );
+        match bot.send_msg_to_topic(msg.as_str()).await {
+            Ok(_) => {}
+            Err(e) => error!("send msg to tg failed. {}", e),
+        };
+    }
+}
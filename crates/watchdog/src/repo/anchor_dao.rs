@@ -40,7 +40,7 @@ impl Dao {
     ) -> Result<Vec<AnchorTxOut>, sqlx::Error> {
         let expired_block_height = current_block_height - 14;
         let resp_data: Vec<AnchorTxOut> = sqlx::query_as(
-            "SELECT * FROM anchor_tx_out WHERE spent = $1 and confirmed_block_height > $2 and confirmed_block_height <= $3",
+            "SELECT * FROM anchor_tx_out WHERE spent = $1 and confirmed_block_height > $2 and confirmed_block_height <= $3 and unlock_info <> ''",
         )
         .bind(false)
         .bind(0)
@@ -107,6 +107,26 @@ impl Dao {
         Ok(rows_affected)
     }
 
+    /// taproot anchors are recorded before their internal key is known
+    pub async fn update_anchor_tx_out_unlock_info(
+        &self,
+        txid: String,
+        vout: i32,
+        unlock_info: String,
+    ) -> Result<u64> {
+        let rows_affected = sqlx::query!(
+            "UPDATE anchor_tx_out SET unlock_info = $1 WHERE tx_id = $2 and vout = $3",
+            unlock_info,
+            txid,
+            vout
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected)
+    }
+
     pub async fn update_anchor_tx_out_spent(&self, txid: String, vout: i32) -> Result<u64> {
         let rows_affected = sqlx::query!(
             "UPDATE anchor_tx_out SET spent = $1 WHERE tx_id = $2 and vout = $3",
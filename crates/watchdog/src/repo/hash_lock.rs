@@ -0,0 +1,20 @@
+use super::*;
+
+/// a preimage published in a spending witness
+#[derive(Debug, PartialEq, Default, FromRow)]
+pub struct PreimageRecord {
+    pub hash: String,
+    pub kind: String,
+    pub preimage: String,
+    /// the transaction revealing it
+    pub tx_id: String,
+}
+
+/// an output script hiding a spend path guarded by a hash lock only
+#[derive(Debug, PartialEq, Default, FromRow)]
+pub struct HashLockScript {
+    pub script_pubkey: String,
+    pub witness_script: String,
+    pub hash: String,
+    pub kind: String,
+}
@@ -0,0 +1,45 @@
+use hash_lock::{HashLockScript, PreimageRecord};
+
+use super::*;
+
+impl Dao {
+    pub async fn insert_preimage(&self, record: PreimageRecord) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT INTO preimage (hash, kind, preimage, tx_id) SELECT $1, $2, $3, $4 WHERE NOT EXISTS (SELECT 1 FROM preimage WHERE hash = $1 and kind = $2)")
+            .bind(&record.hash)
+            .bind(&record.kind)
+            .bind(&record.preimage)
+            .bind(&record.tx_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_preimages(&self) -> Result<Vec<PreimageRecord>, sqlx::Error> {
+        let resp_data: Vec<PreimageRecord> = sqlx::query_as("SELECT * FROM preimage")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(resp_data)
+    }
+
+    pub async fn insert_hash_lock_script(&self, script: HashLockScript) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT INTO hash_lock_script (script_pubkey, witness_script, hash, kind) SELECT $1, $2, $3, $4 WHERE NOT EXISTS (SELECT 1 FROM hash_lock_script WHERE script_pubkey = $1 and hash = $3 and kind = $4)")
+            .bind(&script.script_pubkey)
+            .bind(&script.witness_script)
+            .bind(&script.hash)
+            .bind(&script.kind)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_hash_lock_scripts(&self) -> Result<Vec<HashLockScript>, sqlx::Error> {
+        let resp_data: Vec<HashLockScript> = sqlx::query_as("SELECT * FROM hash_lock_script")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(resp_data)
+    }
+}
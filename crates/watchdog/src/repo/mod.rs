@@ -1,7 +1,13 @@
 pub mod anchor;
 pub mod anchor_dao;
+pub mod hash_lock;
+pub mod hash_lock_dao;
 pub mod indexer;
 pub mod indexer_dao;
+pub mod p2a;
+pub mod p2a_dao;
+pub mod sweep;
+pub mod sweep_dao;
 
 use super::*;
 use crate::config;
@@ -34,6 +40,52 @@ pub async fn create_table(pool: Pool<Postgres>) -> Result<(), sqlx::Error> {
     )
     .await?;
 
+    pool.execute(
+        "CREATE TABLE IF NOT EXISTS sweep_tx (
+            tx_id TEXT,
+            tx_hex TEXT,
+            prevouts_hex TEXT,
+            sign_idx TEXT,
+            change_vout INTEGER,
+            broadcast_height BIGINT,
+            replaced_by TEXT,
+            confirmed BOOLEAN
+        )",
+    )
+    .await?;
+
+    pool.execute(
+        "CREATE TABLE IF NOT EXISTS preimage (
+            hash TEXT,
+            kind TEXT,
+            preimage TEXT,
+            tx_id TEXT
+        )",
+    )
+    .await?;
+
+    pool.execute(
+        "CREATE TABLE IF NOT EXISTS hash_lock_script (
+            script_pubkey TEXT,
+            witness_script TEXT,
+            hash TEXT,
+            kind TEXT
+        )",
+    )
+    .await?;
+
+    pool.execute(
+        "CREATE TABLE IF NOT EXISTS p2a_tx_out (
+            tx_id TEXT,
+            vout INTEGER,
+            value BIGINT,
+            tx_version INTEGER,
+            spent BOOLEAN,
+            confirmed_block_height BIGINT
+        )",
+    )
+    .await?;
+
     Ok(())
 }
 
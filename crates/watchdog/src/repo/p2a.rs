@@ -0,0 +1,13 @@
+use super::*;
+
+/// a keyless pay-to-anchor output, anyone can spend it with an empty witness
+#[derive(Debug, PartialEq, Default, FromRow)]
+pub struct P2aTxOut {
+    pub tx_id: String,
+    pub vout: i32,
+    pub value: i64,
+    /// 3 for TRUC parents, their child has to be TRUC too
+    pub tx_version: i32,
+    pub spent: bool,
+    pub confirmed_block_height: i64,
+}
@@ -0,0 +1,58 @@
+use p2a::P2aTxOut;
+
+use super::*;
+
+impl Dao {
+    pub async fn insert_p2a_tx_out(&self, out: P2aTxOut) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT INTO p2a_tx_out (tx_id, vout, value, tx_version, spent, confirmed_block_height) SELECT $1, $2, $3, $4, $5, $6 WHERE NOT EXISTS (SELECT 1 FROM p2a_tx_out WHERE tx_id = $1 and vout = $2)")
+            .bind(&out.tx_id)
+            .bind(out.vout)
+            .bind(out.value)
+            .bind(out.tx_version)
+            .bind(out.spent)
+            .bind(out.confirmed_block_height)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_unspent_p2a_tx_out(&self) -> Result<Vec<P2aTxOut>, sqlx::Error> {
+        let resp_data: Vec<P2aTxOut> =
+            sqlx::query_as("SELECT * FROM p2a_tx_out WHERE spent = $1 limit 100")
+                .bind(false)
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(resp_data)
+    }
+
+    pub async fn update_p2a_tx_out_confirmed_height(
+        &self,
+        block_height: i64,
+        txid: String,
+    ) -> Result<u64> {
+        let rows_affected =
+            sqlx::query("UPDATE p2a_tx_out SET confirmed_block_height = $1 WHERE tx_id = $2")
+                .bind(block_height)
+                .bind(txid)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+
+        Ok(rows_affected)
+    }
+
+    pub async fn update_p2a_tx_out_spent(&self, txid: String, vout: i32) -> Result<u64> {
+        let rows_affected =
+            sqlx::query("UPDATE p2a_tx_out SET spent = $1 WHERE tx_id = $2 and vout = $3")
+                .bind(true)
+                .bind(txid)
+                .bind(vout)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+
+        Ok(rows_affected)
+    }
+}
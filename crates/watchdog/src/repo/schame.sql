@@ -24,4 +24,64 @@ CREATE TABLE IF NOT EXISTS indexer (
 
 CREATE TRIGGER trigger_update_updated_at BEFORE
 UPDATE
-    ON indexer FOR EACH ROW EXECUTE FUNCTION update_updated_at_column ();
\ No newline at end of file
+    ON indexer FOR EACH ROW EXECUTE FUNCTION update_updated_at_column ();
+
+CREATE TABLE IF NOT EXISTS sweep_tx (
+    tx_id VARCHAR(128) NOT NULL,
+    tx_hex TEXT NOT NULL,
+    prevouts_hex TEXT NOT NULL,
+    sign_idx VARCHAR(128) NOT NULL,
+    change_vout INTEGER NOT NULL,
+    broadcast_height BIGINT NOT NULL,
+    replaced_by VARCHAR(128) NOT NULL DEFAULT '',
+    confirmed BOOLEAN DEFAULT FALSE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TRIGGER trigger_update_updated_at BEFORE
+UPDATE
+    ON sweep_tx FOR EACH ROW EXECUTE FUNCTION update_updated_at_column ();
+
+CREATE TABLE IF NOT EXISTS preimage (
+    hash VARCHAR(64) NOT NULL,
+    kind VARCHAR(16) NOT NULL,
+    preimage TEXT NOT NULL,
+    tx_id VARCHAR(128) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (hash, kind)
+);
+
+CREATE TRIGGER trigger_update_updated_at BEFORE
+UPDATE
+    ON preimage FOR EACH ROW EXECUTE FUNCTION update_updated_at_column ();
+
+CREATE TABLE IF NOT EXISTS hash_lock_script (
+    script_pubkey VARCHAR(128) NOT NULL,
+    witness_script TEXT NOT NULL,
+    hash VARCHAR(64) NOT NULL,
+    kind VARCHAR(16) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TRIGGER trigger_update_updated_at BEFORE
+UPDATE
+    ON hash_lock_script FOR EACH ROW EXECUTE FUNCTION update_updated_at_column ();
+
+CREATE TABLE IF NOT EXISTS p2a_tx_out (
+    tx_id VARCHAR(128) NOT NULL,
+    vout INTEGER NOT NULL,
+    value BIGINT NOT NULL,
+    tx_version INTEGER NOT NULL,
+    spent BOOLEAN DEFAULT FALSE,
+    confirmed_block_height BIGINT DEFAULT 0,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (tx_id, vout)
+);
+
+CREATE TRIGGER trigger_update_updated_at BEFORE
+UPDATE
+    ON p2a_tx_out FOR EACH ROW EXECUTE FUNCTION update_updated_at_column ();
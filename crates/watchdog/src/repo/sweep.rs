@@ -0,0 +1,56 @@
+use super::*;
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+
+/// a sweep we broadcast, kept until it confirms so it can be fee bumped
+#[derive(Debug, PartialEq, Default, FromRow)]
+pub struct SweepTx {
+    pub tx_id: String,
+    pub tx_hex: String,
+    /// the spent outputs in input order
+    pub prevouts_hex: String,
+    /// comma separated indexes of the inputs we signed
+    pub sign_idx: String,
+    /// the output paying us, it absorbs fee bumps
+    pub change_vout: i32,
+    pub broadcast_height: i64,
+    /// txid of our replacement, empty while this one is the latest
+    pub replaced_by: String,
+    /// mined, or its inputs were spent by someone else
+    pub confirmed: bool,
+}
+
+impl SweepTx {
+    pub fn new(
+        tx: &Transaction,
+        prevouts: &[TxOut],
+        sign_idx: &[usize],
+        change_vout: usize,
+        broadcast_height: u64,
+    ) -> Self {
+        Self {
+            tx_id: tx.compute_txid().to_string(),
+            tx_hex: serialize_hex(tx),
+            prevouts_hex: serialize_hex(&prevouts.to_vec()),
+            sign_idx: sign_idx
+                .iter()
+                .map(|idx| idx.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+            change_vout: change_vout as i32,
+            broadcast_height: broadcast_height as i64,
+            ..Default::default()
+        }
+    }
+
+    pub fn decode(&self) -> Result<(Transaction, Vec<TxOut>, Vec<usize>)> {
+        let tx = deserialize_hex(&self.tx_hex)?;
+        let prevouts = deserialize_hex(&self.prevouts_hex)?;
+        let sign_idx = self
+            .sign_idx
+            .split(',')
+            .filter(|idx| !idx.is_empty())
+            .map(|idx| idx.parse())
+            .collect::<Result<_, _>>()?;
+        Ok((tx, prevouts, sign_idx))
+    }
+}
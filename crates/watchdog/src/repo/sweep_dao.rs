@@ -0,0 +1,69 @@
+use sweep::SweepTx;
+
+use super::*;
+
+impl Dao {
+    pub async fn insert_sweep_tx(&self, sweep: SweepTx) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT INTO sweep_tx (tx_id, tx_hex, prevouts_hex, sign_idx, change_vout, broadcast_height, replaced_by, confirmed) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)")
+            .bind(&sweep.tx_id)
+            .bind(&sweep.tx_hex)
+            .bind(&sweep.prevouts_hex)
+            .bind(&sweep.sign_idx)
+            .bind(sweep.change_vout)
+            .bind(sweep.broadcast_height)
+            .bind(&sweep.replaced_by)
+            .bind(sweep.confirmed)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// latest versions of sweeps broadcast at or before `max_height` and not yet confirmed
+    pub async fn get_stuck_sweeps(&self, max_height: i64) -> Result<Vec<SweepTx>, sqlx::Error> {
+        let resp_data: Vec<SweepTx> = sqlx::query_as(
+            "SELECT * FROM sweep_tx WHERE confirmed = $1 and replaced_by = $2 and broadcast_height <= $3",
+        )
+        .bind(false)
+        .bind("")
+        .bind(max_height)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(resp_data)
+    }
+
+    /// whether `txid` is one of our sweeps or a replacement of one
+    pub async fn is_sweep_tx(&self, txid: String) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM sweep_tx WHERE tx_id = $1)")
+            .bind(txid)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    pub async fn update_sweep_confirmed(&self, txid: String) -> Result<u64> {
+        let rows_affected = sqlx::query!(
+            "UPDATE sweep_tx SET confirmed = $1 WHERE tx_id = $2",
+            true,
+            txid
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected)
+    }
+
+    pub async fn update_sweep_replaced(&self, txid: String, replaced_by: String) -> Result<u64> {
+        let rows_affected = sqlx::query!(
+            "UPDATE sweep_tx SET replaced_by = $1 WHERE tx_id = $2",
+            replaced_by,
+            txid
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected)
+    }
+}
@@ -1,4 +1,14 @@
+pub mod schedule;
 pub mod tx;
 pub mod unsign;
 
 use super::*;
+
+/// blocks the transactions we build aim to confirm in
+pub const FEE_CONF_TARGET: u16 = 2;
+
+/// blocks a sweep may stay unconfirmed before we replace it with a higher fee
+pub const RBF_AFTER_BLOCKS: u64 = 3;
+
+/// every sweep builder pays us in its first output
+pub const SWEEP_VOUT: usize = 0;
@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+
+use super::*;
+
+/// a signed anchor sweep waiting for the block its anchors mature in
+#[derive(Debug, Clone)]
+pub struct PreparedSweep {
+    /// the first block the sweep is valid in
+    pub maturity_height: u64,
+    pub tx: Transaction,
+    pub prevouts: Vec<TxOut>,
+}
+
+/// anchor sweeps signed a block before they can be mined
+#[derive(Debug, Default)]
+pub struct AnchorSchedule {
+    prepared: Vec<PreparedSweep>,
+}
+
+impl AnchorSchedule {
+    pub fn insert(&mut self, sweep: PreparedSweep) {
+        self.prepared.push(sweep);
+    }
+
+    pub fn len(&self) -> usize {
+        self.prepared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prepared.is_empty()
+    }
+
+    /// the anchors and fee inputs the prepared sweeps spend
+    pub fn spent_out_points(&self) -> HashSet<OutPoint> {
+        self.prepared
+            .iter()
+            .flat_map(|sweep| sweep.tx.input.iter().map(|input| input.previous_output))
+            .collect()
+    }
+
+    /// remove the sweeps the block after `tip` can include
+    pub fn take_due(&mut self, tip: u64) -> Vec<PreparedSweep> {
+        let (due, waiting) = std::mem::take(&mut self.prepared)
+            .into_iter()
+            .partition(|sweep| sweep.maturity_height <= tip + 1);
+        self.prepared = waiting;
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::Sequence;
+    use datatypes::fixtures;
+
+    fn sweep(maturity_height: u64, vout: u32) -> PreparedSweep {
+        PreparedSweep {
+            maturity_height,
+            tx: fixtures::spend(fixtures::out_point(vout), Sequence(16), vec![]),
+            prevouts: vec![],
+        }
+    }
+
+    #[test]
+    fn releases_sweeps_at_their_first_valid_block() {
+        let mut schedule = AnchorSchedule::default();
+        schedule.insert(sweep(102, 0));
+        schedule.insert(sweep(101, 1));
+        assert_eq!(schedule.spent_out_points().len(), 2);
+
+        assert!(schedule.take_due(99).is_empty());
+        let due = schedule.take_due(100);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].maturity_height, 101);
+        assert_eq!(schedule.len(), 1);
+
+        assert_eq!(schedule.take_due(101)[0].maturity_height, 102);
+        assert!(schedule.is_empty());
+    }
+}
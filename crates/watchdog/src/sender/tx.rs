@@ -1,148 +1,482 @@
-use bitcoin::{consensus::encode::serialize_hex, Amount, OutPoint, ScriptBuf};
-use bittx::{build_helper, builder::anchor, signer};
-use btcrpc::BtcCli;
+use bitcoin::{
+    consensus::encode::serialize_hex, hashes::Hash, Address, Amount, OutPoint, PubkeyHash, Script,
+    ScriptBuf,
+};
+use bitcoincore_rpc::json::GetMempoolEntryResult;
+use bittx::{
+    build_helper,
+    builder::{
+        anchor,
+        anchor_batch::{self, MaturedAnchor, ANCHOR_MATURITY},
+        cpfp, p2a,
+    },
+    coin_select,
+    fee_policy::SweepFeePolicy,
+    fee_rate::FeeRate,
+    hd::Destination,
+    policy::DustThreshold,
+    replacement, signer, verify,
+    weight::Satisfaction,
+};
+use btcrpc::{BtcCli, PackageResult};
 use datatypes::types;
-use std::{collections::HashMap, str::FromStr};
+use repo::{p2a::P2aTxOut, sweep::SweepTx};
+use schedule::{AnchorSchedule, PreparedSweep};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+use utxo::FeeUtxoPool;
 
 use super::*;
 
 #[derive(Debug)]
 pub struct TxSender {
     btccli: btcrpc::BtcCli,
-    receiver: String,
-    wif: String,
+    destination: Destination,
+    signer: Arc<dyn signer::Signer>,
     dao: Arc<repo::Dao>,
+    sweep_policy: SweepFeePolicy,
+    max_batch_weight: usize,
+    fee_pool: Arc<FeeUtxoPool>,
 }
 
 impl TxSender {
-    pub async fn new(cfg: &config::Config) -> Self {
+    pub async fn new(cfg: &config::Config, fee_pool: Arc<FeeUtxoPool>) -> Self {
         let btccli = BtcCli::new(&cfg.bitcoin.endpoint, &cfg.bitcoin.user, &cfg.bitcoin.pass);
         let conn_pool = repo::conn_pool(&cfg.database).await.unwrap();
         let dao = Dao::new(conn_pool);
         Self {
             btccli,
-            receiver: cfg.sign.receiver.clone(),
-            wif: cfg.sign.wif.clone(),
+            destination: cfg.sign.destination().expect("invalid sign config"),
+            signer: cfg.sign.signer().expect("invalid sign config"),
             dao: Arc::new(dao),
+            sweep_policy: cfg.sweep.fee_policy(),
+            max_batch_weight: cfg.sweep.max_batch_weight(),
+            fee_pool,
         }
     }
 
-    pub fn send(&self, tx: Transaction) -> Result<Txid> {
+    /// refuses transactions failing script verification against `prevouts`
+    pub fn send(&self, tx: Transaction, prevouts: &[TxOut]) -> Result<Txid> {
+        verify::verify_tx(&tx, prevouts)?;
         self.btccli.send_tx(&tx)
     }
 
-    pub async fn send_unsigned_tx(
+    /// relay parents and child together, `package` holds each tx with its prevouts, parents first
+    pub fn send_package(&self, package: &[(Transaction, Vec<TxOut>)]) -> Result<PackageResult> {
+        let txs = verify_package(package)?;
+        let res = self.btccli.submit_package(&txs)?;
+        log_package("submit", &res);
+        if !res.accepted {
+            return Err(anyhow!("package rejected: {}", res.message));
+        }
+        Ok(res)
+    }
+
+    /// ask the node whether `package` would be accepted, nothing is broadcast
+    pub fn test_package(&self, package: &[(Transaction, Vec<TxOut>)]) -> Result<PackageResult> {
+        let txs = verify_package(package)?;
+        let res = self.btccli.test_package(&txs)?;
+        log_package("test", &res);
+        Ok(res)
+    }
+
+    /// broadcast one of our sweeps and remember it for fee bumping. the fee pool
+    /// gets its inputs and the destination its address back when the node refuses it
+    pub async fn send_sweep(
         &self,
         tx: Transaction,
-        idx: u32,
-        my_utxos: Vec<types::Utxo>,
+        prevouts: &[TxOut],
+        sign_idx: &[usize],
+    ) -> Result<Txid> {
+        let sent = self.send(tx.clone(), prevouts);
+        self.settle_sweep(sent, tx, prevouts, sign_idx).await
+    }
+
+    /// `send_sweep` for a child of the unconfirmed `parent`, relayed together as a
+    /// package. the child goes alone when the prevouts of the parent, needed for its
+    /// verification, can not be looked up; the parent is in our mempool then
+    pub async fn send_child_sweep(
+        &self,
+        parent: &Transaction,
+        tx: Transaction,
+        prevouts: &[TxOut],
+        sign_idx: &[usize],
+    ) -> Result<Txid> {
+        let parent_prevouts = match self.prevouts_of(parent) {
+            Ok(parent_prevouts) => parent_prevouts,
+            Err(e) => {
+                debug!("sending {} without its parent: {}", tx.compute_txid(), e);
+                return self.send_sweep(tx, prevouts, sign_idx).await;
+            }
+        };
+        let txid = tx.compute_txid();
+        let package = [
+            (parent.clone(), parent_prevouts),
+            (tx.clone(), prevouts.to_vec()),
+        ];
+        let sent = self.send_package(&package).map(|_| txid);
+        self.settle_sweep(sent, tx, prevouts, sign_idx).await
+    }
+
+    /// the outputs `tx` spends, from the mempool or a -txindex node
+    fn prevouts_of(&self, tx: &Transaction) -> Result<Vec<TxOut>> {
+        tx.input
+            .iter()
+            .map(|input| {
+                let outpoint = input.previous_output;
+                let (_, prev_tx) = self.btccli.get_raw_transaction_info(&outpoint.txid)?;
+                prev_tx
+                    .output
+                    .get(outpoint.vout as usize)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("{} has no output {}", outpoint.txid, outpoint.vout))
+            })
+            .collect()
+    }
+
+    /// book the outcome of broadcasting `tx` with the fee pool, destination and db
+    async fn settle_sweep(
+        &self,
+        sent: Result<Txid>,
+        tx: Transaction,
+        prevouts: &[TxOut],
+        sign_idx: &[usize],
     ) -> Result<Txid> {
+        let recipient = tx.output.get(SWEEP_VOUT).map(|out| &out.script_pubkey);
+        let txid = match sent {
+            Ok(txid) => txid,
+            Err(e) => {
+                for input in tx.input.iter() {
+                    self.fee_pool.release(&input.previous_output).await;
+                }
+                if let Some(recipient) = recipient {
+                    self.destination.release(recipient);
+                }
+                return Err(e);
+            }
+        };
+        if let Some(recipient) = recipient {
+            if let Err(e) = self.destination.commit(recipient) {
+                error!("failed to commit receive address of {}: {}", txid, e);
+            }
+        }
+        self.fee_pool.mark_broadcast(&tx).await;
+        self.track_sweep(&tx, prevouts, sign_idx).await;
+        Ok(txid)
+    }
+
+    /// the script the next sweep pays to, committed or released by `send_sweep`
+    fn peek_recipient(&self) -> Result<ScriptBuf> {
+        Ok(Address::from_str(&self.destination.peek_address()?)?
+            .assume_checked()
+            .script_pubkey())
+    }
+
+    async fn track_sweep(&self, tx: &Transaction, prevouts: &[TxOut], sign_idx: &[usize]) {
+        let height = match self.btccli.get_best_block_height() {
+            Ok(height) => height,
+            Err(e) => {
+                error!("failed to track sweep {}: {}", tx.compute_txid(), e);
+                return;
+            }
+        };
+        let sweep = SweepTx::new(tx, prevouts, sign_idx, SWEEP_VOUT, height);
+        if let Err(e) = self.dao.insert_sweep_tx(sweep).await {
+            error!("failed to track sweep {}: {}", tx.compute_txid(), e);
+        }
+    }
+
+    /// replace our sweeps still unconfirmed `RBF_AFTER_BLOCKS` after their broadcast,
+    /// adding confirmed fee utxos of the pool when their change can not pay for it
+    pub async fn bump_stuck_sweeps(&self) -> Result<()> {
+        let height = self.btccli.get_best_block_height()?;
+        let sweeps = self
+            .dao
+            .get_stuck_sweeps(height as i64 - RBF_AFTER_BLOCKS as i64)
+            .await?;
+        if sweeps.is_empty() {
+            return Ok(());
+        }
+
+        let fee_rate = FeeRate::try_from(self.btccli.estimate_fee_rate(FEE_CONF_TARGET)? as f64)?;
+        for sweep in sweeps {
+            let (tx, prevouts, sign_idx) = sweep.decode()?;
+            if self.sweep_settled(&tx)? {
+                self.dao.update_sweep_confirmed(sweep.tx_id.clone()).await?;
+                continue;
+            }
+            // a sweep chained on it would be evicted and BIP125 rule 3 would want its
+            // fees paid too, the child gets bumped instead
+            if let Ok(entry) = self.btccli.get_mempool_entry(&tx.compute_txid()) {
+                if !entry.spent_by.is_empty() {
+                    debug!("not bumping {}, it has descendants", sweep.tx_id);
+                    continue;
+                }
+            }
+
+            let extra = self.fee_pool.confirmed().await;
+            let unconfirmed = self.fee_pool.unconfirmed_txids().await;
+            let replacement = match replacement::bump_fee(
+                &tx,
+                &prevouts,
+                &sign_idx,
+                sweep.change_vout as usize,
+                fee_rate,
+                &extra,
+                &unconfirmed,
+                &DustThreshold::default(),
+            ) {
+                Ok(replacement) => replacement,
+                Err(e) => {
+                    error!("failed to bump sweep {}: {}", sweep.tx_id, e);
+                    continue;
+                }
+            };
+
+            let added: Vec<OutPoint> = replacement.tx.input[tx.input.len()..]
+                .iter()
+                .map(|input| input.previous_output)
+                .collect();
+            if let Err(e) = self.fee_pool.claim(&added).await {
+                error!("failed to bump sweep {}: {}", sweep.tx_id, e);
+                continue;
+            }
+
+            let signed_tx = match signer::spawn_sign_tx(
+                self.signer.clone(),
+                replacement.tx,
+                replacement.prevouts.clone(),
+                replacement.sign_idx.clone(),
+            )
+            .await
+            {
+                Ok(signed_tx) => signed_tx,
+                Err(e) => {
+                    error!("failed to sign replacement of {}: {}", sweep.tx_id, e);
+                    for out_point in added.iter() {
+                        self.fee_pool.release(out_point).await;
+                    }
+                    continue;
+                }
+            };
+            let new_txid = match self
+                .send_sweep(signed_tx, &replacement.prevouts, &replacement.sign_idx)
+                .await
+            {
+                Ok(txid) => txid,
+                Err(e) => {
+                    error!("failed to replace sweep {}: {}", sweep.tx_id, e);
+                    continue;
+                }
+            };
+            self.dao
+                .update_sweep_replaced(sweep.tx_id.clone(), new_txid.to_string())
+                .await?;
+            info!(
+                "replaced sweep {} with {} paying {}",
+                sweep.tx_id, new_txid, replacement.fee
+            );
+        }
+        Ok(())
+    }
+
+    /// pay for unconfirmed transactions to our addresses still in the mempool
+    /// `RBF_AFTER_BLOCKS` after they entered it, with a child spending our output and
+    /// confirmed fee utxos of the pool
+    pub async fn accelerate_stuck_parents(&self) -> Result<()> {
+        let height = self.btccli.get_best_block_height()?;
+        let fee_rate = FeeRate::try_from(self.btccli.estimate_fee_rate(FEE_CONF_TARGET)? as f64)?;
+        for address in self.destination.watched_addresses()? {
+            let pending = match mempool::utxo::gets_utxo_with_pending(&address).await {
+                Ok(utxos) => utxos,
+                Err(e) => {
+                    debug!("no utxo for {}: {}", address, e);
+                    continue;
+                }
+            };
+            for utxo in pending {
+                let txid = utxo.out_point.txid;
+                // confirmed or gone
+                let Ok(entry) = self.btccli.get_mempool_entry(&txid) else {
+                    continue;
+                };
+                // not stuck yet, or already has a child
+                if entry.height + RBF_AFTER_BLOCKS > height || !entry.spent_by.is_empty() {
+                    continue;
+                }
+                // our own sweeps are replaced by `bump_stuck_sweeps` instead
+                if self.dao.is_sweep_tx(txid.to_string()).await? {
+                    continue;
+                }
+                let ancestors = cpfp::Ancestors::from(&entry);
+                if ancestors.fee >= fee_rate.fee(ancestors.vsize as usize) {
+                    continue;
+                }
+
+                let (_, parent) = self.btccli.get_raw_transaction_info(&txid)?;
+                let recipient = self.peek_recipient()?;
+                let confirmed = self.fee_pool.confirmed().await;
+                let child = match cpfp::build_cpfp_child(
+                    &parent,
+                    utxo.out_point.vout,
+                    &ancestors,
+                    fee_rate,
+                    recipient.clone(),
+                    &confirmed,
+                    &DustThreshold::default(),
+                ) {
+                    Ok(child) => child,
+                    Err(e) => {
+                        error!("failed to build cpfp child for {}: {}", txid, e);
+                        self.destination.release(&recipient);
+                        continue;
+                    }
+                };
+                let added: Vec<OutPoint> = child
+                    .tx
+                    .input
+                    .iter()
+                    .map(|input| input.previous_output)
+                    .filter(|out_point| *out_point != utxo.out_point)
+                    .collect();
+                if let Err(e) = self.fee_pool.claim(&added).await {
+                    error!("failed to fund cpfp child for {}: {}", txid, e);
+                    self.destination.release(&recipient);
+                    continue;
+                }
+                let sign_idx: Vec<usize> = (0..child.tx.input.len()).collect();
+                let signed_tx = match signer::spawn_sign_tx(
+                    self.signer.clone(),
+                    child.tx,
+                    child.prevouts.clone(),
+                    sign_idx.clone(),
+                )
+                .await
+                {
+                    Ok(signed_tx) => signed_tx,
+                    Err(e) => {
+                        error!("failed to sign cpfp child for {}: {}", txid, e);
+                        for out_point in added.iter() {
+                            self.fee_pool.release(out_point).await;
+                        }
+                        self.destination.release(&recipient);
+                        continue;
+                    }
+                };
+                let child_txid = match self
+                    .send_child_sweep(&parent, signed_tx, &child.prevouts, &sign_idx)
+                    .await
+                {
+                    Ok(txid) => txid,
+                    Err(e) => {
+                        error!("failed to send cpfp child for {}: {}", txid, e);
+                        continue;
+                    }
+                };
+                info!(
+                    "cpfp child {} pays {} for stuck {}",
+                    child_txid, child.fee, txid
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// mined, or its inputs were spent by another transaction
+    fn sweep_settled(&self, tx: &Transaction) -> Result<bool> {
+        if let Ok((info, _)) = self.btccli.get_raw_transaction_info(&tx.compute_txid()) {
+            return Ok(info.confirmations.is_some_and(|n| n > 0));
+        }
+
+        // unknown to the node: mined without -txindex, conflicted, or evicted
+        for input in tx.input.iter() {
+            let outpoint = input.previous_output;
+            if self
+                .btccli
+                .get_tx_out_spent(&outpoint.txid, outpoint.vout)?
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    pub async fn send_unsigned_tx(&self, tx: Transaction, idx: u32) -> Result<Txid> {
         let input = tx.input.get(idx as usize).unwrap();
         let prev_out = self
             .btccli
             .get_tx_out(&input.previous_output.txid, input.previous_output.vout)?;
+        let feerate = self.btccli.estimate_fee_rate(FEE_CONF_TARGET)?;
+        let recipient = self.destination.peek_address()?;
+        let recipient_script = Address::from_str(&recipient)?
+            .assume_checked()
+            .script_pubkey();
+        let floor = sweep_fee_floor(
+            &[Satisfaction::Satisfied {
+                script_sig: input.script_sig.clone(),
+                witness: input.witness.clone(),
+            }],
+            prev_out.value,
+            &recipient_script,
+            FeeRate::try_from(feerate as f64)?,
+        );
+        let fee_utxo = match self.fee_pool.reserve(floor).await {
+            Ok(utxo) => utxo,
+            Err(e) => {
+                self.destination.release(&recipient_script);
+                return Err(anyhow!("not found unspent utxo: {}", e));
+            }
+        };
         let info = types::UnsignedInfo {
-            recipient: self.receiver.clone(),
+            recipient,
             tx,
             input_idx: idx,
             input_out: prev_out,
+            feerate,
         };
 
-        if my_utxos.is_empty() {
-            return Err(anyhow!("not found unspent utxo"));
-        }
-
-        let my_utxo = my_utxos.first().unwrap();
         info!("start build unsign_tx...");
         // match build_helper::build_unsigned_tx(info).await {
-        match build_helper::build_unsigned_tx_with_receive_utxo(info, my_utxo.clone()) {
+        match build_helper::build_unsigned_tx_with_receive_utxo(
+            info,
+            std::slice::from_ref(&fee_utxo),
+        ) {
             Ok((unsigned_tx, prevouts)) => {
-                match signer::sign_tx(self.wif.clone(), unsigned_tx, prevouts, vec![0]) {
+                match signer::spawn_sign_tx(
+                    self.signer.clone(),
+                    unsigned_tx,
+                    prevouts.clone(),
+                    vec![0],
+                )
+                .await
+                {
                     Ok(signed_tx) => {
                         info!(
                             "build and signed the unsign_tx, id: {} hex : {}",
                             signed_tx.compute_txid(),
                             serialize_hex(&signed_tx)
                         );
-                        self.send(signed_tx.clone())
+                        self.send_sweep(signed_tx, &prevouts, &[0]).await
                     }
                     Err(err) => {
                         error!("failed to sign the unsign_tx: {:?}", err);
+                        self.fee_pool.release(&fee_utxo.out_point).await;
+                        self.destination.release(&recipient_script);
                         return Err(err);
                     }
                 }
             }
             Err(err) => {
                 error!("build unsign_tx error: {:?}", err);
+                self.fee_pool.release(&fee_utxo.out_point).await;
+                self.destination.release(&recipient_script);
                 return Err(err);
             }
         }
     }
 
-    pub async fn send_task(&self, my_utxos: Vec<types::Utxo>) -> Result<()> {
-        let height = self.btccli.get_best_block_height();
-        if height.is_err() {
-            return Err(anyhow!("get block height failed"));
-        }
-
-        debug!("send task get block height successfully");
-        let height = height.unwrap();
-        let txouts = self.dao.get_anchor_tx_out(height as i64).await;
-        if txouts.is_err() {
-            return Err(anyhow!("get anchor txouts failed"));
-        }
-
-        let txouts = txouts.unwrap();
-        if txouts.len() <= 1 {
-            return Ok(());
-        }
-
-        info!("send task get db successfully. len {}", txouts.len());
-        let mut datas = HashMap::new();
-        for tx_out in txouts.into_iter() {
-            datas
-                .entry(tx_out.tx_id.clone())
-                .or_insert_with(Vec::new)
-                .push(tx_out);
-        }
-
-        for (tx_id, tx_outs) in &datas {
-            if tx_outs.len() != 2 {
-                continue;
-            }
-
-            info!("send task into build. txid {}", tx_id);
-            let mut unlock_infos = vec![];
-            let mut unlock_outs = vec![];
-            for tx_out in tx_outs {
-                let unlock_hex = hex::decode(tx_out.unlock_info.clone()).unwrap();
-                unlock_infos.push(unlock_hex);
-                let out_point = OutPoint::from_str(&format!("{}:{}", tx_id, tx_out.vout)).unwrap();
-                let out = TxOut {
-                    value: Amount::from_sat(tx_out.value as u64),
-                    script_pubkey: ScriptBuf::from_hex(&tx_out.script_pubkey).unwrap(),
-                };
-                unlock_outs.push((out, out_point));
-            }
-
-            let anchor_info = types::AnchorInfo {
-                anchor_txid: tx_id.to_string(),
-                unlock_bytes: unlock_infos,
-                unlock_outs,
-                recipient: self.receiver.clone(),
-            };
-            info!("send task into build finish. txid {}", tx_id);
-            match self.build_and_sign(anchor_info, &my_utxos).await {
-                Ok(tx) => {
-                    info!("send transaction started: {:?}", tx.compute_txid());
-                    self.send(tx)?;
-                }
-                Err(e) => return Err(anyhow!("build and sign tx fail : {}", e)),
-            };
-        }
-        Ok(())
-    }
-
     pub async fn send_task_by_hash(
         &self,
         tx_id: String,
@@ -167,14 +501,24 @@ impl TxSender {
             unlock_outs.push((out, out_point));
         }
 
+        let recipient = self.destination.peek_address()?;
         let anchor_info = types::AnchorInfo {
             anchor_txid: tx_id.to_string(),
             unlock_bytes: unlock_infos,
             unlock_outs,
-            recipient: self.receiver.clone(),
+            recipient: recipient.clone(),
+            feerate: self.btccli.estimate_fee_rate(FEE_CONF_TARGET)?,
         };
 
-        self.build_and_sign(anchor_info, &my_utxos).await
+        // the address stays handed out until `send_sweep` broadcasts the sweep
+        match self.build_and_sign(anchor_info, &my_utxos).await {
+            Ok((tx, _)) => Ok(tx),
+            Err(e) => {
+                let recipient = Address::from_str(&recipient)?.assume_checked();
+                self.destination.release(&recipient.script_pubkey());
+                Err(e)
+            }
+        }
     }
 
     pub async fn build_sign_and_send(
@@ -182,15 +526,17 @@ impl TxSender {
         anchor_info: types::AnchorInfo,
         my_utxos: Vec<types::Utxo>,
     ) -> Result<Txid> {
-        let my_utxo = my_utxos.first().unwrap();
         let (anchor_tx, prevouts) =
-            build_helper::build_anchor_tx(anchor_info, my_utxo.clone()).await?;
-        let signed_tx = signer::sign_tx(self.wif.clone(), anchor_tx, prevouts, vec![0])?;
+            build_helper::build_anchor_tx(anchor_info, &my_utxos, &self.sweep_policy).await?;
+        let signed_tx =
+            signer::spawn_sign_tx(self.signer.clone(), anchor_tx, prevouts.clone(), vec![0])
+                .await?;
         println!("{}", serialize_hex(&signed_tx));
         loop {
-            match self.send(signed_tx.clone()) {
+            match self.send(signed_tx.clone(), &prevouts) {
                 Ok(txid) => {
                     println!("{}", txid);
+                    self.track_sweep(&signed_tx, &prevouts, &[0]).await;
                     return Ok(txid);
                 }
                 Err(e) => {
@@ -210,16 +556,18 @@ impl TxSender {
         &self,
         anchor_info: types::AnchorInfo,
         my_utxos: &[types::Utxo],
-    ) -> Result<Transaction> {
-        let my_utxo = my_utxos.first().unwrap();
+    ) -> Result<(Transaction, Vec<TxOut>)> {
         let (anchor_tx, prevouts) =
-            build_helper::build_anchor_tx(anchor_info, my_utxo.clone()).await?;
-        let signed_tx = signer::sign_tx(self.wif.clone(), anchor_tx, prevouts, vec![0])?;
+            build_helper::build_anchor_tx(anchor_info, my_utxos, &self.sweep_policy).await?;
+        let signed_tx =
+            signer::spawn_sign_tx(self.signer.clone(), anchor_tx, prevouts.clone(), vec![0])
+                .await?;
         info!("{}", serialize_hex(&signed_tx));
-        Ok(signed_tx)
+        Ok((signed_tx, prevouts))
     }
 
-    pub async fn anchor_closed_task(&self, my_utxos: Vec<types::Utxo>) -> Result<()> {
+    /// sweep the matured anchors of every close, batched oldest first within `max_batch_weight`
+    pub async fn anchor_closed_task(&self) -> Result<()> {
         let height = self.btccli.get_best_block_height();
         if height.is_err() {
             return Err(anyhow!("get block height failed"));
@@ -227,45 +575,386 @@ impl TxSender {
 
         debug!("send task get block height successfully");
         let height = height.unwrap();
-        let infos = self.dao.get_anchor_tx_out(height as i64).await?;
-        if infos.is_empty() {
+        // the sweep enters the block after the tip
+        let anchors = self.matured_anchors(height, height + 1).await?;
+        if anchors.is_empty() {
             return Err(anyhow!("not found anchor txouts"));
         }
 
-        let mut details = vec![];
-        for info in infos.iter() {
-            let anchor_info = types::AnchorDetail {
-                anchor_txid: info.tx_id.clone(),
-                vout: info.vout as u32,
-                redeem_script_hex: info.unlock_info.clone(),
-                script_pubkey_hex: info.script_pubkey.clone(),
-                out_value: info.value as u64,
-            };
-            details.push(anchor_info);
+        info!("anchor sweep task build start...");
+        for (tx, prevouts) in self.build_anchor_sweeps(anchors, &HashSet::new()).await? {
+            let txid = tx.compute_txid();
+            info!("send transaction started: {:?}", txid);
+            if let Err(e) = self.send_sweep(tx, &prevouts, &[0]).await {
+                error!("failed to send anchor sweep {}: {}", txid, e);
+            }
         }
+        Ok(())
+    }
 
-        info!("anchor sweep task build start...");
-        if my_utxos.is_empty() {
-            return Err(anyhow!("my_utxos id empty"));
+    /// run on every new block. sends the sweeps prepared for the block after the tip,
+    /// then signs the sweeps of anchors maturing one block later so they go out the
+    /// moment their first valid block can be mined
+    pub async fn scheduled_anchor_task(&self, schedule: &mut AnchorSchedule) -> Result<()> {
+        let height = self.btccli.get_best_block_height()?;
+        for sweep in schedule.take_due(height) {
+            let txid = sweep.tx.compute_txid();
+            match self.send_sweep(sweep.tx, &sweep.prevouts, &[0]).await {
+                Ok(_) => info!("sent prepared anchor sweep {} at {}", txid, height),
+                Err(e) => error!("failed to send prepared anchor sweep {}: {}", txid, e),
+            }
         }
-        let my_utxo = my_utxos.first().unwrap();
-        match anchor::build_anchor_sweep_tx(&my_utxo, details) {
+
+        let claimed = schedule.spent_out_points();
+        let (due, next): (Vec<MaturedAnchor>, Vec<MaturedAnchor>) = self
+            .matured_anchors(height, height + 2)
+            .await?
+            .into_iter()
+            .partition(|anchor| anchor.maturity_height <= height + 1);
+
+        // anchors we had no chance to prepare, a restart or a late record
+        if !due.is_empty() {
+            for (tx, prevouts) in self.build_anchor_sweeps(due, &claimed).await? {
+                let txid = tx.compute_txid();
+                if let Err(e) = self.send_sweep(tx, &prevouts, &[0]).await {
+                    error!("failed to send anchor sweep {}: {}", txid, e);
+                }
+            }
+        }
+
+        // their fee inputs stay reserved until the sweeps go out
+        if !next.is_empty() {
+            for (tx, prevouts) in self.build_anchor_sweeps(next, &claimed).await? {
+                info!(
+                    "prepared anchor sweep {} for block {}",
+                    tx.compute_txid(),
+                    height + 2
+                );
+                schedule.insert(PreparedSweep {
+                    maturity_height: height + 2,
+                    tx,
+                    prevouts,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// sweep the p2a outputs recorded in the db, one sweep per parent. the fee input is a
+    /// confirmed one, a TRUC child may have no other unconfirmed ancestor, and sweeps
+    /// leaving us less than it brought are not sent
+    pub async fn p2a_task(&self) -> Result<()> {
+        let mut parents: HashMap<String, Vec<P2aTxOut>> = HashMap::new();
+        for out in self.dao.get_unspent_p2a_tx_out().await? {
+            parents.entry(out.tx_id.clone()).or_default().push(out);
+        }
+        if parents.is_empty() {
+            return Ok(());
+        }
+
+        let fee_rate = FeeRate::try_from(self.btccli.estimate_fee_rate(FEE_CONF_TARGET)? as f64)?;
+        for (tx_id, outs) in parents {
+            let txid = Txid::from_str(&tx_id)?;
+            let entry = self.btccli.get_mempool_entry(&txid).ok();
+            let mut anchors = vec![];
+            for out in outs {
+                // spent in the chain or by someone in the mempool
+                if self.btccli.get_tx_out_spent(&txid, out.vout as u32)? {
+                    continue;
+                }
+                anchors.push(types::P2aDetail {
+                    out_point: OutPoint {
+                        txid,
+                        vout: out.vout as u32,
+                    },
+                    value: out.value as u64,
+                    parent_version: out.tx_version,
+                    confirmed: entry.is_none(),
+                });
+            }
+            if anchors.is_empty() {
+                continue;
+            }
+            if let Err(e) = self
+                .send_p2a_sweep(&anchors, entry.as_ref(), fee_rate)
+                .await
+            {
+                error!("failed to sweep p2a outputs of {}: {}", tx_id, e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn send_p2a_sweep(
+        &self,
+        anchors: &[types::P2aDetail],
+        parent: Option<&GetMempoolEntryResult>,
+        fee_rate: FeeRate,
+    ) -> Result<Txid> {
+        // relayed together with the sweep
+        let parent_tx = match parent {
+            Some(_) => Some(
+                self.btccli
+                    .get_raw_transaction_info(&anchors[0].out_point.txid)?
+                    .1,
+            ),
+            None => None,
+        };
+        let recipient = self.peek_recipient()?;
+        let floor = sweep_fee_floor(
+            &vec![Satisfaction::P2a; anchors.len()],
+            anchors
+                .iter()
+                .map(|anchor| Amount::from_sat(anchor.value))
+                .sum(),
+            &recipient,
+            fee_rate,
+        );
+        let fee_utxo = match coin_select::select_fee_input(&self.fee_pool.confirmed().await, floor)
+        {
+            Ok(utxo) => utxo,
+            Err(e) => {
+                self.destination.release(&recipient);
+                return Err(e);
+            }
+        };
+        if let Err(e) = self.fee_pool.claim(&[fee_utxo.out_point]).await {
+            self.destination.release(&recipient);
+            return Err(e);
+        }
+        let ancestors = parent.map(cpfp::Ancestors::from);
+        let signed = match p2a::build_p2a_sweep_tx(
+            &fee_utxo,
+            anchors,
+            ancestors.as_ref(),
+            recipient.clone(),
+            fee_rate,
+        )
+        .and_then(|(tx, prevouts)| {
+            if tx.output[0].value <= fee_utxo.value {
+                return Err(anyhow!(
+                    "sweep returns {} of the {} fee input",
+                    tx.output[0].value,
+                    fee_utxo.value
+                ));
+            }
+            Ok((tx, prevouts))
+        }) {
             Ok((tx, prevouts)) => {
-                info!("send transaction started: {:?}", tx.compute_txid());
-                let signed_tx = signer::sign_tx(self.wif.clone(), tx, prevouts, vec![0])?;
-                println!("{}", serialize_hex(&signed_tx));
-                self.send(signed_tx)?;
+                signer::spawn_sign_tx(self.signer.clone(), tx, prevouts.clone(), vec![0])
+                    .await
+                    .map(|signed_tx| (signed_tx, prevouts))
             }
-            Err(e) => return Err(anyhow!("build and sign tx fail : {}", e)),
+            Err(e) => Err(e),
         };
-        Ok(())
+        let (tx, prevouts) = match signed {
+            Ok(signed) => signed,
+            Err(e) => {
+                self.fee_pool.release(&fee_utxo.out_point).await;
+                self.destination.release(&recipient);
+                return Err(e);
+            }
+        };
+        let txid = match parent_tx {
+            Some(parent_tx) => {
+                self.send_child_sweep(&parent_tx, tx, &prevouts, &[0])
+                    .await?
+            }
+            None => self.send_sweep(tx, &prevouts, &[0]).await?,
+        };
+        info!("p2a sweep {} spends {} anchors", txid, anchors.len());
+        Ok(txid)
+    }
+
+    /// unspent anchors recorded in the db maturing at `due_by` at the latest
+    async fn matured_anchors(&self, height: u64, due_by: u64) -> Result<Vec<MaturedAnchor>> {
+        let infos = self.dao.get_anchor_tx_out(height as i64).await?;
+        let mut anchors = vec![];
+        for info in infos.iter() {
+            let maturity_height = info.confirmed_block_height as u64 + ANCHOR_MATURITY;
+            if maturity_height > due_by {
+                continue;
+            }
+            let out_point = OutPoint::from_str(&format!("{}:{}", info.tx_id, info.vout))?;
+            if self
+                .btccli
+                .get_tx_out_spent(&out_point.txid, out_point.vout)?
+            {
+                continue;
+            }
+            anchors.push(MaturedAnchor {
+                detail: types::AnchorDetail {
+                    anchor_txid: info.tx_id.clone(),
+                    vout: info.vout as u32,
+                    redeem_script_hex: info.unlock_info.clone(),
+                    script_pubkey_hex: info.script_pubkey.clone(),
+                    out_value: info.value as u64,
+                },
+                maturity_height,
+            });
+        }
+        Ok(anchors)
+    }
+
+    /// signed sweeps of `anchors` leaving out those in `skip`. each batch is planned for
+    /// a p2pkh fee input, the largest, and reserves a fee utxo covering what it costs.
+    /// a batch failing stops the loop, the sweeps signed so far are returned as they hold
+    /// fee utxos and recipients
+    async fn build_anchor_sweeps(
+        &self,
+        anchors: Vec<MaturedAnchor>,
+        skip: &HashSet<OutPoint>,
+    ) -> Result<Vec<(Transaction, Vec<TxOut>)>> {
+        let fee_rate = FeeRate::try_from(self.btccli.estimate_fee_rate(FEE_CONF_TARGET)? as f64)?;
+        let out_points = anchors
+            .iter()
+            .map(|anchor| anchor_batch::anchor_out_point(&anchor.detail))
+            .collect::<Result<Vec<_>>>()?;
+        let mut claimed = skip.clone();
+        let mut sweeps = vec![];
+        loop {
+            let left = out_points
+                .iter()
+                .filter(|out_point| !claimed.contains(out_point))
+                .count();
+            if left == 0 {
+                break;
+            }
+            let recipient = match self.peek_recipient() {
+                Ok(recipient) => recipient,
+                Err(e) => {
+                    error!("no recipient for {} anchors: {}", left, e);
+                    break;
+                }
+            };
+            let (inputs, details, batch_out_points) = match anchor_batch::plan_anchor_batches(
+                anchors.clone(),
+                &claimed,
+                &ScriptBuf::new_p2pkh(&PubkeyHash::all_zeros()),
+                &recipient,
+                self.max_batch_weight,
+                self.sweep_policy.version,
+            )
+            .and_then(|batches| {
+                batches
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| anyhow!("no anchor batch planned"))
+            })
+            .and_then(|details| {
+                let inputs = details
+                    .iter()
+                    .map(anchor_batch::anchor_satisfaction)
+                    .collect::<Result<Vec<_>>>()?;
+                let batch_out_points = details
+                    .iter()
+                    .map(anchor_batch::anchor_out_point)
+                    .collect::<Result<Vec<_>>>()?;
+                Ok((inputs, details, batch_out_points))
+            }) {
+                Ok(planned) => planned,
+                Err(e) => {
+                    error!("planning a batch of {} anchors failed: {}", left, e);
+                    self.destination.release(&recipient);
+                    break;
+                }
+            };
+            let swept = details
+                .iter()
+                .map(|detail| Amount::from_sat(detail.out_value))
+                .sum();
+            // every sweep needs a fee input of its own
+            let floor = sweep_fee_floor(&inputs, swept, &recipient, fee_rate);
+            let my_utxo = match self.fee_pool.reserve(floor).await {
+                Ok(utxo) => utxo,
+                Err(e) => {
+                    error!("no fee utxo left for {} anchors: {}", left, e);
+                    self.destination.release(&recipient);
+                    break;
+                }
+            };
+            claimed.extend(batch_out_points);
+
+            let ancestors = self
+                .fee_pool
+                .unconfirmed_ancestors(&my_utxo.out_point)
+                .await;
+            let signed = match anchor::build_anchor_sweep_tx(
+                &my_utxo,
+                ancestors,
+                details,
+                recipient.clone(),
+                fee_rate,
+                &self.sweep_policy,
+            ) {
+                Ok((tx, prevouts)) => {
+                    signer::spawn_sign_tx(self.signer.clone(), tx, prevouts.clone(), vec![0])
+                        .await
+                        .map(|signed_tx| (signed_tx, prevouts))
+                }
+                Err(e) => Err(e),
+            };
+            match signed {
+                Ok((signed_tx, prevouts)) => {
+                    debug!("{}", serialize_hex(&signed_tx));
+                    sweeps.push((signed_tx, prevouts));
+                }
+                Err(e) => {
+                    error!("build and sign tx fail : {}", e);
+                    self.fee_pool.release(&my_utxo.out_point).await;
+                    self.destination.release(&recipient);
+                }
+            };
+        }
+        Ok(sweeps)
+    }
+}
+
+/// the least value of the fee input of a sweep of `inputs` worth `swept` to `recipient`
+fn sweep_fee_floor(
+    inputs: &[Satisfaction],
+    swept: Amount,
+    recipient: &Script,
+    fee_rate: FeeRate,
+) -> Amount {
+    let outputs = [TxOut {
+        value: Amount::ZERO,
+        script_pubkey: recipient.to_owned(),
+    }];
+    coin_select::sweep_fee_floor(inputs, swept, &outputs, fee_rate)
+}
+
+/// script verification of every tx against its prevouts
+fn verify_package(package: &[(Transaction, Vec<TxOut>)]) -> Result<Vec<Transaction>> {
+    if package.is_empty() {
+        return Err(anyhow!("package is empty"));
+    }
+    for (tx, prevouts) in package {
+        verify::verify_tx(tx, prevouts)?;
+    }
+    Ok(package.iter().map(|(tx, _)| tx.clone()).collect())
+}
+
+fn log_package(action: &str, res: &PackageResult) {
+    for tx in res.txs.iter() {
+        match &tx.error {
+            Some(e) => warn!("package {} rejected {}: {}", action, tx.txid, e),
+            None => info!(
+                "package {} accepted {}, vsize {:?}, fee {:?}",
+                action, tx.txid, tx.vsize, tx.fee
+            ),
+        }
     }
+    info!(
+        "package {} {}, feerate {:?} sat/vB, sequential {}",
+        action, res.message, res.package_feerate, res.sequential
+    );
 }
 
 #[cfg(test)]
 mod tests {
     use super::TxSender;
-    use crate::{btcrpc, config};
+    use crate::{btcrpc, config, utxo::FeeUtxoPool};
     use bitcoin::{
         consensus::encode::{deserialize_hex, serialize_hex},
         key::Secp256k1,
@@ -274,12 +963,13 @@ mod tests {
     };
     use bittx::{build_helper, lightning::check_lightning_channel_close, signer};
     use datatypes::types;
+    use std::sync::Arc;
     use tracing::info;
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_anchor_send() {
         let cfg = config::load_config("./config.toml");
-        let sender = TxSender::new(&cfg).await;
+        let sender = TxSender::new(&cfg, Arc::new(FeeUtxoPool::default())).await;
         let raw_tx = "0200000000010178fe51519ed02464f9d3c09888857b9558afb155d16fc4f72aaad6870e201d750000000000dc35df80044a010000000000002200200a4e28601b900086f4cf4fa6f247bd96c535edb8a4a894636d2d5e58008c6b354a010000000000002200208a9884a0a051ba1ed3dfcec7877a8c5437f5c81e4d775ed6886d183af620b46dcff1020000000000220020780633c65fbbeb079fe4e90f6d1745403c2f4b3c9bacc06a2c1042465d98e63f99aa03000000000022002074628c124a040fbd05c99fcca60cb433bf73ceacb11f37471a18da12067db7750400473044022050441fee1326e6e4e716805dacc108ad8cad52744f480a8d9a70db2c32e4160002204f560d068bad1df69580280d3f60be7a758ff5efd20806e7846cc016142ec02b01483045022100a91cba623b9bbc985be3e781c1cdd196d9c42db86bbb923394b3dd057327c97e02202a344d347a05c785dbb5022906520f6046f9b7264370769380627774026a8927014752210223fad034950098b0cedf25b5cdcff13540c47fb288c51650c74200bffb4fa6502103079763bb5b9d7832783e680d4f1cacd8ba95abaf8bfdb5eb9d49ba8abe5782db52aed7998a20";
         let tx = deserialize_hex::<Transaction>(&raw_tx).unwrap();
         let unlock_info = check_lightning_channel_close(&tx).unwrap();
@@ -303,6 +993,7 @@ mod tests {
                 ),
             ],
             recipient: cfg.sign.receiver.clone(),
+            feerate: 2.0,
         };
         let my_utxos = vec![];
         let res = sender.build_and_sign(anchor_info, &my_utxos).await;
@@ -322,7 +1013,8 @@ mod tests {
             .try_init();
 
         let cfg = config::load_config("./config.toml");
-        let sender = TxSender::new(&cfg).await;
+        let fee_pool = Arc::new(FeeUtxoPool::default());
+        let sender = TxSender::new(&cfg, fee_pool.clone()).await;
         let raw_tx = "0200000000010264f41669722e08cc5c8c75a7d94fd8658889e18db5903a0b86c015832a4ca2360000000000ffffffff8f2b243127e5c00ec4de5b71ec33db6e2aabad29c1828b91b60722bc2ceaf91f0000000000ffffffff01f30500000000000016001492b8c3a56fac121ddcdffbc85b02fb9ef681038a0247304402201090f8622eb31b7a6e79afcf2fc38eaf767b703779430e229dc8d3faca2e4f190220134db78f6840cab74ca4d113332a3812ffdf5b39813c983f826dbdb5e99f1b110121030c7196376bc1df61b6da6ee711868fd30e370dd273332bfb02a2287d11e2e9c5030101fdef0251690063036f7264010117746578742f68746d6c3b636861727365743d7574662d38004d08023c73637269707420646174612d733d2230783738623766363166396431643731313263616532626434326161363235633433326134306532346165646538623237323963346365656433336432383065613922207372633d222f636f6e74656e742f663830623933343636613238633565666337303366616230326265656262663465333265316263346630363361633237666564666437396164393832663263656930223e3c2f7363726970743e3c626f6479207374796c653d22646973706c61793a206e6f6e65223e3c2f626f64793e00000000000000004f505f42564d5f56321b5f02e82f076c70afcc81987b472fd4a347ec4fa4a74da99f9d0c19a1d2ffff71af54e29a1f98c654ee7d38671960a96b6cdb4f03d000e62d418df013a0933521404a9356d87f625c3ee86dd54a809c64365f871b41c1e391075cac2e9127e6b5b4292e94c1500320fc2c19e6dc6b4b0fa83fc8ffaea3c4d7653f696162e293a4f96733b043d9e08cd30a404f596955ff94b4aa3b1356a092b7ef407509639c32aa4b8294918146c62122618e09a684074f1d8680c88321a08408da02370a697046c4880ebcb4d682e9eeeb6b0b8f1963aa79ad5b67617bd3c6a24edba3aaadf2db6ad967c2eac896b3206dd47bf1949af7466c24a65f4fa2bb4fddeff99787ce92bdd3766fa54759196e59f2f00fc14989325c791a14f192f38008e8918ae888200834124cbeefa946e99d03156390c23b894e2bca62b05251deddd7dfa6ea57b0f3bef3bae1183aa0b72a96bca37ea752599f838b8ab9121feade6a0bb8d77bfbb78ff5527e4785d99f1bd99f5c687e7769160d6fada3aa401eb1346725d246eb2dd7da71e3988720a403ca08c410a08c5b2fc153f486714b0586408d899418f49a681ee3fd7212bde65427e074384ef32f1b3e53ab3bf8a09910a6f7b7146cbe7dde0fc0c9596b17a3db4a26bfca2796316f43a6c29512235163c3e5cf7b0bfdbb1e006821c08c6be8f1a0311e1bf2e3f3b997397a2f08655f37d8ef1ccd2b57dfbde917d83300000000";
         let tx = deserialize_hex::<Transaction>(&raw_tx).unwrap();
         let my_utxo = types::Utxo {
@@ -334,7 +1026,8 @@ mod tests {
             script_pubkey: tx.output[0].script_pubkey.clone(),
         };
 
-        let res1 = sender.send_unsigned_tx(tx, 1, vec![my_utxo]).await;
+        fee_pool.update(vec![my_utxo]).await;
+        let res1 = sender.send_unsigned_tx(tx, 1).await;
         assert!(res1.is_err());
         println!("{:?}", res1.unwrap());
     }
@@ -380,7 +1073,7 @@ mod tests {
         };
 
         // let cfg = config::load_config("./config.toml");
-        // let sender = TxSender::new(&cfg).await;
+        // let sender = TxSender::new(&cfg, Arc::new(FeeUtxoPool::default())).await;
         // let raw_tx = "0200000000010264f41669722e08cc5c8c75a7d94fd8658889e18db5903a0b86c015832a4ca2360000000000ffffffff8f2b243127e5c00ec4de5b71ec33db6e2aabad29c1828b91b60722bc2ceaf91f0000000000ffffffff01f30500000000000016001492b8c3a56fac121ddcdffbc85b02fb9ef681038a0247304402201090f8622eb31b7a6e79afcf2fc38eaf767b703779430e229dc8d3faca2e4f190220134db78f6840cab74ca4d113332a3812ffdf5b39813c983f826dbdb5e99f1b110121030c7196376bc1df61b6da6ee711868fd30e370dd273332bfb02a2287d11e2e9c5030101fdef0251690063036f7264010117746578742f68746d6c3b636861727365743d7574662d38004d08023c73637269707420646174612d733d2230783738623766363166396431643731313263616532626434326161363235633433326134306532346165646538623237323963346365656433336432383065613922207372633d222f636f6e74656e742f663830623933343636613238633565666337303366616230326265656262663465333265316263346630363361633237666564666437396164393832663263656930223e3c2f7363726970743e3c626f6479207374796c653d22646973706c61793a206e6f6e65223e3c2f626f64793e00000000000000004f505f42564d5f56321b5f02e82f076c70afcc81987b472fd4a347ec4fa4a74da99f9d0c19a1d2ffff71af54e29a1f98c654ee7d38671960a96b6cdb4f03d000e62d418df013a0933521404a9356d87f625c3ee86dd54a809c64365f871b41c1e391075cac2e9127e6b5b4292e94c1500320fc2c19e6dc6b4b0fa83fc8ffaea3c4d7653f696162e293a4f96733b043d9e08cd30a404f596955ff94b4aa3b1356a092b7ef407509639c32aa4b8294918146c62122618e09a684074f1d8680c88321a08408da02370a697046c4880ebcb4d682e9eeeb6b0b8f1963aa79ad5b67617bd3c6a24edba3aaadf2db6ad967c2eac896b3206dd47bf1949af7466c24a65f4fa2bb4fddeff99787ce92bdd3766fa54759196e59f2f00fc14989325c791a14f192f38008e8918ae888200834124cbeefa946e99d03156390c23b894e2bca62b05251deddd7dfa6ea57b0f3bef3bae1183aa0b72a96bca37ea752599f838b8ab9121feade6a0bb8d77bfbb78ff5527e4785d99f1bd99f5c687e7769160d6fada3aa401eb1346725d246eb2dd7da71e3988720a403ca08c410a08c5b2fc153f486714b0586408d899418f49a681ee3fd7212bde65427e074384ef32f1b3e53ab3bf8a09910a6f7b7146cbe7dde0fc0c9596b17a3db4a26bfca2796316f43a6c29512235163c3e5cf7b0bfdbb1e006821c08c6be8f1a0311e1bf2e3f3b997397a2f08655f37d8ef1ccd2b57dfbde917d83300000000";
         // let tx = deserialize_hex::<Transaction>(&raw_tx).unwrap();
         // let res1 = sender.send_unsigned_tx(tx, 1).await;
@@ -1,14 +1,16 @@
-use bittx::{build_helper, signer};
+use bitcoin::Address;
+use bittx::{build_helper, hd::Destination, signer, verify};
 use btcrpc::BtcCli;
 use datatypes::types;
+use std::str::FromStr;
 
 use super::*;
 
 #[derive(Debug)]
 pub struct UnsginSender {
     btccli: btcrpc::BtcCli,
-    receiver: String,
-    wif: String,
+    destination: Destination,
+    signer: Arc<dyn signer::Signer>,
 }
 
 impl UnsginSender {
@@ -16,12 +18,14 @@ impl UnsginSender {
         let btccli = BtcCli::new(&cfg.bitcoin.endpoint, &cfg.bitcoin.user, &cfg.bitcoin.pass);
         Self {
             btccli,
-            receiver: cfg.sign.receiver.clone(),
-            wif: cfg.sign.wif.clone(),
+            destination: cfg.sign.destination().expect("invalid sign config"),
+            signer: cfg.sign.signer().expect("invalid sign config"),
         }
     }
 
-    pub fn send(&self, tx: Transaction) -> Result<Txid> {
+    /// refuses transactions failing script verification against `prevouts`
+    pub fn send(&self, tx: Transaction, prevouts: &[TxOut]) -> Result<Txid> {
+        verify::verify_tx(&tx, prevouts)?;
         self.btccli.send_tx(&tx)
     }
 
@@ -31,34 +35,53 @@ impl UnsginSender {
         idx: u32,
         my_utxos: &[types::Utxo],
     ) -> Result<()> {
+        if my_utxos.is_empty() {
+            return Err(anyhow!("not found unspent utxo"));
+        }
+
         let input = tx.input.get(idx as usize).unwrap();
         let prev_out = self
             .btccli
             .get_tx_out(&input.previous_output.txid, input.previous_output.vout)?;
+        let feerate = self.btccli.estimate_fee_rate(FEE_CONF_TARGET)?;
+        let recipient = self.destination.peek_address()?;
+        let recipient_script = Address::from_str(&recipient)?
+            .assume_checked()
+            .script_pubkey();
         let info = types::UnsignedInfo {
-            recipient: self.receiver.clone(),
+            recipient,
             tx: tx.clone(),
             input_idx: idx,
             input_out: prev_out,
+            feerate,
         };
 
-        if my_utxos.is_empty() {
-            return Err(anyhow!("not found unspent utxo"));
+        // the receive index is only used up once the tx is out
+        let res = self.build_sign_and_send(info, my_utxos);
+        match res {
+            Ok(_) => self.destination.commit(&recipient_script)?,
+            Err(_) => self.destination.release(&recipient_script),
         }
+        res
+    }
 
-        let my_utxo = my_utxos.first().unwrap();
+    fn build_sign_and_send(
+        &self,
+        info: types::UnsignedInfo,
+        my_utxos: &[types::Utxo],
+    ) -> Result<()> {
         info!("start build unsign_tx...");
         // match build_helper::build_unsigned_tx(info).await {
-        match build_helper::build_unsigned_tx_with_receive_utxo(info, my_utxo.clone()) {
+        match build_helper::build_unsigned_tx_with_receive_utxo(info, my_utxos) {
             Ok((unsigned_tx, prevouts)) => {
-                match signer::sign_tx(self.wif.clone(), unsigned_tx, prevouts, vec![0]) {
+                match self.signer.sign_tx(unsigned_tx, prevouts.clone(), vec![0]) {
                     Ok(signed_tx) => {
                         info!(
                             "build and signed the unsign_tx, id: {} hex : {}",
                             signed_tx.compute_txid(),
                             serialize_hex(&signed_tx)
                         );
-                        self.send(signed_tx.clone())?;
+                        self.send(signed_tx.clone(), &prevouts)?;
                     }
                     Err(err) => {
                         error!("failed to sign the unsign_tx: {:?}", err);
@@ -100,7 +100,8 @@ impl Syncer {
                         {
                             let effect_rows = self
                                 .dao
-                                .update_anchor_tx_confirmed_height(-1, out.tx_id.clone()).await;
+                                .update_anchor_tx_confirmed_height(-1, out.tx_id.clone())
+                                .await;
                             info!("update_anchor_tx_confirmed_height : {:?}", effect_rows);
                         }
                     }
@@ -128,6 +129,66 @@ impl Syncer {
             }
         }
     }
+
+    pub async fn sync_p2a(&self) {
+        let tx_outs = match self.dao.get_unspent_p2a_tx_out().await {
+            Ok(tx_outs) => tx_outs,
+            Err(e) => {
+                error!("get_unspent_p2a_tx_out failed : {}", e);
+                return;
+            }
+        };
+        for out in tx_outs.iter() {
+            let txid = Txid::from_str(out.tx_id.as_str()).unwrap();
+            let mut confirmed = out.confirmed_block_height > 0;
+            if !confirmed {
+                if let Ok((raw_tx, _)) = self.btccli.get_raw_transaction_info(&txid) {
+                    if let Some(blockhash) = raw_tx.blockhash {
+                        if let Ok(block) = self.btccli.get_block_by_hash(blockhash) {
+                            match self
+                                .dao
+                                .update_p2a_tx_out_confirmed_height(
+                                    block.bip34_block_height().unwrap() as i64,
+                                    raw_tx.txid.to_string(),
+                                )
+                                .await
+                            {
+                                Ok(_) => confirmed = true,
+                                Err(e) => {
+                                    error!("update_p2a_tx_out_confirmed_height failed : {}", e)
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            // a mempool spend may still be replaced or evicted, and an unconfirmed
+            // parent is missing from the utxo set as well
+            if !confirmed {
+                continue;
+            }
+            match self
+                .btccli
+                .get_tx_out_spent_confirmed(&txid, out.vout as u32)
+            {
+                Ok(true) => {
+                    match self
+                        .dao
+                        .update_p2a_tx_out_spent(out.tx_id.clone(), out.vout)
+                        .await
+                    {
+                        Ok(_) => {}
+                        Err(e) => error!("update_p2a_tx_out_spent failed : {}", e),
+                    }
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    error!("get tx out {} vout {} failed : {}", txid, out.vout, e);
+                }
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1,29 +1,416 @@
 use super::*;
+use bittx::{
+    coin_select,
+    hd::{AddressScan, Destination},
+};
 use datatypes::types;
-use tokio::sync::RwLock;
+use sender::SWEEP_VOUT;
+use std::collections::{HashMap, HashSet};
+use tokio::sync::Mutex;
 
 pub struct UtxoUpdater {
-    address: String,
-    share_data: Arc<RwLock<Vec<types::Utxo>>>,
+    btccli: btcrpc::BtcCli,
+    destination: Destination,
+    fee_pool: Arc<FeeUtxoPool>,
+    scan: Mutex<AddressScan>,
 }
 
 impl UtxoUpdater {
-    pub fn new(cfg: &config::Config, data: Arc<RwLock<Vec<types::Utxo>>>) -> Self {
+    pub fn new(cfg: &config::Config, fee_pool: Arc<FeeUtxoPool>) -> Self {
         Self {
-            address: cfg.sign.receiver.clone(),
-            share_data: data,
+            btccli: btcrpc::BtcCli::new(
+                &cfg.bitcoin.endpoint,
+                &cfg.bitcoin.user,
+                &cfg.bitcoin.pass,
+            ),
+            destination: cfg.sign.destination().expect("invalid sign config"),
+            fee_pool,
+            scan: Mutex::new(AddressScan::default()),
         }
     }
 
     pub async fn update_utxo(&self) -> Result<()> {
-        let utxos = mempool::utxo::gets_uspent_utxo(&self.address).await?;
+        let watched = self.destination.watched_addresses()?;
+        let mut scan = self.scan.lock().await;
+        let mut utxos = vec![];
+        for address in scan.due(&watched) {
+            // an address without utxos is reported as an error
+            let found = match mempool::utxo::gets_uspent_utxo(&address).await {
+                Ok(found) => found,
+                Err(e) => {
+                    debug!("no utxo for {}: {}", address, e);
+                    vec![]
+                }
+            };
+            scan.record(&address, !found.is_empty());
+            utxos.extend(found);
+        }
+        scan.finish(&watched);
+        // our broadcasts that confirmed or the node dropped, conflicted or evicted
+        self.fee_pool
+            .expire(
+                |txid| self.btccli.in_mempool(txid),
+                |out_point| {
+                    self.btccli
+                        .get_tx_out_spent(&out_point.txid, out_point.vout)
+                        .map(|spent| !spent)
+                },
+            )
+            .await;
         if utxos.is_empty() {
             return Ok(());
         }
 
         info!("update utxo: {:?}", utxos);
-        let mut share_data = self.share_data.write().await;
-        *share_data = utxos;
+        self.fee_pool.update(utxos).await;
+        Ok(())
+    }
+}
+
+/// the wallet utxos sweeps pay their fee from. a utxo is reserved while its sweep is
+/// built, released when the sweep fails and spent once it is broadcast, so two sweeps
+/// never pick the same fee input however stale the last snapshot is
+#[derive(Debug, Default)]
+pub struct FeeUtxoPool {
+    state: Mutex<PoolState>,
+}
+
+#[derive(Debug, Default)]
+struct PoolState {
+    /// the last wallet snapshot of `UtxoUpdater`
+    snapshot: Vec<types::Utxo>,
+    /// outputs of our broadcasts the snapshot has not seen yet
+    unconfirmed: Vec<types::Utxo>,
+    reserved: HashSet<OutPoint>,
+    /// spent by our broadcasts, the snapshot may still list them
+    spent: HashMap<OutPoint, Txid>,
+}
+
+impl PoolState {
+    fn available(&self, confirmed: bool) -> Vec<types::Utxo> {
+        let utxos = if confirmed {
+            &self.snapshot
+        } else {
+            &self.unconfirmed
+        };
+        utxos
+            .iter()
+            .filter(|utxo| {
+                !self.reserved.contains(&utxo.out_point)
+                    && !self.spent.contains_key(&utxo.out_point)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+impl FeeUtxoPool {
+    /// take a new wallet snapshot, our spends and outputs it reflects are forgotten
+    pub async fn update(&self, utxos: Vec<types::Utxo>) {
+        let mut state = self.state.lock().await;
+        let listed: HashSet<OutPoint> = utxos.iter().map(|utxo| utxo.out_point).collect();
+        state
+            .spent
+            .retain(|out_point, _| listed.contains(out_point));
+        state
+            .unconfirmed
+            .retain(|utxo| !listed.contains(&utxo.out_point));
+        state.snapshot = utxos;
+    }
+
+    /// spendable utxos, reserved and spent ones left out
+    pub async fn available(&self) -> Vec<types::Utxo> {
+        let state = self.state.lock().await;
+        let mut utxos = state.available(true);
+        utxos.extend(state.available(false));
+        utxos
+    }
+
+    /// forget broadcasts of ours that left the mempool. `in_mempool` asks the node for
+    /// the tx itself, so an output our next sweep spends keeps its parent counted; those
+    /// it no longer has confirmed or were dropped. `unspent` asks whether an out point is
+    /// unspent in the chain and mempool, inputs it reports unspent were given back.
+    /// lookup errors keep the entry
+    pub async fn expire(
+        &self,
+        in_mempool: impl Fn(&Txid) -> Result<bool>,
+        unspent: impl Fn(&OutPoint) -> Result<bool>,
+    ) {
+        let mut state = self.state.lock().await;
+        let txids: HashSet<Txid> = state
+            .unconfirmed
+            .iter()
+            .map(|utxo| utxo.out_point.txid)
+            .collect();
+        let gone: HashSet<Txid> = txids
+            .into_iter()
+            .filter(|txid| matches!(in_mempool(txid), Ok(false)))
+            .collect();
+        let returned: Vec<OutPoint> = state
+            .spent
+            .keys()
+            .filter(|out_point| matches!(unspent(out_point), Ok(true)))
+            .copied()
+            .collect();
+        for out_point in returned {
+            if let Some(txid) = state.spent.remove(&out_point) {
+                info!(
+                    "fee utxo {} is unspent again, {} was dropped",
+                    out_point, txid
+                );
+            }
+        }
+        state
+            .unconfirmed
+            .retain(|utxo| !gone.contains(&utxo.out_point.txid));
+    }
+
+    /// confirmed utxos neither reserved nor spent, the only inputs a BIP125
+    /// replacement may add
+    pub async fn confirmed(&self) -> Vec<types::Utxo> {
+        self.state.lock().await.available(true)
+    }
+
+    /// reserve the inputs a replacement or cpfp child picked from `confirmed`,
+    /// fails when another sweep took one of them meanwhile
+    pub async fn claim(&self, out_points: &[OutPoint]) -> Result<()> {
+        let mut state = self.state.lock().await;
+        let confirmed: HashSet<OutPoint> = state
+            .available(true)
+            .iter()
+            .map(|utxo| utxo.out_point)
+            .collect();
+        if let Some(taken) = out_points.iter().find(|op| !confirmed.contains(op)) {
+            return Err(anyhow!("fee utxo {} is no longer available", taken));
+        }
+        state.reserved.extend(out_points.iter().copied());
         Ok(())
     }
+
+    /// hold a fee input of at least `min_value` for a sweep, confirmed utxos first and
+    /// the outputs of our unconfirmed sweeps after them
+    pub async fn reserve(&self, min_value: Amount) -> Result<types::Utxo> {
+        let mut state = self.state.lock().await;
+        let utxo = coin_select::select_fee_input(&state.available(true), min_value)
+            .or_else(|_| coin_select::select_fee_input(&state.available(false), min_value))?;
+        state.reserved.insert(utxo.out_point);
+        debug!("reserved fee utxo {}", utxo.out_point);
+        Ok(utxo)
+    }
+
+    /// our broadcasts not seen confirmed yet
+    pub async fn unconfirmed_txids(&self) -> HashSet<Txid> {
+        let state = self.state.lock().await;
+        state
+            .unconfirmed
+            .iter()
+            .map(|utxo| utxo.out_point.txid)
+            .collect()
+    }
+
+    /// our mempool transactions `out_point` descends from, what BIP 431 limits
+    pub async fn unconfirmed_ancestors(&self, out_point: &OutPoint) -> usize {
+        let unconfirmed = self.unconfirmed_txids().await;
+        let state = self.state.lock().await;
+        let mut ancestors = HashSet::new();
+        let mut todo = vec![out_point.txid];
+        while let Some(txid) = todo.pop() {
+            if !unconfirmed.contains(&txid) || !ancestors.insert(txid) {
+                continue;
+            }
+            // the inputs of `txid` that are outputs of our other broadcasts
+            todo.extend(
+                state
+                    .spent
+                    .iter()
+                    .filter(|(_, spender)| **spender == txid)
+                    .map(|(input, _)| input.txid),
+            );
+        }
+        ancestors.len()
+    }
+
+    /// give back a reservation whose sweep failed
+    pub async fn release(&self, out_point: &OutPoint) {
+        if self.state.lock().await.reserved.remove(out_point) {
+            debug!("released fee utxo {}", out_point);
+        }
+    }
+
+    /// `tx` went out, its inputs are spent and its `SWEEP_VOUT` output can fund the next sweep
+    pub async fn mark_broadcast(&self, tx: &Transaction) {
+        let mut state = self.state.lock().await;
+        let txid = tx.compute_txid();
+        for input in tx.input.iter() {
+            state.reserved.remove(&input.previous_output);
+            // a replacement takes the outputs of the tx it replaces with it
+            if let Some(replaced) = state.spent.insert(input.previous_output, txid) {
+                if replaced != txid {
+                    state
+                        .unconfirmed
+                        .retain(|utxo| utxo.out_point.txid != replaced);
+                }
+            }
+        }
+        if let Some(out) = tx.output.get(SWEEP_VOUT) {
+            state.unconfirmed.push(types::Utxo {
+                out_point: OutPoint {
+                    txid,
+                    vout: SWEEP_VOUT as u32,
+                },
+                value: out.value,
+                script_pubkey: out.script_pubkey.clone(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::Sequence;
+    use datatypes::fixtures;
+
+    fn p2wpkh() -> ScriptBuf {
+        ScriptBuf::from_hex("00140000000000000000000000000000000000000000").unwrap()
+    }
+
+    fn sweep(fee_input: OutPoint, value: u64) -> Transaction {
+        fixtures::spend(
+            fee_input,
+            Sequence::ENABLE_RBF_NO_LOCKTIME,
+            vec![TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: p2wpkh(),
+            }],
+        )
+    }
+
+    fn out_points(utxos: &[types::Utxo]) -> Vec<OutPoint> {
+        utxos.iter().map(|utxo| utxo.out_point).collect()
+    }
+
+    #[tokio::test]
+    async fn reserves_fee_inputs_and_chains_sweeps() {
+        let pool = FeeUtxoPool::default();
+        pool.update(vec![
+            fixtures::utxo(&p2wpkh(), 0, 10_000),
+            fixtures::utxo(&p2wpkh(), 1, 20_000),
+        ])
+        .await;
+
+        // quick detections get different fee inputs
+        let first = pool.reserve(Amount::ZERO).await.unwrap();
+        let second = pool.reserve(Amount::ZERO).await.unwrap();
+        assert_ne!(first.out_point, second.out_point);
+        assert!(pool.reserve(Amount::ZERO).await.is_err());
+
+        // a failed sweep gives its input back
+        pool.release(&second.out_point).await;
+        assert_eq!(out_points(&pool.available().await), vec![second.out_point]);
+
+        // a broadcast sweep spends its input and its output funds the next one
+        let tx = sweep(first.out_point, 9_000);
+        pool.mark_broadcast(&tx).await;
+        // replacements only get the confirmed one
+        assert_eq!(out_points(&pool.confirmed().await), vec![second.out_point]);
+        assert!(pool.claim(&[first.out_point]).await.is_err());
+        pool.claim(&[second.out_point]).await.unwrap();
+        assert!(pool.confirmed().await.is_empty());
+        pool.release(&second.out_point).await;
+        let confirmed = pool.reserve(Amount::from_sat(15_000)).await.unwrap();
+        assert_eq!(confirmed.out_point, second.out_point);
+        let chained = pool.reserve(Amount::from_sat(5_000)).await.unwrap();
+        assert_eq!(chained.out_point.txid, tx.compute_txid());
+        pool.release(&confirmed.out_point).await;
+        pool.release(&chained.out_point).await;
+
+        // the stale snapshot still lists the spent input
+        pool.update(vec![
+            fixtures::utxo(&p2wpkh(), 0, 10_000),
+            fixtures::utxo(&p2wpkh(), 1, 20_000),
+        ])
+        .await;
+        let available = out_points(&pool.available().await);
+        assert_eq!(available, vec![second.out_point, chained.out_point]);
+
+        // a replacement drops the output of the sweep it replaces
+        let replacement = sweep(first.out_point, 8_000);
+        pool.mark_broadcast(&replacement).await;
+        let available = out_points(&pool.available().await);
+        assert_eq!(available.len(), 2);
+        assert_eq!(available[1].txid, replacement.compute_txid());
+
+        // the replacement got evicted: its output is gone and its input comes back,
+        // lookups failing leave the pool as it is
+        pool.expire(
+            |_| Err(anyhow!("node unreachable")),
+            |_| Err(anyhow!("node unreachable")),
+        )
+        .await;
+        assert_eq!(pool.available().await.len(), 2);
+        let evicted = replacement.compute_txid();
+        pool.expire(
+            |txid| Ok(*txid != evicted),
+            |out_point| Ok(out_point.txid != evicted),
+        )
+        .await;
+        let available = out_points(&pool.available().await);
+        assert_eq!(available, vec![first.out_point, second.out_point]);
+    }
+
+    #[tokio::test]
+    async fn counts_unconfirmed_ancestors_of_chained_sweeps() {
+        let pool = FeeUtxoPool::default();
+        pool.update(vec![fixtures::utxo(&p2wpkh(), 0, 10_000)])
+            .await;
+        let confirmed = fixtures::utxo(&p2wpkh(), 0, 10_000).out_point;
+        assert_eq!(pool.unconfirmed_ancestors(&confirmed).await, 0);
+
+        let parent = sweep(confirmed, 9_000);
+        pool.mark_broadcast(&parent).await;
+        let child_input = pool.reserve(Amount::ZERO).await.unwrap().out_point;
+        assert_eq!(pool.unconfirmed_ancestors(&child_input).await, 1);
+
+        let child = sweep(child_input, 8_000);
+        pool.mark_broadcast(&child).await;
+        let grandchild_input = pool.reserve(Amount::ZERO).await.unwrap().out_point;
+        assert_eq!(grandchild_input.txid, child.compute_txid());
+        assert_eq!(pool.unconfirmed_ancestors(&grandchild_input).await, 2);
+    }
+
+    #[tokio::test]
+    async fn expiry_keeps_parents_our_next_sweep_spends() {
+        let pool = FeeUtxoPool::default();
+        let confirmed = fixtures::utxo(&p2wpkh(), 0, 10_000);
+        pool.update(vec![confirmed.clone()]).await;
+        let parent = sweep(confirmed.out_point, 9_000);
+        pool.mark_broadcast(&parent).await;
+        let child_input = pool.reserve(Amount::ZERO).await.unwrap().out_point;
+        let child = sweep(child_input, 8_000);
+        pool.mark_broadcast(&child).await;
+
+        // both still in the mempool, the parent output is spent by the child
+        let spent = [confirmed.out_point, child_input];
+        let child_output = OutPoint {
+            txid: child.compute_txid(),
+            vout: SWEEP_VOUT as u32,
+        };
+        pool.expire(|_| Ok(true), |out_point| Ok(!spent.contains(out_point)))
+            .await;
+        assert_eq!(pool.unconfirmed_ancestors(&child_output).await, 2);
+        assert!(pool
+            .unconfirmed_txids()
+            .await
+            .contains(&parent.compute_txid()));
+
+        // the parent confirms, only the child is left unconfirmed
+        let parent_txid = parent.compute_txid();
+        pool.expire(
+            |txid| Ok(*txid != parent_txid),
+            |out_point| Ok(!spent.contains(out_point)),
+        )
+        .await;
+        assert_eq!(pool.unconfirmed_ancestors(&child_output).await, 1);
+    }
 }